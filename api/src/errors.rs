@@ -6,6 +6,7 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use serde::Serialize;
+use tracing::error;
 use utoipa::ToSchema;
 
 use crate::models::APIJson;
@@ -14,6 +15,8 @@ pub(crate) enum APIError {
     NotFound,
     ValidationFailed { message: Option<&'static str> },
     InputRejection { message: String },
+    ServiceUnavailable,
+    InternalError,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -65,6 +68,47 @@ impl IntoResponse for APIError {
                 }),
             )
                 .into_response(),
+
+            Self::ServiceUnavailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                APIJson(APIErrorResponse {
+                    error: APIErrorDetail {
+                        code: "SERVICE_UNAVAILABLE",
+                        message: Cow::Borrowed("The service is busy, please try again later"),
+                    },
+                }),
+            )
+                .into_response(),
+
+            Self::InternalError => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                APIJson(APIErrorResponse {
+                    error: APIErrorDetail {
+                        code: "INTERNAL_ERROR",
+                        message: Cow::Borrowed("An unexpected error occurred"),
+                    },
+                }),
+            )
+                .into_response(),
+        }
+    }
+}
+
+impl From<radiojournal::Error> for APIError {
+    fn from(error: radiojournal::Error) -> Self {
+        match error {
+            radiojournal::Error::NotFound => Self::NotFound,
+            radiojournal::Error::InvalidInput(message) => Self::ValidationFailed {
+                message: Some(message),
+            },
+            radiojournal::Error::Throttled(_) => {
+                error!(error = ?error, "Storage backend throttled request");
+                Self::ServiceUnavailable
+            }
+            error => {
+                error!(error = ?error, "Unexpected error from radiojournal");
+                Self::InternalError
+            }
         }
     }
 }
@@ -41,6 +41,16 @@ async fn main() -> Result<(), Error> {
             .init();
     }
 
+    // tokens handed out have to stay valid across instances and restarts
+    if !init::use_local_backend() {
+        assert!(
+            !std::env::var("CURSOR_SECRET")
+                .unwrap_or_default()
+                .is_empty(),
+            "env CURSOR_SECRET to be set"
+        );
+    }
+
     let context = init::initialize()
         .await
         .expect("initialize radiojournal app");
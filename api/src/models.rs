@@ -6,13 +6,14 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 use utoipa::ToSchema;
 
 use crate::errors::APIError;
 use radiojournal::crud::play::models::{PlayId, PlayInDB};
-use radiojournal::crud::station::models::{StationId, StationInDB};
+use radiojournal::crud::station::models::{Band, StationId, StationInDB};
 use radiojournal::crud::track::models::{TrackId, TrackInDB, TrackMinimalInDB, TrackPlayInDB};
 use radiojournal::helpers::truncate_datetime_to_minutes;
 
@@ -52,6 +53,17 @@ pub(crate) struct Station {
     id: StationId,
     name: String,
     location: Option<String>,
+    #[schema(value_type = Option<String>, example = "Asia/Bangkok")]
+    timezone: Option<Tz>,
+    band: Option<Band>,
+    frequency: Option<String>,
+    country: Option<String>,
+    language: Option<String>,
+    website_url: Option<String>,
+    stream_url: Option<String>,
+    logo_url: Option<String>,
+    genres: Vec<String>,
+    active: bool,
     track_count: usize,
     play_count: usize,
 }
@@ -62,6 +74,16 @@ impl From<StationInDB> for Station {
             id: station.id,
             name: station.name,
             location: station.location,
+            timezone: station.timezone,
+            band: station.profile.band,
+            frequency: station.profile.frequency,
+            country: station.profile.country,
+            language: station.profile.language,
+            website_url: station.profile.website_url,
+            stream_url: station.profile.stream_url,
+            logo_url: station.profile.logo_url,
+            genres: station.profile.genres,
+            active: station.profile.active,
             track_count: station.track_count,
             play_count: station.play_count,
         }
@@ -74,6 +96,11 @@ pub(crate) struct Track {
     title: String,
     artist: String,
     is_song: bool,
+    album: Option<String>,
+    duration_ms: Option<u64>,
+    isrc: Option<String>,
+    artwork_url: Option<String>,
+    provider_track_id: Option<String>,
     play_count: usize,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
@@ -86,6 +113,11 @@ impl From<TrackInDB> for Track {
             title: track.title,
             artist: track.artist,
             is_song: track.is_song,
+            album: track.details.album,
+            duration_ms: track.details.duration_ms,
+            isrc: track.details.isrc,
+            artwork_url: track.details.artwork_url,
+            provider_track_id: track.details.provider_track_id,
             play_count: track.play_count,
             created_at: truncate_datetime_to_minutes(track.created_ts)
                 .expect("truncate to minutes on utc datetime"),
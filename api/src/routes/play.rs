@@ -4,21 +4,29 @@ use std::{
 };
 
 use axum::extract::State;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
 use serde::Deserialize;
-use ulid::Ulid;
 
 use crate::AppState;
 use crate::errors::{APIError, APIErrorResponse};
 use crate::extractors::{Path, Query};
 use crate::models::{APIJson, ListPlaysResponse, NextToken, Play, TrackMinimal};
+use radiojournal::crud::shared::models::SortOrder;
 use radiojournal::crud::station::models::StationId;
 use radiojournal::crud::track::models::TrackId;
+use radiojournal::helpers::local_day_range;
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct ListPlaysQuery {
-    start: DateTime<Utc>,
-    end: DateTime<Utc>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    /// Broadcast day to list, instead of `start` and `end`.
+    date: Option<NaiveDate>,
+    /// Timezone of `date`, the station timezone if not set.
+    tz: Option<Tz>,
+    #[serde(default)]
+    order: SortOrder,
     next_token: Option<NextToken>,
 }
 
@@ -27,8 +35,14 @@ pub(crate) struct ListPlaysQuery {
     path = "/station/{station_id}/plays",
     params(
         ("station_id" = StationId, Path, deprecated = false),
-        ("start" = DateTime<Utc>, Query, deprecated = false),
-        ("end" = DateTime<Utc>, Query, deprecated = false),
+        ("start" = Option<DateTime<Utc>>, Query, deprecated = false),
+        ("end" = Option<DateTime<Utc>>, Query, deprecated = false),
+        ("date" = Option<NaiveDate>, Query, deprecated = false,
+            description = "Day to list plays of, instead of `start` and `end`"),
+        ("tz" = Option<String>, Query, deprecated = false,
+            description = "IANA timezone of `date`, defaults to the station timezone"),
+        ("order" = Option<SortOrder>, Query, deprecated = false,
+            description = "`desc` lists the newest plays first"),
         ("next_token" = Option<String>, Query, deprecated = false),
     ),
     responses(
@@ -42,42 +56,68 @@ pub(crate) async fn list_plays(
     Query(query): Query<ListPlaysQuery>,
     State(state): State<Arc<AppState>>,
 ) -> Result<APIJson<ListPlaysResponse>, APIError> {
-    let next_key = if let Some(next_token) = query.next_token {
-        Some(
-            Ulid::from_string(&next_token).or(Err(APIError::ValidationFailed {
-                message: Some("Invalid next_token"),
-            }))?,
-        )
-    } else {
-        None
+    let (start, end) = match (query.date, query.start, query.end) {
+        (Some(date), None, None) => {
+            let day_range = if let Some(tz) = query.tz {
+                local_day_range(date, tz)
+            } else {
+                state
+                    .crud_station
+                    .get_station(station_id)
+                    .await?
+                    .ok_or(APIError::NotFound)?
+                    .get_day_range(date)
+            };
+
+            day_range.ok_or(APIError::ValidationFailed {
+                message: Some("`date` is out of range"),
+            })?
+        }
+        (None, Some(start), Some(end)) if query.tz.is_none() => (start, end),
+        (None, Some(_), Some(_)) => {
+            return Err(APIError::ValidationFailed {
+                message: Some("`tz` can only be used with `date`"),
+            });
+        }
+        _ => {
+            return Err(APIError::ValidationFailed {
+                message: Some("either `date` or both `start` and `end` are required"),
+            });
+        }
     };
 
-    if query.end <= query.start {
+    if end <= start {
         return Err(APIError::ValidationFailed {
             message: Some("`end` must be later than `start`"),
         });
     }
 
-    let (plays_internal, next_key) = state
+    let (plays_internal, next_token) = state
         .crud_play
-        .list_plays(station_id, 50, query.start, query.end, next_key)
-        .await
-        .unwrap();
+        .list_plays(
+            station_id,
+            50,
+            start,
+            end,
+            query.order,
+            query.next_token.as_deref(),
+        )
+        .await?;
 
     let track_ids: HashSet<TrackId> = plays_internal.iter().map(|play| play.track_id).collect();
     if track_ids.is_empty() {
         // FIXME actually return 404 if station id in pk not found
+        // a page can be empty after a long quiet stretch while later days still have plays
         return Ok(APIJson(ListPlaysResponse {
             plays: vec![],
-            next_token: None,
+            next_token: next_token.map(NextToken::from),
         }));
     }
 
     let tracks: HashMap<TrackId, TrackMinimal> = state
         .crud_track
         .batch_get_tracks_minimal(station_id, track_ids.iter())
-        .await
-        .unwrap()
+        .await?
         .into_iter()
         .map(|track_internal| (track_internal.id.into(), TrackMinimal::from(track_internal)))
         .collect();
@@ -94,6 +134,6 @@ pub(crate) async fn list_plays(
                 Play::new(play_internal, track)
             })
             .collect(),
-        next_token: next_key.map(|val| val.to_string().into()),
+        next_token: next_token.map(NextToken::from),
     }))
 }
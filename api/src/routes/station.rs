@@ -1,25 +1,62 @@
 use std::sync::Arc;
 
 use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue};
 use radiojournal::crud::station::models::StationId;
+use serde::Deserialize;
 
 use crate::AppState;
 use crate::errors::{APIError, APIErrorResponse};
-use crate::extractors::Path;
-use crate::models::{APIJson, Station};
+use crate::extractors::{Path, Query};
+use crate::models::{APIJson, NextToken, Station};
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ListStationsQuery {
+    next_token: Option<NextToken>,
+}
+
+/// Header carrying the `next_token` of station listings, as the body is a bare array.
+const NEXT_TOKEN_HEADER: &str = "x-next-token";
 
 #[utoipa::path(
     get,
     path = "/stations",
+    params(
+        ("next_token" = Option<String>, Query, deprecated = false),
+    ),
     responses(
-        (status = 200, description = "Stations listed successfully", body = Vec<Station>),
+        (
+            status = 200,
+            description = "Stations listed successfully",
+            body = Vec<Station>,
+            headers(
+                ("x-next-token" = String, description = "Token of the next page, unset on the last page"),
+            ),
+        ),
     ),
     tag = "station"
 )]
-pub(crate) async fn list_stations(State(state): State<Arc<AppState>>) -> APIJson<Vec<Station>> {
-    let internal_stations = state.crud_station.list_stations(50).await.unwrap();
+pub(crate) async fn list_stations(
+    Query(query): Query<ListStationsQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<(HeaderMap, APIJson<Vec<Station>>), APIError> {
+    let (internal_stations, next_token) = state
+        .crud_station
+        .list_stations(50, query.next_token.as_deref())
+        .await?;
+
+    let mut headers = HeaderMap::new();
+    if let Some(next_token) = next_token {
+        headers.insert(
+            NEXT_TOKEN_HEADER,
+            HeaderValue::from_str(&next_token).expect("next_token is a valid header value"),
+        );
+    }
 
-    APIJson(internal_stations.into_iter().map(Station::from).collect())
+    Ok((
+        headers,
+        APIJson(internal_stations.into_iter().map(Station::from).collect()),
+    ))
 }
 
 #[utoipa::path(
@@ -38,7 +75,7 @@ pub(crate) async fn get_station(
     Path(station_id): Path<StationId>,
     State(state): State<Arc<AppState>>,
 ) -> Result<APIJson<Station>, APIError> {
-    let maybe_station_internal = state.crud_station.get_station(station_id).await.unwrap();
+    let maybe_station_internal = state.crud_station.get_station(station_id).await?;
 
     if let Some(station) = maybe_station_internal.map(Station::from) {
         Ok(APIJson(station))
@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use axum::extract::State;
+use axum::response::{IntoResponse, Redirect, Response};
 use serde::Deserialize;
-use ulid::Ulid;
 
 use crate::AppState;
 use crate::errors::{APIError, APIErrorResponse};
@@ -22,6 +22,7 @@ use radiojournal::crud::track::models::TrackId;
     ),
     responses(
         (status = 200, description = "Track returned successfully", body = Track),
+        (status = 308, description = "Track was merged into another track, redirecting to it"),
         (status = 404, description = "Station or track not found", body = APIErrorResponse),
     ),
     tag = "track"
@@ -29,15 +30,18 @@ use radiojournal::crud::track::models::TrackId;
 pub(crate) async fn get_track(
     Path((station_id, track_id)): Path<(StationId, TrackId)>,
     State(state): State<Arc<AppState>>,
-) -> Result<APIJson<Track>, APIError> {
-    let maybe_track_internal = state
-        .crud_track
-        .get_track(station_id, track_id)
-        .await
-        .unwrap();
+) -> Result<Response, APIError> {
+    let maybe_track_internal = state.crud_track.get_track(station_id, track_id).await?;
 
     if let Some(track) = maybe_track_internal.map(Track::from) {
-        Ok(APIJson(track))
+        Ok(APIJson(track).into_response())
+    } else if let Some(merged_track_id) = state
+        .crud_track
+        .get_track_redirect(station_id, track_id)
+        .await?
+    {
+        // relative to /station/{station_id}/track/{track_id}
+        Ok(Redirect::permanent(&merged_track_id.to_string()).into_response())
     } else {
         Err(APIError::NotFound)
     }
@@ -58,6 +62,7 @@ pub(crate) struct ListTrackPlaysQuery {
     ),
     responses(
         (status = 200, description = "Plays of track returned successfully", body = ListTrackPlaysResponse),
+        (status = 308, description = "Track was merged into another track, redirecting to its plays"),
         (status = 404, description = "Station or track not found", body = APIErrorResponse),
     ),
     tag = "track"
@@ -66,30 +71,33 @@ pub(crate) async fn list_plays_of_track(
     Path((station_id, track_id)): Path<(StationId, TrackId)>,
     Query(query): Query<ListTrackPlaysQuery>,
     State(state): State<Arc<AppState>>,
-) -> Result<APIJson<ListTrackPlaysResponse>, APIError> {
-    let next_key = if let Some(next_token) = query.next_token {
-        Some(
-            Ulid::from_string(&next_token).or(Err(APIError::ValidationFailed {
-                message: Some("Invalid next_token"),
-            }))?,
-        )
-    } else {
-        None
-    };
-
-    let (track_plays_internal, next_key) = state
+) -> Result<Response, APIError> {
+    let first_page = query.next_token.is_none();
+    let (track_plays_internal, next_token) = state
         .crud_track
-        .list_plays_of_track(station_id, track_id, 50, next_key)
-        .await
-        .unwrap();
+        .list_plays_of_track(station_id, track_id, 50, query.next_token.as_deref())
+        .await?;
+
+    // a merged track has no plays left, only check for a redirect then
+    if first_page
+        && track_plays_internal.is_empty()
+        && let Some(merged_track_id) = state
+            .crud_track
+            .get_track_redirect(station_id, track_id)
+            .await?
+    {
+        // relative to /station/{station_id}/track/{track_id}/plays
+        return Ok(Redirect::permanent(&format!("../{}/plays", merged_track_id.0)).into_response());
+    }
 
     Ok(APIJson(ListTrackPlaysResponse {
         plays: track_plays_internal
             .into_iter()
             .map(PlayMinimal::from)
             .collect(),
-        next_token: next_key.map(NextToken::from),
-    }))
+        next_token: next_token.map(NextToken::from),
+    })
+    .into_response())
 }
 
 #[derive(Debug, Deserialize)]
@@ -117,34 +125,27 @@ pub(crate) async fn list_tracks(
     Query(query): Query<ListTracksQuery>,
     State(state): State<Arc<AppState>>,
 ) -> Result<APIJson<ListTracksResponse>, APIError> {
-    let (tracks_internal, next_key) = if let Some(artist) = query.artist {
+    let (tracks_internal, next_token) = if let Some(artist) = query.artist {
+        // the artist is matched with the normalization rules of the station
+        let station = state
+            .crud_station
+            .get_station(station_id)
+            .await?
+            .ok_or(APIError::NotFound)?;
+
         state
             .crud_track
-            .list_tracks_by_artist(station_id, &artist, 50, query.next_token.as_deref())
-            .await
-            .unwrap()
+            .list_tracks_by_artist(&station, &artist, 50, query.next_token.as_deref())
+            .await?
     } else {
-        let next_key = if let Some(next_token) = query.next_token {
-            Some(
-                Ulid::from_string(&next_token).or(Err(APIError::ValidationFailed {
-                    message: Some("Invalid next_token"),
-                }))?,
-            )
-        } else {
-            None
-        };
-
-        let (tracks_internal, next_key) = state
+        state
             .crud_track
-            .list_tracks(station_id, 50, next_key)
-            .await
-            .unwrap();
-
-        (tracks_internal, next_key.map(String::from))
+            .list_tracks(station_id, 50, query.next_token.as_deref())
+            .await?
     };
 
     Ok(APIJson(ListTracksResponse {
         tracks: tracks_internal.into_iter().map(Track::from).collect(),
-        next_token: next_key.map(NextToken::from),
+        next_token: next_token.map(NextToken::from),
     }))
 }
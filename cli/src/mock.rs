@@ -44,6 +44,7 @@ pub(crate) async fn mock_database(
         name: "coolism".to_string(),
         location: None,
         fetcher: Some(FetcherConfig::Coolism),
+        ..Default::default()
     };
 
     mock_station(crud_station, crud_logger, coolism)
@@ -56,6 +57,7 @@ pub(crate) async fn mock_database(
         fetcher: Some(FetcherConfig::Atime {
             station: AtimeStation::EFM,
         }),
+        ..Default::default()
     };
 
     mock_station(crud_station, crud_logger, efm).await.unwrap();
@@ -66,6 +68,7 @@ pub(crate) async fn mock_database(
         fetcher: Some(FetcherConfig::Atime {
             station: AtimeStation::Greenwave,
         }),
+        ..Default::default()
     };
 
     mock_station(crud_station, crud_logger, greenwave)
@@ -78,6 +81,7 @@ pub(crate) async fn mock_database(
         fetcher: Some(FetcherConfig::Atime {
             station: AtimeStation::Chill,
         }),
+        ..Default::default()
     };
 
     mock_station(crud_station, crud_logger, chill)
@@ -90,6 +94,7 @@ pub(crate) async fn mock_database(
         fetcher: Some(FetcherConfig::Iheart {
             slug: "whtz-fm".to_string(),
         }),
+        ..Default::default()
     };
 
     mock_station(crud_station, crud_logger, z100).await.unwrap();
@@ -100,6 +105,7 @@ pub(crate) async fn mock_database(
         fetcher: Some(FetcherConfig::Iheart {
             slug: "kiis-fm".to_string(),
         }),
+        ..Default::default()
     };
 
     mock_station(crud_station, crud_logger, kiis).await.unwrap();
@@ -143,3 +149,30 @@ async fn mock_station(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use radiojournal::backend::{Backend, MemoryBackend};
+
+    #[tokio::test]
+    async fn test_mock_database_in_memory() {
+        let context = Arc::new(Context::with_backend(Backend::Memory(MemoryBackend::new(
+            "radiojournal-test".to_owned(),
+        ))));
+
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+
+        mock_database(context, &crud_station, &crud_logger).await;
+
+        let (stations, _) = crud_station.list_stations(50, None).await.unwrap();
+        assert_eq!(stations.len(), 6);
+
+        for station in stations {
+            assert_eq!(station.play_count, 4);
+            assert_eq!(station.track_count, 3);
+        }
+    }
+}
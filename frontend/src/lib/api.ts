@@ -9,6 +9,16 @@ export type Station = {
   id: string;
   name: string;
   location?: string;
+  timezone?: string;
+  band?: "am" | "fm" | "dab" | "online";
+  frequency?: string;
+  country?: string;
+  language?: string;
+  website_url?: string;
+  stream_url?: string;
+  logo_url?: string;
+  genres: string[];
+  active: boolean;
   play_count: number;
   track_count: number;
 };
@@ -19,9 +29,21 @@ export const listStations = async ({
   fetch?: typeof window.fetch;
 }): Promise<Station[]> => {
   if (!fetch) fetch = window.fetch;
-  const res = await fetch(`${API_BASE_URL}/v1/stations`);
 
-  const stations: Station[] = await res.json();
+  const stations: Station[] = [];
+  let nextToken: string | null = null;
+
+  do {
+    const params = new URLSearchParams();
+    if (nextToken) params.append("next_token", nextToken);
+
+    const res = await fetch(`${API_BASE_URL}/v1/stations?${params.toString()}`);
+    const page: Station[] = await res.json();
+
+    stations.push(...page);
+    nextToken = res.headers.get("x-next-token");
+  } while (nextToken);
+
   return stations;
 };
 
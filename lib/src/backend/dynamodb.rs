@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::types::{
+    AttributeValue, KeysAndAttributes, Select, TransactWriteItem as DDBTransactWriteItem, Update,
+};
+
+use crate::backend::{
+    BatchGetItemConfig, BatchGetItemInput, BatchGetItemOutput, GetItemConfig, Item, Key,
+    ProjectedFields, QueryConfig, QueryOutput, QueryPrefixGsi1Input, QueryPrefixInput,
+    QueryRangeInput, StorageBackend, TransactWriteItem,
+};
+use crate::{Error, Result};
+
+/// Error codes of requests that failed because of load or a concurrent transaction on the
+/// same items, retrying them later can succeed.
+const RETRYABLE_ERROR_CODES: &[&str] = &[
+    "ProvisionedThroughputExceededException",
+    "RequestLimitExceeded",
+    "ThrottlingException",
+    "TransactionConflictException",
+    "TransactionInProgressException",
+];
+
+pub struct DynamoDBBackend {
+    pub(crate) client: Client,
+    pub(crate) table_name: String,
+}
+
+impl DynamoDBBackend {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self { client, table_name }
+    }
+}
+
+impl StorageBackend for DynamoDBBackend {
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    async fn get_item(&self, key: Key, config: GetItemConfig) -> Result<Option<Item>> {
+        let mut get_item = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(key.pk))
+            .key("sk", AttributeValue::S(key.sk))
+            .consistent_read(config.consistent_read);
+
+        if let ProjectedFields::Some(projected_fields) = config.projected_fields {
+            get_item = get_item.projection_expression(projected_fields.join(", "));
+        }
+
+        Ok(get_item.send().await?.item)
+    }
+
+    async fn put_item(&self, item: Item) -> Result<()> {
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_item(&self, update: Update) -> Result<()> {
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(update.key))
+            .update_expression(update.update_expression)
+            .set_condition_expression(update.condition_expression)
+            .set_expression_attribute_names(update.expression_attribute_names)
+            .set_expression_attribute_values(update.expression_attribute_values)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn query_prefix(
+        &self,
+        input: QueryPrefixInput,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        let mut query = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("pk = :pk AND begins_with(sk, :sk_prefix)")
+            .expression_attribute_values(":pk", AttributeValue::S(input.pk))
+            .expression_attribute_values(":sk_prefix", AttributeValue::S(input.sk_prefix))
+            .scan_index_forward(input.scan_forward)
+            .limit(config.limit);
+
+        query = match config.projected_fields {
+            ProjectedFields::All => query.select(Select::AllAttributes),
+            ProjectedFields::Some(projected_fields) => query
+                .select(Select::SpecificAttributes)
+                .projection_expression(projected_fields.join(", ")),
+        };
+
+        if let Some(exclusive_start_key) = input.exclusive_start_key {
+            query = query
+                .exclusive_start_key("pk", AttributeValue::S(exclusive_start_key.pk))
+                .exclusive_start_key("sk", AttributeValue::S(exclusive_start_key.sk));
+        }
+
+        let resp = query.send().await?;
+
+        Ok(QueryOutput {
+            items: resp.items.unwrap_or_default(),
+            last_evaluated_key: resp.last_evaluated_key,
+        })
+    }
+
+    async fn query_range(
+        &self,
+        input: QueryRangeInput,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        let mut query = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("pk = :pk AND sk BETWEEN :start_sk AND :end_sk")
+            .expression_attribute_values(":pk", AttributeValue::S(input.pk))
+            .expression_attribute_values(":start_sk", AttributeValue::S(input.start_sk))
+            .expression_attribute_values(":end_sk", AttributeValue::S(input.end_sk))
+            .scan_index_forward(input.scan_forward)
+            .limit(config.limit);
+
+        query = match config.projected_fields {
+            ProjectedFields::All => query.select(Select::AllAttributes),
+            ProjectedFields::Some(projected_fields) => query
+                .select(Select::SpecificAttributes)
+                .projection_expression(projected_fields.join(", ")),
+        };
+
+        if let Some(exclusive_start_key) = input.exclusive_start_key {
+            query = query
+                .exclusive_start_key("pk", AttributeValue::S(exclusive_start_key.pk))
+                .exclusive_start_key("sk", AttributeValue::S(exclusive_start_key.sk));
+        }
+
+        let resp = query.send().await?;
+
+        Ok(QueryOutput {
+            items: resp.items.unwrap_or_default(),
+            last_evaluated_key: resp.last_evaluated_key,
+        })
+    }
+
+    async fn query_prefix_gsi1(
+        &self,
+        input: QueryPrefixGsi1Input,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        let mut query = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .index_name("gsi1")
+            .key_condition_expression("gsi1pk = :gsi1pk AND begins_with(sk, :sk_prefix)")
+            .expression_attribute_values(":gsi1pk", AttributeValue::S(input.gsi1pk))
+            .expression_attribute_values(":sk_prefix", AttributeValue::S(input.sk_prefix))
+            .scan_index_forward(input.scan_forward)
+            .limit(config.limit);
+
+        if let ProjectedFields::Some(projected_fields) = config.projected_fields {
+            query = query
+                .select(Select::SpecificAttributes)
+                .projection_expression(projected_fields.join(", "));
+        }
+
+        if let Some(pk_prefix) = input.pk_prefix {
+            query = query
+                .filter_expression("begins_with(pk, :pk_prefix)")
+                .expression_attribute_values(":pk_prefix", AttributeValue::S(pk_prefix))
+        }
+
+        if let Some(exclusive_start_key) = input.exclusive_start_key {
+            query = query
+                .exclusive_start_key("gsi1pk", AttributeValue::S(exclusive_start_key.gsi1pk))
+                .exclusive_start_key("sk", AttributeValue::S(exclusive_start_key.sk))
+                .exclusive_start_key("pk", AttributeValue::S(exclusive_start_key.pk));
+        }
+
+        let resp = query.send().await?;
+
+        Ok(QueryOutput {
+            items: resp.items.unwrap_or_default(),
+            last_evaluated_key: resp.last_evaluated_key,
+        })
+    }
+
+    async fn batch_get_item(
+        &self,
+        input: BatchGetItemInput,
+        config: BatchGetItemConfig,
+    ) -> Result<BatchGetItemOutput> {
+        let mut request_keys = KeysAndAttributes::builder();
+
+        if let ProjectedFields::Some(projected_fields) = config.projected_fields {
+            request_keys = request_keys.projection_expression(projected_fields.join(", "));
+        }
+
+        for key in input.keys {
+            request_keys = request_keys.keys(HashMap::from([
+                ("pk".to_owned(), AttributeValue::S(key.pk)),
+                ("sk".to_owned(), AttributeValue::S(key.sk)),
+            ]))
+        }
+
+        let mut resp = self
+            .client
+            .batch_get_item()
+            .request_items(&self.table_name, request_keys.build()?)
+            .send()
+            .await?;
+
+        let items = resp
+            .responses
+            .as_mut()
+            .and_then(|responses| responses.remove(&self.table_name))
+            .unwrap_or_default();
+
+        let unprocessed_keys = resp
+            .unprocessed_keys
+            .as_mut()
+            .and_then(|unprocessed_keys| unprocessed_keys.remove(&self.table_name))
+            .map(|keys_and_attributes| {
+                keys_and_attributes
+                    .keys
+                    .into_iter()
+                    .map(serde_dynamo::from_item)
+                    .collect::<Result<Vec<Key>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(BatchGetItemOutput {
+            items,
+            unprocessed_keys,
+        })
+    }
+
+    async fn transact_write_items(&self, items: Vec<TransactWriteItem>) -> Result<()> {
+        let mut transaction = self.client.transact_write_items();
+
+        for item in items.into_iter() {
+            transaction = transaction.transact_items(match item {
+                TransactWriteItem::Put(put) => DDBTransactWriteItem::builder().put(put).build(),
+                TransactWriteItem::Update(update) => {
+                    DDBTransactWriteItem::builder().update(update).build()
+                }
+                TransactWriteItem::Delete(delete) => {
+                    DDBTransactWriteItem::builder().delete(delete).build()
+                }
+            });
+        }
+
+        transaction.send().await.map_err(transaction_error)?;
+
+        Ok(())
+    }
+}
+
+impl<E, R> From<SdkError<E, R>> for Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: std::fmt::Debug + Send + Sync + 'static,
+{
+    fn from(error: SdkError<E, R>) -> Self {
+        match error.code() {
+            Some("ConditionalCheckFailedException") => {
+                Self::ConditionFailed(error.message().unwrap_or_default().to_owned())
+            }
+            Some(code) if RETRYABLE_ERROR_CODES.contains(&code) => Self::Throttled(Box::new(error)),
+            _ => Self::backend(error),
+        }
+    }
+}
+
+/// Cancelled transactions report why each item failed, surface the first meaningful reason.
+fn transaction_error<R>(error: SdkError<TransactWriteItemsError, R>) -> Error
+where
+    R: std::fmt::Debug + Send + Sync + 'static,
+{
+    if let Some(TransactWriteItemsError::TransactionCanceledException(cancelled)) =
+        error.as_service_error()
+    {
+        for (index, reason) in cancelled.cancellation_reasons().iter().enumerate() {
+            match reason.code() {
+                Some("ConditionalCheckFailed") => {
+                    return Error::ConditionFailed(format!(
+                        "transaction cancelled at item {index}: {}",
+                        reason.message().unwrap_or_default()
+                    ));
+                }
+                Some(
+                    "ThrottlingError" | "ProvisionedThroughputExceeded" | "TransactionConflict",
+                ) => {
+                    return Error::Throttled(Box::new(error));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    error.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use aws_sdk_dynamodb::error::ErrorMetadata;
+    use aws_sdk_dynamodb::types::CancellationReason;
+    use aws_sdk_dynamodb::types::error::{
+        TransactionCanceledException, TransactionInProgressException,
+    };
+
+    fn cancelled(codes: &[&str]) -> SdkError<TransactWriteItemsError, ()> {
+        let reasons = codes
+            .iter()
+            .map(|code| CancellationReason::builder().code(*code).build())
+            .collect();
+        let exception = TransactionCanceledException::builder()
+            .set_cancellation_reasons(Some(reasons))
+            .meta(
+                ErrorMetadata::builder()
+                    .code("TransactionCanceledException")
+                    .build(),
+            )
+            .build();
+
+        SdkError::service_error(
+            TransactWriteItemsError::TransactionCanceledException(exception),
+            (),
+        )
+    }
+
+    #[test]
+    fn test_transaction_error_cancellation_reasons() {
+        assert!(matches!(
+            transaction_error(cancelled(&["None", "ConditionalCheckFailed"])),
+            Error::ConditionFailed(_)
+        ));
+        assert!(matches!(
+            transaction_error(cancelled(&["None", "TransactionConflict"])),
+            Error::Throttled(_)
+        ));
+        assert!(matches!(
+            transaction_error(cancelled(&["ThrottlingError"])),
+            Error::Throttled(_)
+        ));
+        assert!(matches!(
+            transaction_error(cancelled(&["ValidationError"])),
+            Error::Backend(_)
+        ));
+    }
+
+    #[test]
+    fn test_transaction_error_in_progress() {
+        let exception = TransactionInProgressException::builder()
+            .meta(
+                ErrorMetadata::builder()
+                    .code("TransactionInProgressException")
+                    .build(),
+            )
+            .build();
+        let error = SdkError::service_error(
+            TransactWriteItemsError::TransactionInProgressException(exception),
+            (),
+        );
+
+        assert!(matches!(transaction_error(error), Error::Throttled(_)));
+    }
+}
@@ -0,0 +1,182 @@
+//! Building blocks for backends that reproduce the DynamoDB table semantics on top of
+//! another store, see [`super::memory`] and [`super::sqlite`].
+
+use aws_sdk_dynamodb::types::{AttributeValue, Delete, Put, Update};
+
+use crate::backend::expression::{ExpressionAttributes, apply_update, evaluate_condition};
+use crate::backend::{Item, ProjectedFields, QueryOutput, TransactWriteItem};
+use crate::{Error, Result};
+
+/// Attributes projected into the `gsi1` index, mirroring the table definition in `mock::table`.
+pub(crate) const GSI1_PROJECTION: &[&str] = &["pk", "sk", "gsi1pk", "id", "track_id"];
+
+pub(crate) type TableKey = (String, String);
+
+/// Checks the put condition against the existing item and returns the item to store.
+fn prepare_put(put: Put, existing: Option<&Item>) -> Result<Item> {
+    if let Some(condition_expression) = &put.condition_expression {
+        check_condition(
+            condition_expression,
+            existing.unwrap_or(&Item::new()),
+            ExpressionAttributes {
+                names: put.expression_attribute_names.as_ref(),
+                values: put.expression_attribute_values.as_ref(),
+            },
+        )?;
+    }
+
+    Ok(put.item)
+}
+
+/// Checks the update condition against the existing item and returns the updated item.
+pub(crate) fn prepare_update(update: Update, existing: Option<&Item>) -> Result<Item> {
+    let key = table_key(&update.key)?;
+    let attributes = ExpressionAttributes {
+        names: update.expression_attribute_names.as_ref(),
+        values: update.expression_attribute_values.as_ref(),
+    };
+
+    if let Some(condition_expression) = &update.condition_expression {
+        check_condition(
+            condition_expression,
+            existing.unwrap_or(&Item::new()),
+            attributes,
+        )?;
+    }
+
+    // updating a missing item creates it from the key attributes
+    let mut item = existing.cloned().unwrap_or_else(|| update.key.clone());
+    apply_update(&update.update_expression, &mut item, attributes).map_err(Error::backend)?;
+
+    if table_key(&item)? != key {
+        return Err(Error::backend(
+            "update expression cannot modify key attributes",
+        ));
+    }
+
+    Ok(item)
+}
+
+/// Checks the delete condition against the existing item.
+pub(crate) fn prepare_delete(delete: &Delete, existing: Option<&Item>) -> Result<()> {
+    if let Some(condition_expression) = &delete.condition_expression {
+        check_condition(
+            condition_expression,
+            existing.unwrap_or(&Item::new()),
+            ExpressionAttributes {
+                names: delete.expression_attribute_names.as_ref(),
+                values: delete.expression_attribute_values.as_ref(),
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Stages a transaction item, `None` means the item is deleted.
+pub(crate) fn prepare_transact_write_item(
+    item: TransactWriteItem,
+    existing: Option<&Item>,
+) -> Result<Option<Item>> {
+    match item {
+        TransactWriteItem::Put(put) => prepare_put(put, existing).map(Some),
+        TransactWriteItem::Update(update) => prepare_update(update, existing).map(Some),
+        TransactWriteItem::Delete(delete) => prepare_delete(&delete, existing).map(|_| None),
+    }
+}
+
+pub(crate) fn transact_write_item_key(item: &TransactWriteItem) -> Result<TableKey> {
+    match item {
+        TransactWriteItem::Put(put) => table_key(&put.item),
+        TransactWriteItem::Update(update) => table_key(&update.key),
+        TransactWriteItem::Delete(delete) => table_key(&delete.key),
+    }
+}
+
+fn check_condition(expression: &str, item: &Item, attributes: ExpressionAttributes) -> Result<()> {
+    if evaluate_condition(expression, item, attributes).map_err(Error::backend)? {
+        Ok(())
+    } else {
+        Err(Error::ConditionFailed(expression.to_owned()))
+    }
+}
+
+/// Points a failed condition at the transaction item it belongs to.
+pub(crate) fn cancel_transaction(index: usize, error: Error) -> Error {
+    match error {
+        Error::ConditionFailed(reason) => {
+            Error::ConditionFailed(format!("transaction cancelled at item {index}: {reason}"))
+        }
+        error => error,
+    }
+}
+
+pub(crate) fn duplicate_transaction_item() -> Error {
+    Error::backend("transaction cannot include more than one operation on the same item")
+}
+
+pub(crate) fn table_key(item: &Item) -> Result<TableKey> {
+    match (string_attribute(item, "pk"), string_attribute(item, "sk")) {
+        (Some(pk), Some(sk)) => Ok((pk.to_owned(), sk.to_owned())),
+        _ => Err(Error::backend("item must have string pk and sk attributes")),
+    }
+}
+
+pub(crate) fn string_attribute<'i>(item: &'i Item, name: &str) -> Option<&'i str> {
+    match item.get(name) {
+        Some(AttributeValue::S(value)) => Some(value),
+        _ => None,
+    }
+}
+
+pub(crate) fn project(item: &Item, projected_fields: &ProjectedFields) -> Item {
+    match projected_fields {
+        ProjectedFields::All => item.clone(),
+        ProjectedFields::Some(fields) => item
+            .iter()
+            .filter(|(name, _)| fields.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+    }
+}
+
+/// Rejects a query limit below 1 the way DynamoDB does, with a validation error.
+pub(crate) fn query_limit(limit: i32) -> Result<usize> {
+    usize::try_from(limit)
+        .ok()
+        .filter(|limit| *limit >= 1)
+        .ok_or_else(|| Error::backend("limit must be greater than or equal to 1"))
+}
+
+/// Applies the query limit and builds the `LastEvaluatedKey` the way DynamoDB does:
+/// whenever the limit is reached, even if no further items exist.
+pub(crate) fn paginate(
+    matched: Vec<&Item>,
+    limit: i32,
+    key_attributes: &'static [&'static str],
+    output_item: impl Fn(&Item) -> Item,
+) -> Result<QueryOutput> {
+    let limit = query_limit(limit)?;
+    let evaluated: Vec<&Item> = matched.into_iter().take(limit).collect();
+
+    let last_evaluated_key = if evaluated.len() == limit {
+        evaluated
+            .last()
+            .map(|item| project(item, &ProjectedFields::Some(key_attributes)))
+    } else {
+        None
+    };
+
+    Ok(QueryOutput {
+        items: evaluated.into_iter().map(output_item).collect(),
+        last_evaluated_key,
+    })
+}
+
+/// Projects an item the way a `gsi1` query returns it.
+pub(crate) fn project_gsi1(item: &Item, projected_fields: &ProjectedFields) -> Item {
+    project(
+        &project(item, &ProjectedFields::Some(GSI1_PROJECTION)),
+        projected_fields,
+    )
+}
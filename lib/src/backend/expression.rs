@@ -0,0 +1,959 @@
+//! Evaluator for the subset of DynamoDB update and condition expressions used by radiojournal.
+//!
+//! Backends that don't talk to DynamoDB use this to apply `Put`/`Update` requests with the
+//! same semantics: `SET`/`REMOVE`/`ADD` clauses, `if_not_exists`/`list_append`, arithmetic,
+//! comparisons, `BETWEEN`/`IN`, boolean operators and the `attribute_exists`,
+//! `attribute_not_exists`, `begins_with`, `contains` and `size` functions.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+use aws_sdk_dynamodb::types::AttributeValue;
+
+use crate::backend::Item;
+
+/// Placeholder substitutions supplied alongside an expression.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ExpressionAttributes<'a> {
+    pub names: Option<&'a HashMap<String, String>>,
+    pub values: Option<&'a HashMap<String, AttributeValue>>,
+}
+
+/// Evaluates a condition expression against `item`. Missing items are represented by an empty map.
+pub(crate) fn evaluate_condition(
+    expression: &str,
+    item: &Item,
+    attributes: ExpressionAttributes,
+) -> Result<bool> {
+    let mut parser = Parser::new(expression, attributes)?;
+    let condition = parser.parse_condition()?;
+    parser.expect_end()?;
+
+    condition.evaluate(item)
+}
+
+/// Applies an update expression to `item` in place.
+pub(crate) fn apply_update(
+    expression: &str,
+    item: &mut Item,
+    attributes: ExpressionAttributes,
+) -> Result<()> {
+    let mut parser = Parser::new(expression, attributes)?;
+    let actions = parser.parse_update()?;
+    parser.expect_end()?;
+
+    // every operand is evaluated against the item as it was before the update
+    let mut resolved = vec![];
+    for action in actions {
+        resolved.push(match action {
+            UpdateAction::Set(path, operand) => {
+                let value = operand
+                    .evaluate(item)?
+                    .ok_or_else(|| anyhow!("update operand refers to a missing attribute"))?;
+                (path, Some(value))
+            }
+            UpdateAction::Add(path, operand) => {
+                let increment = operand
+                    .evaluate(item)?
+                    .ok_or_else(|| anyhow!("ADD operand refers to a missing attribute"))?;
+                let value = match get_path(item, &path) {
+                    Some(current) => arithmetic(current, &increment, Number::add)?,
+                    None => {
+                        arithmetic(&AttributeValue::N("0".to_owned()), &increment, Number::add)?
+                    }
+                };
+                (path, Some(value))
+            }
+            UpdateAction::Remove(path) => (path, None),
+        });
+    }
+
+    for (path, value) in resolved {
+        match value {
+            Some(value) => set_path(item, &path, value)?,
+            None => remove_path(item, &path),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Name(String),
+    Value(String),
+    Index(usize),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Dot,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Plus,
+    Minus,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | '[' | ']' | ',' | '.' | '=' | '+' | '-' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    ',' => Token::Comma,
+                    '.' => Token::Dot,
+                    '=' => Token::Eq,
+                    '+' => Token::Plus,
+                    _ => Token::Minus,
+                });
+            }
+            '<' => {
+                chars.next();
+                tokens.push(match chars.peek() {
+                    Some('>') => {
+                        chars.next();
+                        Token::Ne
+                    }
+                    Some('=') => {
+                        chars.next();
+                        Token::Le
+                    }
+                    _ => Token::Lt,
+                });
+            }
+            '>' => {
+                chars.next();
+                tokens.push(match chars.peek() {
+                    Some('=') => {
+                        chars.next();
+                        Token::Ge
+                    }
+                    _ => Token::Gt,
+                });
+            }
+            '#' | ':' => {
+                chars.next();
+                let word = take_word(&mut chars);
+                if word.is_empty() {
+                    bail!("empty placeholder in expression: {expression}");
+                }
+                tokens.push(match c {
+                    '#' => Token::Name(format!("#{word}")),
+                    _ => Token::Value(format!(":{word}")),
+                });
+            }
+            c if c.is_ascii_digit() => {
+                let word = take_word(&mut chars);
+                tokens.push(Token::Index(word.parse()?));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                tokens.push(Token::Ident(take_word(&mut chars)));
+            }
+            c => bail!("unexpected character {c:?} in expression: {expression}"),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn take_word(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut word = String::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    word
+}
+
+#[derive(Debug, Clone)]
+enum PathElement {
+    Attribute(String),
+    Index(usize),
+}
+
+type Path = Vec<PathElement>;
+
+#[derive(Debug)]
+enum Operand {
+    Path(Path),
+    Value(AttributeValue),
+    Size(Path),
+    IfNotExists(Path, Box<Operand>),
+    ListAppend(Box<Operand>, Box<Operand>),
+    Plus(Box<Operand>, Box<Operand>),
+    Minus(Box<Operand>, Box<Operand>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Condition {
+    Compare(Operand, Comparator, Operand),
+    Between(Operand, Operand, Operand),
+    In(Operand, Vec<Operand>),
+    AttributeExists(Path),
+    AttributeNotExists(Path),
+    BeginsWith(Operand, Operand),
+    Contains(Operand, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+#[derive(Debug)]
+enum UpdateAction {
+    Set(Path, Operand),
+    Remove(Path),
+    Add(Path, Operand),
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    attributes: ExpressionAttributes<'a>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(expression: &str, attributes: ExpressionAttributes<'a>) -> Result<Self> {
+        Ok(Self {
+            tokens: tokenize(expression)?,
+            position: 0,
+            attributes,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(keyword))
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| anyhow!("unexpected end of expression"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let token = self.next()?;
+        if token != expected {
+            bail!("expected {expected:?} but found {token:?}");
+        }
+        Ok(())
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if !self.peek_keyword(keyword) {
+            bail!("expected {keyword} but found {:?}", self.peek());
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn expect_end(&self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => bail!("unexpected trailing token {token:?}"),
+        }
+    }
+
+    fn resolve_name(&self, placeholder: &str) -> Result<String> {
+        self.attributes
+            .names
+            .and_then(|names| names.get(placeholder))
+            .cloned()
+            .ok_or_else(|| anyhow!("missing expression attribute name {placeholder}"))
+    }
+
+    fn resolve_value(&self, placeholder: &str) -> Result<AttributeValue> {
+        self.attributes
+            .values
+            .and_then(|values| values.get(placeholder))
+            .cloned()
+            .ok_or_else(|| anyhow!("missing expression attribute value {placeholder}"))
+    }
+
+    fn parse_path(&mut self) -> Result<Path> {
+        let mut path = vec![self.parse_path_attribute()?];
+
+        loop {
+            match self.peek() {
+                Some(Token::Dot) => {
+                    self.position += 1;
+                    path.push(self.parse_path_attribute()?);
+                }
+                Some(Token::LBracket) => {
+                    self.position += 1;
+                    match self.next()? {
+                        Token::Index(index) => path.push(PathElement::Index(index)),
+                        token => bail!("expected list index but found {token:?}"),
+                    }
+                    self.expect(Token::RBracket)?;
+                }
+                _ => return Ok(path),
+            }
+        }
+    }
+
+    fn parse_path_attribute(&mut self) -> Result<PathElement> {
+        match self.next()? {
+            Token::Ident(name) => Ok(PathElement::Attribute(name)),
+            Token::Name(placeholder) => {
+                Ok(PathElement::Attribute(self.resolve_name(&placeholder)?))
+            }
+            token => bail!("expected attribute name but found {token:?}"),
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        match self.peek() {
+            Some(Token::Value(placeholder)) => {
+                let value = self.resolve_value(&placeholder.clone())?;
+                self.position += 1;
+                Ok(Operand::Value(value))
+            }
+            Some(Token::Ident(ident))
+                if self.tokens.get(self.position + 1) == Some(&Token::LParen) =>
+            {
+                let function = ident.clone();
+                self.position += 2;
+                let operand = match function.as_str() {
+                    "size" => Operand::Size(self.parse_path()?),
+                    "if_not_exists" => {
+                        let path = self.parse_path()?;
+                        self.expect(Token::Comma)?;
+                        Operand::IfNotExists(path, Box::new(self.parse_operand()?))
+                    }
+                    "list_append" => {
+                        let left = self.parse_operand()?;
+                        self.expect(Token::Comma)?;
+                        Operand::ListAppend(Box::new(left), Box::new(self.parse_operand()?))
+                    }
+                    function => bail!("unsupported function {function} in operand"),
+                };
+                self.expect(Token::RParen)?;
+                Ok(operand)
+            }
+            _ => Ok(Operand::Path(self.parse_path()?)),
+        }
+    }
+
+    fn parse_set_value(&mut self) -> Result<Operand> {
+        let left = self.parse_operand()?;
+        match self.peek() {
+            Some(Token::Plus) => {
+                self.position += 1;
+                Ok(Operand::Plus(
+                    Box::new(left),
+                    Box::new(self.parse_operand()?),
+                ))
+            }
+            Some(Token::Minus) => {
+                self.position += 1;
+                Ok(Operand::Minus(
+                    Box::new(left),
+                    Box::new(self.parse_operand()?),
+                ))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn parse_condition(&mut self) -> Result<Condition> {
+        let mut condition = self.parse_and()?;
+        while self.peek_keyword("OR") {
+            self.position += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.parse_and()?));
+        }
+        Ok(condition)
+    }
+
+    fn parse_and(&mut self) -> Result<Condition> {
+        let mut condition = self.parse_not()?;
+        while self.peek_keyword("AND") {
+            self.position += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.parse_not()?));
+        }
+        Ok(condition)
+    }
+
+    fn parse_not(&mut self) -> Result<Condition> {
+        if self.peek_keyword("NOT") {
+            self.position += 1;
+            return Ok(Condition::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Condition> {
+        if self.peek() == Some(&Token::LParen) {
+            self.position += 1;
+            let condition = self.parse_condition()?;
+            self.expect(Token::RParen)?;
+            return Ok(condition);
+        }
+
+        if let Some(Token::Ident(ident)) = self.peek()
+            && self.tokens.get(self.position + 1) == Some(&Token::LParen)
+            && ident != "size"
+        {
+            let function = ident.clone();
+            self.position += 2;
+            let condition = match function.as_str() {
+                "attribute_exists" => Condition::AttributeExists(self.parse_path()?),
+                "attribute_not_exists" => Condition::AttributeNotExists(self.parse_path()?),
+                "begins_with" | "contains" => {
+                    let left = self.parse_operand()?;
+                    self.expect(Token::Comma)?;
+                    let right = self.parse_operand()?;
+                    if function == "begins_with" {
+                        Condition::BeginsWith(left, right)
+                    } else {
+                        Condition::Contains(left, right)
+                    }
+                }
+                function => bail!("unsupported function {function} in condition"),
+            };
+            self.expect(Token::RParen)?;
+            return Ok(condition);
+        }
+
+        let left = self.parse_operand()?;
+
+        if self.peek_keyword("BETWEEN") {
+            self.position += 1;
+            let lower = self.parse_operand()?;
+            self.expect_keyword("AND")?;
+            let upper = self.parse_operand()?;
+            return Ok(Condition::Between(left, lower, upper));
+        }
+
+        if self.peek_keyword("IN") {
+            self.position += 1;
+            self.expect(Token::LParen)?;
+            let mut candidates = vec![self.parse_operand()?];
+            while self.peek() == Some(&Token::Comma) {
+                self.position += 1;
+                candidates.push(self.parse_operand()?);
+            }
+            self.expect(Token::RParen)?;
+            return Ok(Condition::In(left, candidates));
+        }
+
+        let comparator = match self.next()? {
+            Token::Eq => Comparator::Eq,
+            Token::Ne => Comparator::Ne,
+            Token::Lt => Comparator::Lt,
+            Token::Le => Comparator::Le,
+            Token::Gt => Comparator::Gt,
+            Token::Ge => Comparator::Ge,
+            token => bail!("expected comparator but found {token:?}"),
+        };
+
+        Ok(Condition::Compare(left, comparator, self.parse_operand()?))
+    }
+
+    fn parse_update(&mut self) -> Result<Vec<UpdateAction>> {
+        let mut actions = vec![];
+
+        while self.peek().is_some() {
+            let clause = match self.next()? {
+                Token::Ident(clause) => clause.to_ascii_uppercase(),
+                token => bail!("expected update clause but found {token:?}"),
+            };
+
+            loop {
+                actions.push(match clause.as_str() {
+                    "SET" => {
+                        let path = self.parse_path()?;
+                        self.expect(Token::Eq)?;
+                        UpdateAction::Set(path, self.parse_set_value()?)
+                    }
+                    "REMOVE" => UpdateAction::Remove(self.parse_path()?),
+                    "ADD" => {
+                        let path = self.parse_path()?;
+                        UpdateAction::Add(path, self.parse_operand()?)
+                    }
+                    clause => bail!("unsupported update clause {clause}"),
+                });
+
+                if self.peek() == Some(&Token::Comma) {
+                    self.position += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        if actions.is_empty() {
+            bail!("empty update expression");
+        }
+
+        Ok(actions)
+    }
+}
+
+impl Operand {
+    fn evaluate(&self, item: &Item) -> Result<Option<AttributeValue>> {
+        Ok(match self {
+            Self::Path(path) => get_path(item, path).cloned(),
+            Self::Value(value) => Some(value.clone()),
+            Self::Size(path) => get_path(item, path)
+                .and_then(size)
+                .map(|size| AttributeValue::N(size.to_string())),
+            Self::IfNotExists(path, fallback) => match get_path(item, path) {
+                Some(value) => Some(value.clone()),
+                None => fallback.evaluate(item)?,
+            },
+            Self::ListAppend(left, right) => match (left.evaluate(item)?, right.evaluate(item)?) {
+                (Some(AttributeValue::L(mut left)), Some(AttributeValue::L(right))) => {
+                    left.extend(right);
+                    Some(AttributeValue::L(left))
+                }
+                _ => bail!("list_append operands must be lists"),
+            },
+            Self::Plus(left, right) | Self::Minus(left, right) => {
+                let (Some(left), Some(right)) = (left.evaluate(item)?, right.evaluate(item)?)
+                else {
+                    bail!("arithmetic operand refers to a missing attribute");
+                };
+                Some(match self {
+                    Self::Plus(..) => arithmetic(&left, &right, Number::add)?,
+                    _ => arithmetic(&left, &right, Number::sub)?,
+                })
+            }
+        })
+    }
+}
+
+impl Condition {
+    fn evaluate(&self, item: &Item) -> Result<bool> {
+        Ok(match self {
+            Self::Compare(left, comparator, right) => {
+                let (left, right) = (left.evaluate(item)?, right.evaluate(item)?);
+                match (left, right, comparator) {
+                    (Some(left), Some(right), Comparator::Eq) => values_equal(&left, &right),
+                    (Some(left), Some(right), Comparator::Ne) => !values_equal(&left, &right),
+                    (Some(left), Some(right), comparator) => match compare_values(&left, &right) {
+                        Some(ordering) => match comparator {
+                            Comparator::Lt => ordering.is_lt(),
+                            Comparator::Le => ordering.is_le(),
+                            Comparator::Gt => ordering.is_gt(),
+                            _ => ordering.is_ge(),
+                        },
+                        None => false,
+                    },
+                    // comparing against a missing attribute never matches
+                    _ => false,
+                }
+            }
+            Self::Between(value, lower, upper) => {
+                match (
+                    value.evaluate(item)?,
+                    lower.evaluate(item)?,
+                    upper.evaluate(item)?,
+                ) {
+                    (Some(value), Some(lower), Some(upper)) => {
+                        compare_values(&value, &lower).is_some_and(Ordering::is_ge)
+                            && compare_values(&value, &upper).is_some_and(Ordering::is_le)
+                    }
+                    _ => false,
+                }
+            }
+            Self::In(value, candidates) => match value.evaluate(item)? {
+                Some(value) => {
+                    let mut found = false;
+                    for candidate in candidates {
+                        if candidate
+                            .evaluate(item)?
+                            .is_some_and(|candidate| values_equal(&value, &candidate))
+                        {
+                            found = true;
+                            break;
+                        }
+                    }
+                    found
+                }
+                None => false,
+            },
+            Self::AttributeExists(path) => get_path(item, path).is_some(),
+            Self::AttributeNotExists(path) => get_path(item, path).is_none(),
+            Self::BeginsWith(value, prefix) => {
+                match (value.evaluate(item)?, prefix.evaluate(item)?) {
+                    (Some(AttributeValue::S(value)), Some(AttributeValue::S(prefix))) => {
+                        value.starts_with(&prefix)
+                    }
+                    _ => false,
+                }
+            }
+            Self::Contains(value, needle) => {
+                match (value.evaluate(item)?, needle.evaluate(item)?) {
+                    (Some(AttributeValue::S(value)), Some(AttributeValue::S(needle))) => {
+                        value.contains(&needle)
+                    }
+                    (Some(AttributeValue::L(values)), Some(needle)) => {
+                        values.iter().any(|value| values_equal(value, &needle))
+                    }
+                    (Some(AttributeValue::Ss(values)), Some(AttributeValue::S(needle))) => {
+                        values.contains(&needle)
+                    }
+                    _ => false,
+                }
+            }
+            Self::And(left, right) => left.evaluate(item)? && right.evaluate(item)?,
+            Self::Or(left, right) => left.evaluate(item)? || right.evaluate(item)?,
+            Self::Not(condition) => !condition.evaluate(item)?,
+        })
+    }
+}
+
+fn get_path<'i>(item: &'i Item, path: &Path) -> Option<&'i AttributeValue> {
+    let (first, rest) = path.split_first()?;
+    let PathElement::Attribute(name) = first else {
+        return None;
+    };
+
+    let mut value = item.get(name)?;
+    for element in rest {
+        value = match (element, value) {
+            (PathElement::Attribute(name), AttributeValue::M(map)) => map.get(name)?,
+            (PathElement::Index(index), AttributeValue::L(list)) => list.get(*index)?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+fn set_path(item: &mut Item, path: &Path, new_value: AttributeValue) -> Result<()> {
+    let Some((PathElement::Attribute(name), rest)) = path.split_first() else {
+        bail!("update path must start with an attribute name");
+    };
+
+    let Some((last, parents)) = rest.split_last() else {
+        item.insert(name.clone(), new_value);
+        return Ok(());
+    };
+
+    let mut value = item
+        .get_mut(name)
+        .ok_or_else(|| anyhow!("document path {name} does not exist"))?;
+    for element in parents {
+        value = match (element, value) {
+            (PathElement::Attribute(name), AttributeValue::M(map)) => map
+                .get_mut(name)
+                .ok_or_else(|| anyhow!("document path {name} does not exist"))?,
+            (PathElement::Index(index), AttributeValue::L(list)) => list
+                .get_mut(*index)
+                .ok_or_else(|| anyhow!("list index {index} does not exist"))?,
+            _ => bail!("document path is invalid for update"),
+        };
+    }
+
+    match (last, value) {
+        (PathElement::Attribute(name), AttributeValue::M(map)) => {
+            map.insert(name.clone(), new_value);
+        }
+        (PathElement::Index(index), AttributeValue::L(list)) => {
+            if *index < list.len() {
+                list[*index] = new_value;
+            } else {
+                list.push(new_value);
+            }
+        }
+        _ => bail!("document path is invalid for update"),
+    }
+
+    Ok(())
+}
+
+fn remove_path(item: &mut Item, path: &Path) {
+    let Some((PathElement::Attribute(name), rest)) = path.split_first() else {
+        return;
+    };
+
+    let Some((last, parents)) = rest.split_last() else {
+        item.remove(name);
+        return;
+    };
+
+    let Some(mut value) = item.get_mut(name) else {
+        return;
+    };
+    for element in parents {
+        let next = match (element, value) {
+            (PathElement::Attribute(name), AttributeValue::M(map)) => map.get_mut(name),
+            (PathElement::Index(index), AttributeValue::L(list)) => list.get_mut(*index),
+            _ => None,
+        };
+        let Some(next) = next else {
+            return;
+        };
+        value = next;
+    }
+
+    match (last, value) {
+        (PathElement::Attribute(name), AttributeValue::M(map)) => {
+            map.remove(name);
+        }
+        (PathElement::Index(index), AttributeValue::L(list)) if *index < list.len() => {
+            list.remove(*index);
+        }
+        _ => {}
+    }
+}
+
+fn size(value: &AttributeValue) -> Option<usize> {
+    Some(match value {
+        AttributeValue::S(value) => value.chars().count(),
+        AttributeValue::B(value) => value.as_ref().len(),
+        AttributeValue::L(value) => value.len(),
+        AttributeValue::M(value) => value.len(),
+        AttributeValue::Ss(value) => value.len(),
+        AttributeValue::Ns(value) => value.len(),
+        AttributeValue::Bs(value) => value.len(),
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Integer(i128),
+    Float(f64),
+}
+
+impl Number {
+    fn parse(value: &str) -> Result<Self> {
+        if let Ok(integer) = value.parse() {
+            Ok(Self::Integer(integer))
+        } else {
+            Ok(Self::Float(value.parse()?))
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Self::Integer(integer) => integer as f64,
+            Self::Float(float) => float,
+        }
+    }
+
+    fn add(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Integer(left), Self::Integer(right)) => Self::Integer(left + right),
+            (left, right) => Self::Float(left.as_f64() + right.as_f64()),
+        }
+    }
+
+    fn sub(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Integer(left), Self::Integer(right)) => Self::Integer(left - right),
+            (left, right) => Self::Float(left.as_f64() - right.as_f64()),
+        }
+    }
+
+    fn compare(self, other: Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Integer(left), Self::Integer(right)) => Some(left.cmp(&right)),
+            (left, right) => left.as_f64().partial_cmp(&right.as_f64()),
+        }
+    }
+}
+
+impl std::fmt::Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(integer) => write!(f, "{integer}"),
+            Self::Float(float) => write!(f, "{float}"),
+        }
+    }
+}
+
+fn arithmetic(
+    left: &AttributeValue,
+    right: &AttributeValue,
+    operation: fn(Number, Number) -> Number,
+) -> Result<AttributeValue> {
+    match (left, right) {
+        (AttributeValue::N(left), AttributeValue::N(right)) => Ok(AttributeValue::N(
+            operation(Number::parse(left)?, Number::parse(right)?).to_string(),
+        )),
+        _ => bail!("arithmetic operands must be numbers"),
+    }
+}
+
+fn values_equal(left: &AttributeValue, right: &AttributeValue) -> bool {
+    match (left, right) {
+        (AttributeValue::N(_), AttributeValue::N(_)) => {
+            compare_values(left, right).is_some_and(Ordering::is_eq)
+        }
+        (left, right) => left == right,
+    }
+}
+
+fn compare_values(left: &AttributeValue, right: &AttributeValue) -> Option<Ordering> {
+    match (left, right) {
+        (AttributeValue::S(left), AttributeValue::S(right)) => Some(left.cmp(right)),
+        (AttributeValue::N(left), AttributeValue::N(right)) => Number::parse(left)
+            .ok()?
+            .compare(Number::parse(right).ok()?),
+        (AttributeValue::B(left), AttributeValue::B(right)) => {
+            Some(left.as_ref().cmp(right.as_ref()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn values(pairs: &[(&str, AttributeValue)]) -> HashMap<String, AttributeValue> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn station_item() -> Item {
+        values(&[
+            ("pk", AttributeValue::S("STATIONS".to_owned())),
+            ("sk", AttributeValue::S("STATION#1".to_owned())),
+            ("play_count", AttributeValue::N("2".to_owned())),
+            ("first_play_id", AttributeValue::Null(true)),
+            (
+                "updated_ts",
+                AttributeValue::S("2001-02-03T04:05:06Z".to_owned()),
+            ),
+        ])
+    }
+
+    #[rstest]
+    #[case("updated_ts = :ts", true)]
+    #[case("updated_ts = :other_ts", false)]
+    #[case("updated_ts <> :other_ts", true)]
+    #[case("first_play_id = :null", true)]
+    #[case("missing = :null", false)]
+    #[case("play_count > :one AND play_count <= :two", true)]
+    #[case("play_count BETWEEN :one AND :two", true)]
+    #[case("play_count IN (:one, :ts)", false)]
+    #[case("attribute_exists(pk) AND attribute_not_exists(missing)", true)]
+    #[case("begins_with(sk, :prefix)", true)]
+    #[case("NOT begins_with(#sk, :prefix) OR (play_count = :two)", true)]
+    #[case("size(sk) > :two", true)]
+    fn test_evaluate_condition(#[case] expression: &str, #[case] expected: bool) {
+        let names = HashMap::from([("#sk".to_owned(), "sk".to_owned())]);
+        let values = values(&[
+            (":ts", AttributeValue::S("2001-02-03T04:05:06Z".to_owned())),
+            (
+                ":other_ts",
+                AttributeValue::S("2001-02-03T04:05:07Z".to_owned()),
+            ),
+            (":null", AttributeValue::Null(true)),
+            (":one", AttributeValue::N("1".to_owned())),
+            (":two", AttributeValue::N("2.0".to_owned())),
+            (":prefix", AttributeValue::S("STATION#".to_owned())),
+        ]);
+
+        let result = evaluate_condition(
+            expression,
+            &station_item(),
+            ExpressionAttributes {
+                names: Some(&names),
+                values: Some(&values),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_apply_update() {
+        let mut item = station_item();
+        let values = values(&[
+            (":ts", AttributeValue::S("2001-02-03T04:05:07Z".to_owned())),
+            (":inc", AttributeValue::N("1".to_owned())),
+            (":zero", AttributeValue::N("0".to_owned())),
+        ]);
+
+        apply_update(
+            "SET updated_ts = :ts, play_count = play_count + :inc, track_count = if_not_exists(track_count, :zero) + :inc REMOVE first_play_id ADD counter :inc",
+            &mut item,
+            ExpressionAttributes {
+                names: None,
+                values: Some(&values),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            item.get("updated_ts"),
+            Some(&AttributeValue::S("2001-02-03T04:05:07Z".to_owned()))
+        );
+        assert_eq!(
+            item.get("play_count"),
+            Some(&AttributeValue::N("3".to_owned()))
+        );
+        assert_eq!(
+            item.get("track_count"),
+            Some(&AttributeValue::N("1".to_owned()))
+        );
+        assert_eq!(
+            item.get("counter"),
+            Some(&AttributeValue::N("1".to_owned()))
+        );
+        assert!(!item.contains_key("first_play_id"));
+    }
+
+    #[test]
+    fn test_apply_update_missing_value_fails() {
+        let mut item = station_item();
+
+        assert!(
+            apply_update(
+                "SET play_count = play_count + :inc",
+                &mut item,
+                ExpressionAttributes::default(),
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,559 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::RwLock;
+
+use aws_sdk_dynamodb::types::Update;
+
+use crate::Result;
+use crate::backend::emulated::{
+    TableKey, cancel_transaction, duplicate_transaction_item, paginate,
+    prepare_transact_write_item, prepare_update, project, project_gsi1, string_attribute,
+    table_key, transact_write_item_key,
+};
+use crate::backend::{
+    BatchGetItemConfig, BatchGetItemInput, BatchGetItemOutput, GetItemConfig, Item, Key,
+    QueryConfig, QueryOutput, QueryPrefixGsi1Input, QueryPrefixInput, QueryRangeInput,
+    StorageBackend, TransactWriteItem,
+};
+
+type Table = BTreeMap<TableKey, Item>;
+
+/// In-process implementation of the radiojournal DynamoDB table, for tests and local development.
+pub struct MemoryBackend {
+    table_name: String,
+    items: RwLock<Table>,
+}
+
+impl MemoryBackend {
+    pub fn new(table_name: String) -> Self {
+        Self {
+            table_name,
+            items: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Removes every item, leaving an empty table.
+    pub fn clear(&self) {
+        self.items.write().expect("memory table lock").clear();
+    }
+
+    fn query_partition(
+        &self,
+        pk: &str,
+        sk_matches: impl Fn(&str) -> bool,
+        scan_forward: bool,
+        exclusive_start_key: Option<Key>,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        let table = self.items.read().expect("memory table lock");
+
+        let mut matched: Vec<&Item> = table
+            .range((pk.to_owned(), String::new())..)
+            .take_while(|((item_pk, _), _)| item_pk == pk)
+            .filter(|((_, sk), _)| sk_matches(sk))
+            .map(|(_, item)| item)
+            .collect();
+
+        if !scan_forward {
+            matched.reverse();
+        }
+
+        if let Some(exclusive_start_key) = exclusive_start_key {
+            matched.retain(|item| {
+                let sk = string_attribute(item, "sk").unwrap_or_default();
+                if scan_forward {
+                    sk > exclusive_start_key.sk.as_str()
+                } else {
+                    sk < exclusive_start_key.sk.as_str()
+                }
+            });
+        }
+
+        paginate(matched, config.limit, &["pk", "sk"], |item| {
+            project(item, &config.projected_fields)
+        })
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    async fn get_item(&self, key: Key, config: GetItemConfig) -> Result<Option<Item>> {
+        let table = self.items.read().expect("memory table lock");
+
+        Ok(table
+            .get(&(key.pk, key.sk))
+            .map(|item| project(item, &config.projected_fields)))
+    }
+
+    async fn put_item(&self, item: Item) -> Result<()> {
+        let key = table_key(&item)?;
+        self.items
+            .write()
+            .expect("memory table lock")
+            .insert(key, item);
+
+        Ok(())
+    }
+
+    async fn update_item(&self, update: Update) -> Result<()> {
+        let mut table = self.items.write().expect("memory table lock");
+
+        let key = table_key(&update.key)?;
+        let item = prepare_update(update, table.get(&key))?;
+        table.insert(key, item);
+
+        Ok(())
+    }
+
+    async fn query_prefix(
+        &self,
+        input: QueryPrefixInput,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        self.query_partition(
+            &input.pk,
+            |sk| sk.starts_with(&input.sk_prefix),
+            input.scan_forward,
+            input.exclusive_start_key,
+            config,
+        )
+    }
+
+    async fn query_range(
+        &self,
+        input: QueryRangeInput,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        self.query_partition(
+            &input.pk,
+            |sk| input.start_sk.as_str() <= sk && sk <= input.end_sk.as_str(),
+            input.scan_forward,
+            input.exclusive_start_key,
+            config,
+        )
+    }
+
+    async fn query_prefix_gsi1(
+        &self,
+        input: QueryPrefixGsi1Input,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        let table = self.items.read().expect("memory table lock");
+
+        let mut matched: Vec<&Item> = table
+            .values()
+            .filter(|item| string_attribute(item, "gsi1pk") == Some(input.gsi1pk.as_str()))
+            .filter(|item| {
+                string_attribute(item, "sk").is_some_and(|sk| sk.starts_with(&input.sk_prefix))
+            })
+            .collect();
+
+        let index_key = |item: &Item| {
+            (
+                string_attribute(item, "sk").unwrap_or_default().to_owned(),
+                string_attribute(item, "pk").unwrap_or_default().to_owned(),
+            )
+        };
+
+        matched.sort_by_key(|item| index_key(item));
+        if !input.scan_forward {
+            matched.reverse();
+        }
+
+        if let Some(exclusive_start_key) = input.exclusive_start_key {
+            let start = (exclusive_start_key.sk, exclusive_start_key.pk);
+            matched.retain(|item| {
+                if input.scan_forward {
+                    index_key(item) > start
+                } else {
+                    index_key(item) < start
+                }
+            });
+        }
+
+        let mut output = paginate(matched, config.limit, &["pk", "sk", "gsi1pk"], |item| {
+            project_gsi1(item, &config.projected_fields)
+        })?;
+
+        // filter expressions are applied after the limit, like DynamoDB does
+        if let Some(pk_prefix) = input.pk_prefix {
+            output.items.retain(|item| {
+                string_attribute(item, "pk").is_some_and(|pk| pk.starts_with(&pk_prefix))
+            });
+        }
+
+        Ok(output)
+    }
+
+    async fn batch_get_item(
+        &self,
+        input: BatchGetItemInput,
+        config: BatchGetItemConfig,
+    ) -> Result<BatchGetItemOutput> {
+        let table = self.items.read().expect("memory table lock");
+
+        Ok(BatchGetItemOutput {
+            items: input
+                .keys
+                .into_iter()
+                .filter_map(|key| table.get(&(key.pk, key.sk)))
+                .map(|item| project(item, &config.projected_fields))
+                .collect(),
+            unprocessed_keys: vec![],
+        })
+    }
+
+    async fn transact_write_items(&self, items: Vec<TransactWriteItem>) -> Result<()> {
+        let mut table = self.items.write().expect("memory table lock");
+
+        let mut seen_keys = HashSet::new();
+        let mut staged = vec![];
+
+        for (index, item) in items.into_iter().enumerate() {
+            let key = transact_write_item_key(&item)?;
+            let item = prepare_transact_write_item(item, table.get(&key))
+                .map_err(|error| cancel_transaction(index, error))?;
+
+            if !seen_keys.insert(key.clone()) {
+                return Err(duplicate_transaction_item());
+            }
+
+            staged.push((key, item));
+        }
+
+        for (key, item) in staged {
+            match item {
+                Some(item) => table.insert(key, item),
+                None => table.remove(&key),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use aws_sdk_dynamodb::types::{AttributeValue, Delete, Put};
+
+    use crate::Error;
+    use crate::backend::ProjectedFields;
+
+    fn item(pairs: &[(&str, &str)]) -> Item {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), AttributeValue::S(value.to_string())))
+            .collect()
+    }
+
+    async fn backend_with_plays() -> MemoryBackend {
+        let backend = MemoryBackend::new("tablename".to_owned());
+
+        for (sk, gsi1pk) in [
+            ("PLAY#01", "TRACK#A#2001-02"),
+            ("PLAY#02", "TRACK#B#2001-02"),
+            ("PLAY#03", "TRACK#A#2001-02"),
+            ("PLAY#04", "TRACK#A#2001-02"),
+        ] {
+            backend
+                .put_item(item(&[
+                    ("pk", "STATION#1#PLAYS#2001-02-03"),
+                    ("sk", sk),
+                    ("gsi1pk", gsi1pk),
+                    ("id", sk.trim_start_matches("PLAY#")),
+                    ("track_id", "A"),
+                    ("created_ts", "2001-02-03T04:05:06Z"),
+                ]))
+                .await
+                .unwrap();
+        }
+
+        backend
+            .put_item(item(&[("pk", "STATION#1#TRACKS"), ("sk", "TRACK#A")]))
+            .await
+            .unwrap();
+
+        backend
+    }
+
+    fn sort_keys(output: &QueryOutput) -> Vec<&str> {
+        output
+            .items
+            .iter()
+            .map(|item| string_attribute(item, "sk").unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_query_range_paginates() {
+        let backend = backend_with_plays().await;
+
+        let input = |exclusive_start_key| QueryRangeInput {
+            pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+            start_sk: "PLAY#02".to_owned(),
+            end_sk: "PLAY#04".to_owned(),
+            scan_forward: true,
+            exclusive_start_key,
+        };
+        let config = || QueryConfig {
+            limit: 2,
+            projected_fields: ProjectedFields::All,
+        };
+
+        let first_page = backend.query_range(input(None), config()).await.unwrap();
+        assert_eq!(sort_keys(&first_page), ["PLAY#02", "PLAY#03"]);
+
+        let last_evaluated_key = first_page.last_evaluated_key.unwrap();
+        assert_eq!(
+            last_evaluated_key,
+            item(&[("pk", "STATION#1#PLAYS#2001-02-03"), ("sk", "PLAY#03")])
+        );
+
+        let second_page = backend
+            .query_range(
+                input(Some(Key {
+                    pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+                    sk: "PLAY#03".to_owned(),
+                })),
+                config(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(sort_keys(&second_page), ["PLAY#04"]);
+        assert!(second_page.last_evaluated_key.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_query_range_rejects_invalid_limit() {
+        let backend = backend_with_plays().await;
+
+        for limit in [0, -1] {
+            let result = backend
+                .query_range(
+                    QueryRangeInput {
+                        pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+                        start_sk: "PLAY#02".to_owned(),
+                        end_sk: "PLAY#04".to_owned(),
+                        scan_forward: true,
+                        exclusive_start_key: None,
+                    },
+                    QueryConfig {
+                        limit,
+                        projected_fields: ProjectedFields::All,
+                    },
+                )
+                .await;
+            assert!(matches!(result, Err(Error::Backend(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_range_backward_paginates() {
+        let backend = backend_with_plays().await;
+
+        let input = |exclusive_start_key| QueryRangeInput {
+            pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+            start_sk: "PLAY#02".to_owned(),
+            end_sk: "PLAY#04".to_owned(),
+            scan_forward: false,
+            exclusive_start_key,
+        };
+        let config = || QueryConfig {
+            limit: 2,
+            projected_fields: ProjectedFields::All,
+        };
+
+        let first_page = backend.query_range(input(None), config()).await.unwrap();
+        assert_eq!(sort_keys(&first_page), ["PLAY#04", "PLAY#03"]);
+
+        let second_page = backend
+            .query_range(
+                input(Some(Key {
+                    pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+                    sk: "PLAY#03".to_owned(),
+                })),
+                config(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(sort_keys(&second_page), ["PLAY#02"]);
+        assert!(second_page.last_evaluated_key.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_query_prefix_backward_with_projection() {
+        let backend = backend_with_plays().await;
+
+        let output = backend
+            .query_prefix(
+                QueryPrefixInput {
+                    pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+                    sk_prefix: "PLAY#".to_owned(),
+                    scan_forward: false,
+                    exclusive_start_key: None,
+                },
+                QueryConfig {
+                    limit: 10,
+                    projected_fields: ProjectedFields::Some(&["sk"]),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            sort_keys(&output),
+            ["PLAY#04", "PLAY#03", "PLAY#02", "PLAY#01"]
+        );
+        assert!(output.items.iter().all(|item| item.len() == 1));
+        assert!(output.last_evaluated_key.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_query_prefix_gsi1_projects_index_attributes() {
+        let backend = backend_with_plays().await;
+
+        let output = backend
+            .query_prefix_gsi1(
+                QueryPrefixGsi1Input {
+                    gsi1pk: "TRACK#A#2001-02".to_owned(),
+                    sk_prefix: "PLAY#".to_owned(),
+                    pk_prefix: Some("STATION#1".to_owned()),
+                    scan_forward: false,
+                    exclusive_start_key: None,
+                },
+                QueryConfig {
+                    limit: 2,
+                    projected_fields: ProjectedFields::All,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(sort_keys(&output), ["PLAY#04", "PLAY#03"]);
+        assert!(
+            output
+                .items
+                .iter()
+                .all(|item| !item.contains_key("created_ts"))
+        );
+        assert_eq!(
+            output.last_evaluated_key.unwrap(),
+            item(&[
+                ("pk", "STATION#1#PLAYS#2001-02-03"),
+                ("sk", "PLAY#03"),
+                ("gsi1pk", "TRACK#A#2001-02"),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transact_write_items_is_atomic() {
+        let backend = backend_with_plays().await;
+
+        let put = Put::builder()
+            .table_name("tablename")
+            .set_item(Some(item(&[("pk", "STATION#1#TRACKS"), ("sk", "TRACK#B")])))
+            .build()
+            .unwrap();
+
+        let failing_update = Update::builder()
+            .table_name("tablename")
+            .key("pk", AttributeValue::S("STATION#1#TRACKS".to_owned()))
+            .key("sk", AttributeValue::S("TRACK#A".to_owned()))
+            .update_expression("SET updated_ts = :ts")
+            .condition_expression("updated_ts = :station_locked_ts")
+            .set_expression_attribute_values(Some(HashMap::from([
+                (":ts".to_owned(), AttributeValue::S("1".to_owned())),
+                (
+                    ":station_locked_ts".to_owned(),
+                    AttributeValue::S("0".to_owned()),
+                ),
+            ])))
+            .build()
+            .unwrap();
+
+        let result = backend
+            .transact_write_items(vec![
+                TransactWriteItem::Put(put.clone()),
+                TransactWriteItem::Update(failing_update),
+            ])
+            .await;
+        assert!(matches!(result, Err(Error::ConditionFailed(_))));
+
+        let key = Key {
+            pk: "STATION#1#TRACKS".to_owned(),
+            sk: "TRACK#B".to_owned(),
+        };
+        let config = || GetItemConfig {
+            consistent_read: true,
+            projected_fields: ProjectedFields::All,
+        };
+
+        assert!(
+            backend
+                .get_item(key.clone(), config())
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        backend
+            .transact_write_items(vec![TransactWriteItem::Put(put)])
+            .await
+            .unwrap();
+
+        assert!(backend.get_item(key, config()).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_transact_write_items_delete() {
+        let backend = backend_with_plays().await;
+
+        let delete = |track_id: &str| {
+            Delete::builder()
+                .table_name("tablename")
+                .key(
+                    "pk",
+                    AttributeValue::S("STATION#1#PLAYS#2001-02-03".to_owned()),
+                )
+                .key("sk", AttributeValue::S("PLAY#01".to_owned()))
+                .condition_expression("gsi1pk = :gsi1pk")
+                .expression_attribute_values(":gsi1pk", AttributeValue::S(track_id.to_owned()))
+                .build()
+                .unwrap()
+        };
+
+        let key = Key {
+            pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+            sk: "PLAY#01".to_owned(),
+        };
+        let config = || GetItemConfig {
+            consistent_read: true,
+            projected_fields: ProjectedFields::All,
+        };
+
+        let result = backend
+            .transact_write_items(vec![TransactWriteItem::Delete(delete("TRACK#B#2001-02"))])
+            .await;
+        assert!(matches!(result, Err(Error::ConditionFailed(_))));
+        assert!(
+            backend
+                .get_item(key.clone(), config())
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        backend
+            .transact_write_items(vec![TransactWriteItem::Delete(delete("TRACK#A#2001-02"))])
+            .await
+            .unwrap();
+        assert!(backend.get_item(key, config()).await.unwrap().is_none());
+    }
+}
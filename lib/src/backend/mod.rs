@@ -0,0 +1,233 @@
+pub mod dynamodb;
+#[cfg(any(test, feature = "local", feature = "sqlite"))]
+pub(crate) mod emulated;
+#[cfg(any(test, feature = "local", feature = "sqlite"))]
+pub(crate) mod expression;
+#[cfg(any(test, feature = "local"))]
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(test)]
+pub(crate) mod testing;
+
+use std::collections::HashMap;
+use std::future::Future;
+
+use aws_sdk_dynamodb::types::{AttributeValue, Delete, Put, Update};
+use serde::Deserialize;
+
+use crate::Result;
+
+pub use dynamodb::DynamoDBBackend;
+#[cfg(any(test, feature = "local"))]
+pub use memory::MemoryBackend;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteBackend;
+
+/// A single item in the radiojournal table, keyed by attribute name.
+pub type Item = HashMap<String, AttributeValue>;
+
+#[derive(Clone, Copy)]
+pub enum ProjectedFields {
+    All,
+    Some(&'static [&'static str]),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub struct Key {
+    pub pk: String,
+    pub sk: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Gsi1Key {
+    pub gsi1pk: String,
+    pub sk: String,
+    pub pk: String,
+}
+
+pub struct GetItemConfig {
+    pub consistent_read: bool,
+    pub projected_fields: ProjectedFields,
+}
+
+pub struct QueryPrefixInput {
+    pub pk: String,
+    pub sk_prefix: String,
+    pub scan_forward: bool,
+    pub exclusive_start_key: Option<Key>,
+}
+
+pub struct QueryRangeInput {
+    pub pk: String,
+    pub start_sk: String,
+    pub end_sk: String,
+    pub scan_forward: bool,
+    pub exclusive_start_key: Option<Key>,
+}
+
+pub struct QueryPrefixGsi1Input {
+    pub gsi1pk: String,
+    pub sk_prefix: String,
+    pub pk_prefix: Option<String>,
+    pub scan_forward: bool,
+    pub exclusive_start_key: Option<Gsi1Key>,
+}
+
+pub struct QueryConfig {
+    pub limit: i32,
+    pub projected_fields: ProjectedFields,
+}
+
+pub struct QueryOutput {
+    pub items: Vec<Item>,
+    pub last_evaluated_key: Option<Item>,
+}
+
+/// Maximum number of keys DynamoDB accepts in a single BatchGetItem request.
+pub const BATCH_GET_ITEM_MAX_KEYS: usize = 100;
+
+pub struct BatchGetItemInput {
+    pub keys: Vec<Key>,
+}
+
+pub struct BatchGetItemOutput {
+    pub items: Vec<Item>,
+    /// Keys the backend did not get to, they should be requested again after a backoff.
+    pub unprocessed_keys: Vec<Key>,
+}
+
+pub struct BatchGetItemConfig {
+    pub projected_fields: ProjectedFields,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TransactWriteItem {
+    Put(Put),
+    Update(Update),
+    Delete(Delete),
+}
+
+/// Storage operations the CRUD layer needs from the single-table data model.
+///
+/// Items are laid out exactly like the DynamoDB table: every item has a `pk` and `sk`,
+/// and items with a `gsi1pk` attribute are also reachable through the `gsi1` index
+/// (`gsi1pk` + `sk`). Writes are expressed as DynamoDB `Put`/`Update`/`Delete` requests so that
+/// update and condition expressions carry the same meaning on every backend.
+pub trait StorageBackend: Send + Sync {
+    fn table_name(&self) -> &str;
+
+    fn get_item(
+        &self,
+        key: Key,
+        config: GetItemConfig,
+    ) -> impl Future<Output = Result<Option<Item>>> + Send;
+
+    fn put_item(&self, item: Item) -> impl Future<Output = Result<()>> + Send;
+
+    fn update_item(&self, update: Update) -> impl Future<Output = Result<()>> + Send;
+
+    fn query_prefix(
+        &self,
+        input: QueryPrefixInput,
+        config: QueryConfig,
+    ) -> impl Future<Output = Result<QueryOutput>> + Send;
+
+    fn query_range(
+        &self,
+        input: QueryRangeInput,
+        config: QueryConfig,
+    ) -> impl Future<Output = Result<QueryOutput>> + Send;
+
+    fn query_prefix_gsi1(
+        &self,
+        input: QueryPrefixGsi1Input,
+        config: QueryConfig,
+    ) -> impl Future<Output = Result<QueryOutput>> + Send;
+
+    fn batch_get_item(
+        &self,
+        input: BatchGetItemInput,
+        config: BatchGetItemConfig,
+    ) -> impl Future<Output = Result<BatchGetItemOutput>> + Send;
+
+    fn transact_write_items(
+        &self,
+        items: Vec<TransactWriteItem>,
+    ) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Storage backend chosen at runtime, see [`crate::init::initialize`].
+pub enum Backend {
+    DynamoDB(DynamoDBBackend),
+    #[cfg(feature = "local")]
+    Memory(MemoryBackend),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqliteBackend),
+}
+
+macro_rules! dispatch {
+    ($self:ident, $backend:ident => $call:expr) => {
+        match $self {
+            Backend::DynamoDB($backend) => $call,
+            #[cfg(feature = "local")]
+            Backend::Memory($backend) => $call,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite($backend) => $call,
+        }
+    };
+}
+
+impl StorageBackend for Backend {
+    fn table_name(&self) -> &str {
+        dispatch!(self, backend => backend.table_name())
+    }
+
+    async fn get_item(&self, key: Key, config: GetItemConfig) -> Result<Option<Item>> {
+        dispatch!(self, backend => backend.get_item(key, config).await)
+    }
+
+    async fn put_item(&self, item: Item) -> Result<()> {
+        dispatch!(self, backend => backend.put_item(item).await)
+    }
+
+    async fn update_item(&self, update: Update) -> Result<()> {
+        dispatch!(self, backend => backend.update_item(update).await)
+    }
+
+    async fn query_prefix(
+        &self,
+        input: QueryPrefixInput,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        dispatch!(self, backend => backend.query_prefix(input, config).await)
+    }
+
+    async fn query_range(
+        &self,
+        input: QueryRangeInput,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        dispatch!(self, backend => backend.query_range(input, config).await)
+    }
+
+    async fn query_prefix_gsi1(
+        &self,
+        input: QueryPrefixGsi1Input,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        dispatch!(self, backend => backend.query_prefix_gsi1(input, config).await)
+    }
+
+    async fn batch_get_item(
+        &self,
+        input: BatchGetItemInput,
+        config: BatchGetItemConfig,
+    ) -> Result<BatchGetItemOutput> {
+        dispatch!(self, backend => backend.batch_get_item(input, config).await)
+    }
+
+    async fn transact_write_items(&self, items: Vec<TransactWriteItem>) -> Result<()> {
+        dispatch!(self, backend => backend.transact_write_items(items).await)
+    }
+}
@@ -0,0 +1,626 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aws_sdk_dynamodb::types::{AttributeValue, Update};
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params, params_from_iter};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::emulated::{
+    TableKey, cancel_transaction, duplicate_transaction_item, paginate,
+    prepare_transact_write_item, prepare_update, project, project_gsi1, query_limit,
+    string_attribute, table_key, transact_write_item_key,
+};
+use crate::backend::{
+    BatchGetItemConfig, BatchGetItemInput, BatchGetItemOutput, GetItemConfig, Item, Key,
+    QueryConfig, QueryOutput, QueryPrefixGsi1Input, QueryPrefixInput, QueryRangeInput,
+    StorageBackend, TransactWriteItem,
+};
+use crate::{Error, Result};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS items (
+    pk TEXT NOT NULL,
+    sk TEXT NOT NULL,
+    gsi1pk TEXT,
+    item TEXT NOT NULL,
+    PRIMARY KEY (pk, sk)
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS items_gsi1 ON items (gsi1pk, sk, pk) WHERE gsi1pk IS NOT NULL;
+";
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Implementation of the radiojournal table on a SQLite database, for self-hosted deployments.
+///
+/// Every item is stored as one row keyed by `pk` and `sk`, with `gsi1pk` broken out so the
+/// `gsi1` index can be served from a SQLite index. Writes run inside SQLite transactions,
+/// so condition checks and transactional writes are atomic like they are on DynamoDB.
+pub struct SqliteBackend {
+    table_name: String,
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteBackend {
+    pub fn open(path: impl AsRef<Path>, table_name: String) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+
+        Self::with_connection(connection, table_name)
+    }
+
+    pub fn open_in_memory(table_name: String) -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?, table_name)
+    }
+
+    fn with_connection(connection: Connection, table_name: String) -> Result<Self> {
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(Self {
+            table_name,
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Removes every item, leaving an empty table.
+    pub async fn clear(&self) -> Result<()> {
+        self.run(|connection| {
+            connection.execute("DELETE FROM items", [])?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Runs blocking SQLite work on the blocking thread pool.
+    async fn run<T: Send + 'static>(
+        &self,
+        work: impl FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let connection = Arc::clone(&self.connection);
+
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().expect("sqlite connection lock");
+            work(&mut connection)
+        })
+        .await
+        .map_err(Error::backend)?
+    }
+
+    async fn query_partition(
+        &self,
+        pk: String,
+        sk_condition: &'static str,
+        sk_params: Vec<String>,
+        scan_forward: bool,
+        exclusive_start_key: Option<Key>,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        let (order, after) = if scan_forward {
+            ("ASC", ">")
+        } else {
+            ("DESC", "<")
+        };
+
+        let mut sql = format!("SELECT item FROM items WHERE pk = ? AND {sk_condition}");
+        let mut sql_params = vec![pk];
+        sql_params.extend(sk_params);
+
+        if let Some(exclusive_start_key) = exclusive_start_key {
+            sql.push_str(&format!(" AND sk {after} ?"));
+            sql_params.push(exclusive_start_key.sk);
+        }
+
+        sql.push_str(&format!(
+            " ORDER BY sk {order} LIMIT {}",
+            query_limit(config.limit)?
+        ));
+
+        let items = self
+            .run(move |connection| select_items(connection, &sql, sql_params))
+            .await?;
+
+        paginate(
+            items.iter().collect(),
+            config.limit,
+            &["pk", "sk"],
+            |item| project(item, &config.projected_fields),
+        )
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    async fn get_item(&self, key: Key, config: GetItemConfig) -> Result<Option<Item>> {
+        let item = self
+            .run(move |connection| select_item(connection, &(key.pk, key.sk)))
+            .await?;
+
+        Ok(item.map(|item| project(&item, &config.projected_fields)))
+    }
+
+    async fn put_item(&self, item: Item) -> Result<()> {
+        let key = table_key(&item)?;
+
+        self.run(move |connection| write_item(connection, &key, &item))
+            .await
+    }
+
+    async fn update_item(&self, update: Update) -> Result<()> {
+        let key = table_key(&update.key)?;
+
+        self.run(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+            let existing = select_item(&transaction, &key)?;
+            let item = prepare_update(update, existing.as_ref())?;
+            write_item(&transaction, &key, &item)?;
+
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn query_prefix(
+        &self,
+        input: QueryPrefixInput,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        self.query_partition(
+            input.pk,
+            "substr(sk, 1, length(?)) = ?",
+            vec![input.sk_prefix.clone(), input.sk_prefix],
+            input.scan_forward,
+            input.exclusive_start_key,
+            config,
+        )
+        .await
+    }
+
+    async fn query_range(
+        &self,
+        input: QueryRangeInput,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        self.query_partition(
+            input.pk,
+            "sk BETWEEN ? AND ?",
+            vec![input.start_sk, input.end_sk],
+            input.scan_forward,
+            input.exclusive_start_key,
+            config,
+        )
+        .await
+    }
+
+    async fn query_prefix_gsi1(
+        &self,
+        input: QueryPrefixGsi1Input,
+        config: QueryConfig,
+    ) -> Result<QueryOutput> {
+        let (order, after) = if input.scan_forward {
+            ("ASC", ">")
+        } else {
+            ("DESC", "<")
+        };
+
+        let mut sql =
+            "SELECT item FROM items WHERE gsi1pk = ? AND substr(sk, 1, length(?)) = ?".to_owned();
+        let mut sql_params = vec![input.gsi1pk, input.sk_prefix.clone(), input.sk_prefix];
+
+        if let Some(exclusive_start_key) = input.exclusive_start_key {
+            sql.push_str(&format!(" AND (sk, pk) {after} (?, ?)"));
+            sql_params.extend([exclusive_start_key.sk, exclusive_start_key.pk]);
+        }
+
+        sql.push_str(&format!(
+            " ORDER BY sk {order}, pk {order} LIMIT {}",
+            query_limit(config.limit)?
+        ));
+
+        let items = self
+            .run(move |connection| select_items(connection, &sql, sql_params))
+            .await?;
+
+        let mut output = paginate(
+            items.iter().collect(),
+            config.limit,
+            &["pk", "sk", "gsi1pk"],
+            |item| project_gsi1(item, &config.projected_fields),
+        )?;
+
+        // filter expressions are applied after the limit, like DynamoDB does
+        if let Some(pk_prefix) = input.pk_prefix {
+            output.items.retain(|item| {
+                string_attribute(item, "pk").is_some_and(|pk| pk.starts_with(&pk_prefix))
+            });
+        }
+
+        Ok(output)
+    }
+
+    async fn batch_get_item(
+        &self,
+        input: BatchGetItemInput,
+        config: BatchGetItemConfig,
+    ) -> Result<BatchGetItemOutput> {
+        let items = self
+            .run(move |connection| {
+                let mut items = vec![];
+                for key in input.keys {
+                    if let Some(item) = select_item(connection, &(key.pk, key.sk))? {
+                        items.push(item);
+                    }
+                }
+
+                Ok(items)
+            })
+            .await?;
+
+        Ok(BatchGetItemOutput {
+            items: items
+                .iter()
+                .map(|item| project(item, &config.projected_fields))
+                .collect(),
+            unprocessed_keys: vec![],
+        })
+    }
+
+    async fn transact_write_items(&self, items: Vec<TransactWriteItem>) -> Result<()> {
+        self.run(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+            let mut seen_keys = HashSet::new();
+
+            for (index, item) in items.into_iter().enumerate() {
+                let key = transact_write_item_key(&item)?;
+
+                let existing = select_item(&transaction, &key)?;
+                let item = prepare_transact_write_item(item, existing.as_ref())
+                    .map_err(|error| cancel_transaction(index, error))?;
+
+                if !seen_keys.insert(key.clone()) {
+                    return Err(duplicate_transaction_item());
+                }
+
+                match item {
+                    Some(item) => write_item(&transaction, &key, &item)?,
+                    None => delete_item(&transaction, &key)?,
+                }
+            }
+
+            // dropping the transaction without committing rolls back every staged write
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn select_item(connection: &Connection, key: &TableKey) -> Result<Option<Item>> {
+    let item: Option<String> = connection
+        .query_row(
+            "SELECT item FROM items WHERE pk = ?1 AND sk = ?2",
+            params![key.0, key.1],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    item.as_deref().map(decode_item).transpose()
+}
+
+fn select_items(connection: &Connection, sql: &str, sql_params: Vec<String>) -> Result<Vec<Item>> {
+    let mut statement = connection.prepare(sql)?;
+    let rows = statement.query_map(params_from_iter(sql_params), |row| row.get::<_, String>(0))?;
+
+    rows.map(|item| decode_item(&item?)).collect()
+}
+
+fn delete_item(connection: &Connection, key: &TableKey) -> Result<()> {
+    connection.execute(
+        "DELETE FROM items WHERE pk = ?1 AND sk = ?2",
+        params![key.0, key.1],
+    )?;
+
+    Ok(())
+}
+
+fn write_item(connection: &Connection, key: &TableKey, item: &Item) -> Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO items (pk, sk, gsi1pk, item) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            key.0,
+            key.1,
+            string_attribute(item, "gsi1pk"),
+            encode_item(item)?
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Attribute values as stored in the `item` column, using the DynamoDB JSON format.
+#[derive(Serialize, Deserialize)]
+enum StoredValue {
+    S(String),
+    N(String),
+    #[serde(rename = "BOOL")]
+    Bool(bool),
+    #[serde(rename = "NULL")]
+    Null(bool),
+    #[serde(rename = "SS")]
+    Ss(Vec<String>),
+    #[serde(rename = "NS")]
+    Ns(Vec<String>),
+    L(Vec<StoredValue>),
+    M(HashMap<String, StoredValue>),
+}
+
+impl TryFrom<&AttributeValue> for StoredValue {
+    type Error = Error;
+
+    fn try_from(value: &AttributeValue) -> Result<Self> {
+        Ok(match value {
+            AttributeValue::S(value) => Self::S(value.clone()),
+            AttributeValue::N(value) => Self::N(value.clone()),
+            AttributeValue::Bool(value) => Self::Bool(*value),
+            AttributeValue::Null(value) => Self::Null(*value),
+            AttributeValue::Ss(values) => Self::Ss(values.clone()),
+            AttributeValue::Ns(values) => Self::Ns(values.clone()),
+            AttributeValue::L(values) => {
+                Self::L(values.iter().map(Self::try_from).collect::<Result<_>>()?)
+            }
+            AttributeValue::M(values) => Self::M(encode_map(values)?),
+            other => {
+                return Err(Error::Serialization(
+                    format!("unsupported attribute value for sqlite: {other:?}").into(),
+                ));
+            }
+        })
+    }
+}
+
+impl From<StoredValue> for AttributeValue {
+    fn from(value: StoredValue) -> Self {
+        match value {
+            StoredValue::S(value) => Self::S(value),
+            StoredValue::N(value) => Self::N(value),
+            StoredValue::Bool(value) => Self::Bool(value),
+            StoredValue::Null(value) => Self::Null(value),
+            StoredValue::Ss(values) => Self::Ss(values),
+            StoredValue::Ns(values) => Self::Ns(values),
+            StoredValue::L(values) => Self::L(values.into_iter().map(Self::from).collect()),
+            StoredValue::M(values) => Self::M(decode_map(values)),
+        }
+    }
+}
+
+fn encode_map(item: &Item) -> Result<HashMap<String, StoredValue>> {
+    item.iter()
+        .map(|(name, value)| Ok((name.clone(), StoredValue::try_from(value)?)))
+        .collect()
+}
+
+fn decode_map(values: HashMap<String, StoredValue>) -> Item {
+    values
+        .into_iter()
+        .map(|(name, value)| (name, value.into()))
+        .collect()
+}
+
+fn encode_item(item: &Item) -> Result<String> {
+    serde_json::to_string(&encode_map(item)?).map_err(|error| Error::Serialization(error.into()))
+}
+
+fn decode_item(item: &str) -> Result<Item> {
+    let values = serde_json::from_str(item).map_err(|error| Error::Serialization(error.into()))?;
+    Ok(decode_map(values))
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        match error.sqlite_error_code() {
+            // another connection held the write lock for longer than the busy timeout
+            Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+                Self::Throttled(Box::new(error))
+            }
+            _ => Self::backend(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use aws_sdk_dynamodb::types::Put;
+
+    use crate::backend::{Gsi1Key, ProjectedFields};
+
+    fn item(pairs: &[(&str, &str)]) -> Item {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), AttributeValue::S(value.to_string())))
+            .collect()
+    }
+
+    async fn backend_with_plays() -> SqliteBackend {
+        let backend = SqliteBackend::open_in_memory("tablename".to_owned()).unwrap();
+
+        for (sk, gsi1pk) in [
+            ("PLAY#01", "TRACK#A#2001-02"),
+            ("PLAY#02", "TRACK#B#2001-02"),
+            ("PLAY#03", "TRACK#A#2001-02"),
+            ("PLAY#04", "TRACK#A#2001-02"),
+        ] {
+            backend
+                .put_item(item(&[
+                    ("pk", "STATION#1#PLAYS#2001-02-03"),
+                    ("sk", sk),
+                    ("gsi1pk", gsi1pk),
+                    ("id", sk.trim_start_matches("PLAY#")),
+                    ("track_id", "A"),
+                    ("created_ts", "2001-02-03T04:05:06Z"),
+                ]))
+                .await
+                .unwrap();
+        }
+
+        backend
+    }
+
+    fn sort_keys(output: &QueryOutput) -> Vec<&str> {
+        output
+            .items
+            .iter()
+            .map(|item| string_attribute(item, "sk").unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_item_roundtrip() {
+        let item = Item::from([
+            ("pk".to_owned(), AttributeValue::S("STATIONS".to_owned())),
+            ("play_count".to_owned(), AttributeValue::N("3".to_owned())),
+            ("is_song".to_owned(), AttributeValue::Bool(true)),
+            ("latest_play".to_owned(), AttributeValue::Null(true)),
+            (
+                "tags".to_owned(),
+                AttributeValue::L(vec![AttributeValue::M(Item::from([(
+                    "name".to_owned(),
+                    AttributeValue::Ss(vec!["pop".to_owned()]),
+                )]))]),
+            ),
+        ]);
+
+        assert_eq!(decode_item(&encode_item(&item).unwrap()).unwrap(), item);
+    }
+
+    #[tokio::test]
+    async fn test_query_prefix_paginates_backward() {
+        let backend = backend_with_plays().await;
+
+        let input = |exclusive_start_key| QueryPrefixInput {
+            pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+            sk_prefix: "PLAY#".to_owned(),
+            scan_forward: false,
+            exclusive_start_key,
+        };
+        let config = || QueryConfig {
+            limit: 3,
+            projected_fields: ProjectedFields::All,
+        };
+
+        let first_page = backend.query_prefix(input(None), config()).await.unwrap();
+        assert_eq!(sort_keys(&first_page), ["PLAY#04", "PLAY#03", "PLAY#02"]);
+        assert_eq!(
+            first_page.last_evaluated_key.unwrap(),
+            item(&[("pk", "STATION#1#PLAYS#2001-02-03"), ("sk", "PLAY#02")])
+        );
+
+        let second_page = backend
+            .query_prefix(
+                input(Some(Key {
+                    pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+                    sk: "PLAY#02".to_owned(),
+                })),
+                config(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(sort_keys(&second_page), ["PLAY#01"]);
+        assert!(second_page.last_evaluated_key.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_query_prefix_gsi1_continues_from_key() {
+        let backend = backend_with_plays().await;
+
+        let output = backend
+            .query_prefix_gsi1(
+                QueryPrefixGsi1Input {
+                    gsi1pk: "TRACK#A#2001-02".to_owned(),
+                    sk_prefix: "PLAY#".to_owned(),
+                    pk_prefix: Some("STATION#1".to_owned()),
+                    scan_forward: false,
+                    exclusive_start_key: Some(Gsi1Key {
+                        gsi1pk: "TRACK#A#2001-02".to_owned(),
+                        sk: "PLAY#04".to_owned(),
+                        pk: "STATION#1#PLAYS#2001-02-03".to_owned(),
+                    }),
+                },
+                QueryConfig {
+                    limit: 5,
+                    projected_fields: ProjectedFields::All,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(sort_keys(&output), ["PLAY#03", "PLAY#01"]);
+        assert!(
+            output
+                .items
+                .iter()
+                .all(|item| !item.contains_key("created_ts"))
+        );
+        assert!(output.last_evaluated_key.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_transact_write_items_is_atomic() {
+        let backend = backend_with_plays().await;
+
+        let put = Put::builder()
+            .table_name("tablename")
+            .set_item(Some(item(&[("pk", "STATION#1#TRACKS"), ("sk", "TRACK#B")])))
+            .build()
+            .unwrap();
+
+        let failing_update = Update::builder()
+            .table_name("tablename")
+            .key("pk", AttributeValue::S("STATION#1#TRACKS".to_owned()))
+            .key("sk", AttributeValue::S("TRACK#A".to_owned()))
+            .update_expression("SET updated_ts = :ts")
+            .condition_expression("attribute_exists(pk)")
+            .set_expression_attribute_values(Some(HashMap::from([(
+                ":ts".to_owned(),
+                AttributeValue::S("1".to_owned()),
+            )])))
+            .build()
+            .unwrap();
+
+        let result = backend
+            .transact_write_items(vec![
+                TransactWriteItem::Put(put),
+                TransactWriteItem::Update(failing_update),
+            ])
+            .await;
+        assert!(matches!(result, Err(Error::ConditionFailed(_))));
+
+        assert!(
+            backend
+                .get_item(
+                    Key {
+                        pk: "STATION#1#TRACKS".to_owned(),
+                        sk: "TRACK#B".to_owned(),
+                    },
+                    GetItemConfig {
+                        consistent_read: true,
+                        projected_fields: ProjectedFields::All,
+                    },
+                )
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
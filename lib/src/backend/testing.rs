@@ -0,0 +1,110 @@
+//! Fixtures shared by the CRUD tests, which run against the in-memory backend.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::backend::MemoryBackend;
+use crate::clock::Clock;
+use crate::crud::Context;
+use crate::crud::logger::models::Play;
+use crate::crud::play::models::PlayId;
+use crate::crud::station::models::StationId;
+use crate::crud::track::CRUDTrack;
+use crate::crud::track::models::TrackId;
+
+pub(crate) fn memory_context() -> Arc<Context<MemoryBackend>> {
+    Arc::new(Context::with_backend(MemoryBackend::new(
+        "tablename".to_owned(),
+    )))
+}
+
+/// Lists every play of a track newest first, following next tokens across empty months.
+pub(crate) async fn list_all_plays_of_track(
+    crud_track: &CRUDTrack<MemoryBackend>,
+    station_id: StationId,
+    track_id: TrackId,
+) -> Vec<PlayId> {
+    let mut play_ids = vec![];
+    let mut next_token = None;
+
+    loop {
+        let (plays, next) = crud_track
+            .list_plays_of_track(station_id, track_id, 10, next_token.as_deref())
+            .await
+            .unwrap();
+        play_ids.extend(plays.into_iter().map(|play| PlayId(play.id)));
+
+        match next {
+            Some(next) => next_token = Some(next),
+            None => return play_ids,
+        }
+    }
+}
+
+/// Returns the same time on every call.
+pub(crate) struct FixedClock(pub(crate) DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// A song as reported by a fetcher, details are only reported if set.
+#[derive(Debug, Clone)]
+pub(crate) struct TestPlay {
+    pub(crate) artist: &'static str,
+    pub(crate) title: &'static str,
+    pub(crate) album: Option<&'static str>,
+    pub(crate) isrc: Option<&'static str>,
+    pub(crate) started_at: Option<DateTime<Utc>>,
+}
+
+impl TestPlay {
+    pub(crate) fn new(artist: &'static str, title: &'static str) -> Self {
+        Self {
+            artist,
+            title,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for TestPlay {
+    fn default() -> Self {
+        Self {
+            artist: "artist",
+            title: "title",
+            album: None,
+            isrc: None,
+            started_at: None,
+        }
+    }
+}
+
+impl Play for TestPlay {
+    fn get_title(&self) -> &str {
+        self.title
+    }
+
+    fn get_artist(&self) -> &str {
+        self.artist
+    }
+
+    fn is_song(&self) -> bool {
+        true
+    }
+
+    fn get_started_at(&self) -> Option<DateTime<Utc>> {
+        self.started_at
+    }
+
+    fn get_album(&self) -> Option<&str> {
+        self.album
+    }
+
+    fn get_isrc(&self) -> Option<&str> {
+        self.isrc
+    }
+}
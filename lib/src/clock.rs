@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use ulid::Ulid;
+
+/// Source of the current time for timestamps written by the CRUD layer.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Reads the system time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Source of item IDs. Play, track and station IDs are ULIDs whose timestamp part is used
+/// to locate partitions, so the ID must be generated for the time the item belongs to.
+pub trait IdGenerator: Send + Sync {
+    fn generate(&self, datetime: DateTime<Utc>) -> Ulid;
+}
+
+/// Generates ULIDs with a random part.
+pub struct UlidGenerator;
+
+impl IdGenerator for UlidGenerator {
+    fn generate(&self, datetime: DateTime<Utc>) -> Ulid {
+        Ulid::from_datetime(datetime.into())
+    }
+}
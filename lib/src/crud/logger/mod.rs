@@ -3,32 +3,42 @@ mod provider;
 
 use std::sync::Arc;
 
-use anyhow::Result;
-use chrono::{DateTime, Utc};
-use thiserror::Error;
+use chrono::{DateTime, Duration, Utc};
 
+use tracing::warn;
+
+use crate::backend::{Backend, StorageBackend, TransactWriteItem};
 use crate::crud::Context;
 use crate::crud::play::models::{PlayId, PlayInDB};
+use crate::crud::station::CRUDStation;
 use crate::crud::station::models::{LatestPlay, StationId, StationInDB};
 use crate::crud::track::CRUDTrack;
-use crate::crud::track::models::{TrackId, TrackInDB, TrackMetadataCreateInDB};
+use crate::crud::track::models::{TrackDetails, TrackId, TrackInDB, TrackMetadataCreateInDB};
 use crate::helpers::ziso_timestamp;
+use crate::{Error, Result};
 use models::{AddPlayMetadata, AddPlayResult, AddPlayTypeInternal, Play};
 use provider::{
-    BuildStationUpdateInput, BuildTrackUpdateInput, DynamoDBProvider, StationUpdateIncrementType,
-    TransactWriteItem, UpdatePlayInput, build_put, build_station_update, build_track_update,
+    BuildPlayUpdateInput, BuildStationPlayRetractedUpdateInput, BuildStationUpdateInput,
+    BuildTrackUpdateInput, FirstPlayUpdate, StationUpdateIncrementType, build_play_update,
+    build_put, build_station_play_retracted_update, build_station_update, build_track_update,
 };
 
-pub struct CRUDLogger {
-    provider: DynamoDBProvider,
-    crud_track: CRUDTrack,
+/// How many times `add_play` re-reads the station and tries again after losing the
+/// station optimistic lock to a concurrent writer.
+const ADD_PLAY_MAX_RETRIES: u32 = 3;
+
+pub struct CRUDLogger<B: StorageBackend = Backend> {
+    context: Arc<Context<B>>,
+    crud_station: CRUDStation<B>,
+    crud_track: CRUDTrack<B>,
 }
 
-impl CRUDLogger {
-    pub fn new(context: Arc<Context>) -> Self {
+impl<B: StorageBackend> CRUDLogger<B> {
+    pub fn new(context: Arc<Context<B>>) -> Self {
         Self {
+            crud_station: CRUDStation::new(context.clone()),
             crud_track: CRUDTrack::new(context.clone()),
-            provider: DynamoDBProvider::new(context.clone()),
+            context,
         }
     }
 
@@ -37,10 +47,105 @@ impl CRUDLogger {
         station: &mut StationInDB,
         play: impl Play,
     ) -> Result<AddPlayResult> {
+        let now = self.context.clock.now();
+
+        // prefer the start time reported by the source, unless it is ahead of our clock. A
+        // live play cannot start before the latest play, so it stays the newest play.
+        let played_at = match play.get_started_at() {
+            Some(started_at) if started_at <= now => match &station.latest_play {
+                Some(latest_play) => {
+                    let latest_played_at: DateTime<Utc> = latest_play.id.datetime().into();
+                    started_at
+                        .max(latest_played_at + Duration::milliseconds(1))
+                        .min(now)
+                }
+                None => started_at,
+            },
+            _ => now,
+        };
+
+        self.add_play_internal(station, play, played_at, PlayMode::Live)
+            .await
+    }
+
+    /// Adds a play that was played at `played_at` instead of now, the play is stored in the
+    /// day and month partitions of `played_at`. It becomes the latest play of the station
+    /// unless it is older than that, in which case it is inserted like
+    /// [`Self::insert_historical_play`] and the latest play stays in place. It also becomes
+    /// the first play of the station if it is earlier than that.
+    pub async fn add_play_at(
+        &self,
+        station: &mut StationInDB,
+        play: impl Play,
+        played_at: DateTime<Utc>,
+    ) -> Result<AddPlayResult> {
+        self.add_play_internal(station, play, played_at, PlayMode::At)
+            .await
+    }
+
+    /// Inserts a play from the past, e.g. from the playlist of the station for a window the
+    /// logger missed. The play is matched to tracks like a live play, but it never replaces
+    /// the station latest play with an older play, and it only becomes the first play of the
+    /// station or the latest play of its track if it is earlier or newer respectively.
+    pub async fn insert_historical_play(
+        &self,
+        station: &mut StationInDB,
+        play: impl Play,
+        played_at: DateTime<Utc>,
+    ) -> Result<AddPlayResult> {
+        self.add_play_internal(station, play, played_at, PlayMode::Historical)
+            .await
+    }
+
+    async fn add_play_internal(
+        &self,
+        station: &mut StationInDB,
+        play: impl Play,
+        played_at: DateTime<Utc>,
+        mode: PlayMode,
+    ) -> Result<AddPlayResult> {
+        let mut retries = 0;
+
+        loop {
+            match self
+                .try_add_play(station, &play, played_at, mode, retries)
+                .await
+            {
+                Err(Error::ConditionFailed(reason)) if retries < ADD_PLAY_MAX_RETRIES => {
+                    warn!(
+                        station_id = station.id.to_string(),
+                        reason, "Station was modified concurrently, retrying add play"
+                    );
+
+                    // the station changed under us, evaluate the play against its latest state
+                    *station = self
+                        .crud_station
+                        .get_station_consistent(station.id)
+                        .await?
+                        .ok_or(Error::NotFound)?;
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_add_play(
+        &self,
+        station: &mut StationInDB,
+        play: &impl Play,
+        played_at: DateTime<Utc>,
+        mode: PlayMode,
+        retries: u32,
+    ) -> Result<AddPlayResult> {
+        let mode = mode.resolve(station, played_at);
         let artist = play.get_artist();
         let title = play.get_title();
 
-        let add_type = self.evaluate_play_metadata(station, artist, title).await?;
+        let add_type = self
+            .evaluate_play_metadata(station, artist, title, played_at, mode)
+            .await?;
+        let mut retracted_play_id = None;
         let (result_track_id, result_play_id) = match &add_type {
             AddPlayTypeInternal::ExistingPlay { track_id, play_id } => {
                 // all metadata matched, update play updated_ts only
@@ -49,26 +154,106 @@ impl CRUDLogger {
 
                 (*track_id, *play_id)
             }
-            AddPlayTypeInternal::NewPlay { track_id } => {
-                // insert new play with existing track
-                let play = PlayInDB::new(station.id, *track_id);
-                let play_id = play.id;
+            AddPlayTypeInternal::ResumedPlay {
+                track_id,
+                play_id,
+                retracted,
+            } => {
+                // the latest play was flicker, take it back and carry on with the one before
+                self.add_play_with_resumed_play(station, retracted).await?;
+                retracted_play_id = Some(retracted.id);
 
-                // use the metadata from fetcher to populate latest_play
-                self.add_play_with_new_play(station, play, artist, title)
-                    .await?;
+                (*track_id, *play_id)
+            }
+            AddPlayTypeInternal::NewPlay {
+                track_id,
+                add_normalized_metadata,
+            } => {
+                // insert new play with existing track
+                let new_play = PlayInDB::new(
+                    station.id,
+                    *track_id,
+                    played_at,
+                    self.context.id_generator.as_ref(),
+                );
+                let play_id = new_play.id;
+
+                let track_metadata = add_normalized_metadata.then(|| {
+                    TrackMetadataCreateInDB::new(
+                        station.id,
+                        &station.normalization,
+                        artist,
+                        title,
+                        *track_id,
+                    )
+                });
+
+                // a historical play only replaces the latest play of the track if it is newer,
+                // and a play older than the track moves the earliest play of the track back
+                let track_creation: DateTime<Utc> = track_id.datetime().into();
+                let track = if mode == PlayMode::Historical || played_at < track_creation {
+                    Some(
+                        self.crud_track
+                            .get_track_internal(station.id, *track_id, true)
+                            .await?
+                            .ok_or(Error::NotFound)?,
+                    )
+                } else {
+                    None
+                };
+
+                // use the metadata from fetcher to populate latest_play, and fill in any track
+                // details the track is still missing
+                let latest_play = LatestPlay {
+                    id: play_id,
+                    track_id: *track_id,
+                    artist: artist.to_owned(),
+                    title: title.to_owned(),
+                };
+                let placement = PlayPlacement::new(station, latest_play, mode, track);
+
+                self.add_play_with_new_play(
+                    station,
+                    new_play,
+                    track_metadata,
+                    play.into(),
+                    placement,
+                )
+                .await?;
 
                 (*track_id, play_id)
             }
             AddPlayTypeInternal::NewTrack => {
                 // insert new track and play
-                let track = TrackInDB::new(station.id, artist, title, play.is_song());
-                let play = PlayInDB::new(station.id, track.id);
+                let track = TrackInDB::new(
+                    station.id,
+                    artist,
+                    title,
+                    play.is_song(),
+                    played_at,
+                    self.context.id_generator.as_ref(),
+                )
+                .with_details(play.into());
+                let play = PlayInDB::new(
+                    station.id,
+                    track.id,
+                    played_at,
+                    self.context.id_generator.as_ref(),
+                );
 
                 let track_id = track.id;
                 let play_id = play.id;
 
-                self.add_play_with_new_track(station, track, play).await?;
+                let latest_play = LatestPlay {
+                    id: play_id,
+                    track_id,
+                    artist: track.artist.clone(),
+                    title: track.title.clone(),
+                };
+                let placement = PlayPlacement::new(station, latest_play, mode, None);
+
+                self.add_play_with_new_track(station, track, play, placement)
+                    .await?;
 
                 (track_id, play_id)
             }
@@ -82,6 +267,8 @@ impl CRUDLogger {
                 title: title.to_owned(),
                 artist: artist.to_owned(),
             },
+            retracted_play_id,
+            retries,
         })
     }
 
@@ -90,10 +277,19 @@ impl CRUDLogger {
         station: &mut StationInDB,
         artist: &str,
         title: &str,
+        played_at: DateTime<Utc>,
+        mode: PlayMode,
     ) -> Result<AddPlayTypeInternal> {
-        if let Some(latest_play) = &station.latest_play
-            && latest_play.artist == artist
-            && latest_play.title == title
+        let rules = &station.normalization;
+        let is_same_metadata = |play: &LatestPlay| {
+            rules.normalize_artist(&play.artist) == rules.normalize_artist(artist)
+                && rules.normalize_title(&play.title) == rules.normalize_title(title)
+        };
+
+        // a historical play is never a continuation of the plays logged live
+        if mode == PlayMode::Live
+            && let Some(latest_play) = &station.latest_play
+            && is_same_metadata(latest_play)
         {
             return Ok(AddPlayTypeInternal::ExistingPlay {
                 track_id: latest_play.track_id,
@@ -101,13 +297,31 @@ impl CRUDLogger {
             });
         }
 
-        if let Some(track_metadata) = self
+        if mode == PlayMode::Live
+            && let Some(flap_window_seconds) = station.flap_window_seconds
+            && let Some(latest_play) = &station.latest_play
+            && let Some(previous_play) = &station.previous_play
+            && is_same_metadata(previous_play)
+        {
+            let latest_played_at: DateTime<Utc> = latest_play.id.datetime().into();
+
+            if played_at - latest_played_at < Duration::seconds(flap_window_seconds.into()) {
+                return Ok(AddPlayTypeInternal::ResumedPlay {
+                    track_id: previous_play.track_id,
+                    play_id: previous_play.id,
+                    retracted: latest_play.clone(),
+                });
+            }
+        }
+
+        if let Some((track_metadata, verbatim_only)) = self
             .crud_track
-            .get_track_by_metadata(station, artist, title)
+            .find_track_by_metadata(station, artist, title)
             .await?
         {
             Ok(AddPlayTypeInternal::NewPlay {
                 track_id: track_metadata.track_id,
+                add_normalized_metadata: verbatim_only,
             })
         } else {
             Ok(AddPlayTypeInternal::NewTrack)
@@ -122,15 +336,74 @@ impl CRUDLogger {
     ) -> Result<()> {
         let play_datetime: DateTime<Utc> = play_id.datetime().into();
 
-        self.provider
-            .update_play(UpdatePlayInput {
+        let play_update = build_play_update(
+            self.context.backend.table_name(),
+            BuildPlayUpdateInput {
                 pk: PlayInDB::get_pk(station_id, &play_datetime),
                 sk: PlayInDB::get_sk(play_id),
                 play_id: play_id.to_string(),
                 track_id: track_id.to_string(),
-                update_timestamp: ziso_timestamp(&Utc::now()),
-            })
+                update_timestamp: ziso_timestamp(&self.context.clock.now()),
+            },
+        )?;
+
+        self.context.backend.update_item(play_update).await?;
+
+        Ok(())
+    }
+
+    /// Deletes the retracted latest play and makes the play before it the latest play again,
+    /// with the play counts of the station and the retracted track corrected.
+    async fn add_play_with_resumed_play(
+        &self,
+        station: &mut StationInDB,
+        retracted: &LatestPlay,
+    ) -> Result<()> {
+        let resumed_play = station.previous_play.clone().ok_or(Error::NotFound)?;
+        let resumed_play_datetime: DateTime<Utc> = resumed_play.id.datetime().into();
+        let table_name = self.context.backend.table_name();
+        let now = self.context.clock.now();
+
+        let removal = self
+            .crud_track
+            .prepare_play_removal(station, retracted.id, retracted.track_id, now)
             .await?;
+        let mut items = removal.items;
+
+        items.push(TransactWriteItem::Update(build_play_update(
+            table_name,
+            BuildPlayUpdateInput {
+                pk: PlayInDB::get_pk(station.id, &resumed_play_datetime),
+                sk: PlayInDB::get_sk(resumed_play.id),
+                play_id: resumed_play.id.to_string(),
+                track_id: resumed_play.track_id.to_string(),
+                update_timestamp: ziso_timestamp(&now),
+            },
+        )?));
+
+        items.push(TransactWriteItem::Update(
+            build_station_play_retracted_update(
+                table_name,
+                BuildStationPlayRetractedUpdateInput {
+                    pk: StationInDB::get_pk(),
+                    sk: StationInDB::get_sk(station.id),
+                    track_removed: removal.track_deleted,
+                    latest_play: serde_dynamo::to_item(&resumed_play)?,
+                    update_timestamp: ziso_timestamp(&now),
+                    locked_timestamp: ziso_timestamp(&station.updated_ts),
+                },
+            )?,
+        ));
+
+        self.context.backend.transact_write_items(items).await?;
+
+        station.updated_ts = now;
+        station.latest_play = Some(resumed_play);
+        station.previous_play = None;
+        station.play_count -= 1;
+        if removal.track_deleted {
+            station.track_count -= 1;
+        }
 
         Ok(())
     }
@@ -139,28 +412,23 @@ impl CRUDLogger {
         &self,
         station: &mut StationInDB,
         play: PlayInDB,
-        artist: &str,
-        title: &str,
+        track_metadata: Option<TrackMetadataCreateInDB>,
+        details: TrackDetails,
+        placement: PlayPlacement,
     ) -> Result<()> {
-        let latest_play = LatestPlay {
-            id: play.id,
-            track_id: play.track_id,
-            artist: artist.to_owned(),
-            title: title.to_owned(),
-        };
-
-        let now = Utc::now();
+        let now = self.context.clock.now();
 
         let PreparedTransaction { items, callback } = build_new_play_transaction(
-            self.provider.table_name(),
+            self.context.backend.table_name(),
             station,
             &play,
-            latest_play,
+            track_metadata.as_ref(),
+            details,
+            placement,
             now,
         )?;
 
-        // TODO handle errors
-        self.provider.transact_write_items(items).await?;
+        self.context.backend.transact_write_items(items).await?;
 
         callback(station, None);
 
@@ -172,33 +440,26 @@ impl CRUDLogger {
         station: &mut StationInDB,
         mut track: TrackInDB,
         play: PlayInDB,
+        placement: PlayPlacement,
     ) -> Result<()> {
         track.latest_play_id = Some(play.id);
         track.play_count += 1;
 
-        let track_metadata = TrackMetadataCreateInDB::from(&track);
-
-        let latest_play = LatestPlay {
-            id: play.id,
-            track_id: track.id,
-            artist: track.artist.clone(),
-            title: track.title.clone(),
-        };
+        let track_metadata = TrackMetadataCreateInDB::for_track(&track, &station.normalization);
 
-        let now = Utc::now();
+        let now = self.context.clock.now();
 
         let PreparedTransaction { items, callback } = build_new_track_and_play_transaction(
-            self.provider.table_name(),
+            self.context.backend.table_name(),
             station,
             &track,
             &track_metadata,
             &play,
-            latest_play,
+            placement,
             now,
         )?;
 
-        // TODO handle errors
-        self.provider.transact_write_items(items).await?;
+        self.context.backend.transact_write_items(items).await?;
 
         callback(station);
 
@@ -206,12 +467,150 @@ impl CRUDLogger {
     }
 }
 
-#[derive(Error, Debug)]
-enum BuildTransactionError {
-    #[error("Failed to build transaction item: {0:?}")]
-    BuildError(#[from] aws_sdk_dynamodb::error::BuildError),
-    #[error("Failed to serialize into transaction item: {0:?}")]
-    SerializeError(#[from] serde_dynamo::Error),
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PlayMode {
+    /// The play is on air now, or was when it was fetched.
+    Live,
+    /// The play is filled in from the past, it may be older than the plays already logged.
+    Historical,
+    /// The play was on air at a given time, it is placed like a historical play if it is
+    /// older than the station latest play and like a live play otherwise.
+    At,
+}
+
+impl PlayMode {
+    /// Resolves [`PlayMode::At`] against the station as last read, so a retry after a
+    /// concurrent update places the play against the latest play it lost to.
+    fn resolve(self, station: &StationInDB, played_at: DateTime<Utc>) -> Self {
+        match self {
+            Self::At => {
+                let is_older = station.latest_play.as_ref().is_some_and(|latest_play| {
+                    played_at < DateTime::<Utc>::from(latest_play.id.datetime())
+                });
+
+                if is_older {
+                    Self::Historical
+                } else {
+                    Self::Live
+                }
+            }
+            mode => mode,
+        }
+    }
+}
+
+/// Where a new play goes among the plays already logged for its station and track.
+struct PlayPlacement {
+    /// Replaces the station latest play, `None` for a historical play older than it.
+    latest_play: Option<LatestPlay>,
+    /// The play is earlier than the station first play, or the station has none.
+    is_first_play: bool,
+    /// The track of a historical play, or of a play older than its track, as read before the
+    /// play is added, its update is locked on it. Without it the play becomes the latest play
+    /// of its track.
+    track: Option<TrackInDB>,
+}
+
+impl PlayPlacement {
+    fn new(
+        station: &StationInDB,
+        latest_play: LatestPlay,
+        mode: PlayMode,
+        track: Option<TrackInDB>,
+    ) -> Self {
+        let play_id = latest_play.id;
+        // a play added at an earlier time than the first play takes its place in either mode,
+        // plays within the same millisecond keep the first play they were logged after
+        let is_first_play = station
+            .first_play_id
+            .is_none_or(|first_play_id| play_id.timestamp_ms() < first_play_id.timestamp_ms());
+
+        match mode {
+            PlayMode::Live | PlayMode::At => Self {
+                latest_play: Some(latest_play),
+                is_first_play,
+                track,
+            },
+            PlayMode::Historical => Self {
+                latest_play: station
+                    .latest_play
+                    .as_ref()
+                    .is_none_or(|station_latest_play| station_latest_play.id.0 < play_id.0)
+                    .then_some(latest_play),
+                is_first_play,
+                track,
+            },
+        }
+    }
+
+    fn is_track_latest_play(&self, play_id: PlayId) -> bool {
+        self.track.as_ref().is_none_or(|track| {
+            track
+                .latest_play_id
+                .is_none_or(|latest_play_id| latest_play_id.0 < play_id.0)
+        })
+    }
+
+    /// Returns the time of the play if it is older than every play of its track so far.
+    fn track_earliest_play(&self, play_id: PlayId) -> Option<DateTime<Utc>> {
+        let played_at: DateTime<Utc> = play_id.datetime().into();
+
+        self.track
+            .as_ref()
+            .is_some_and(|track| played_at < track.earliest_play_bound())
+            .then_some(played_at)
+    }
+
+    fn first_play_update(&self, station: &StationInDB, play_id: PlayId) -> FirstPlayUpdate {
+        match (self.is_first_play, station.first_play_id) {
+            (false, _) => FirstPlayUpdate::Keep,
+            (true, None) => FirstPlayUpdate::Initial(play_id.to_string()),
+            (true, Some(_)) => FirstPlayUpdate::Earlier(play_id.to_string()),
+        }
+    }
+
+    fn station_update_input(
+        &self,
+        station: &StationInDB,
+        play_id: PlayId,
+        increment: StationUpdateIncrementType,
+        timestamp: &DateTime<Utc>,
+    ) -> Result<BuildStationUpdateInput> {
+        let (latest_play, previous_play) = match &self.latest_play {
+            Some(latest_play) => (
+                Some(serde_dynamo::to_item(latest_play)?),
+                station
+                    .latest_play
+                    .as_ref()
+                    .map(serde_dynamo::to_item)
+                    .transpose()?,
+            ),
+            None => (None, None),
+        };
+
+        Ok(BuildStationUpdateInput {
+            pk: StationInDB::get_pk(),
+            sk: StationInDB::get_sk(station.id),
+            increment,
+            latest_play,
+            previous_play,
+            first_play_id: self.first_play_update(station, play_id),
+            update_timestamp: ziso_timestamp(timestamp),
+            locked_timestamp: Some(ziso_timestamp(&station.updated_ts)),
+        })
+    }
+
+    /// Applies the station changes of the transaction to the station struct.
+    fn update_station(self, station: &mut StationInDB, play_id: PlayId, timestamp: DateTime<Utc>) {
+        station.updated_ts = timestamp;
+        station.play_count += 1;
+        if let Some(latest_play) = self.latest_play {
+            station.previous_play = station.latest_play.replace(latest_play);
+        }
+        if self.is_first_play {
+            station.first_play_id = Some(play_id);
+        }
+    }
 }
 
 struct PreparedTransaction<CallbackFn> {
@@ -223,58 +622,75 @@ fn build_new_play_transaction<'i>(
     table_name: &'i str,
     station: &'i StationInDB,
     play: &'i PlayInDB,
-    latest_play: LatestPlay,
+    track_metadata: Option<&'i TrackMetadataCreateInDB>,
+    details: TrackDetails,
+    placement: PlayPlacement,
     timestamp: DateTime<Utc>,
-) -> Result<
-    PreparedTransaction<impl FnOnce(&mut StationInDB, Option<&mut TrackInDB>) + use<>>,
-    BuildTransactionError,
-> {
+) -> Result<PreparedTransaction<impl FnOnce(&mut StationInDB, Option<&mut TrackInDB>) + use<>>> {
     let play_put = build_put(table_name, serde_dynamo::to_item(play)?)?;
 
+    let is_track_latest_play = placement.is_track_latest_play(play.id);
+    let track_earliest_play = placement.track_earliest_play(play.id);
     let track_update = build_track_update(
         table_name,
         BuildTrackUpdateInput {
             pk: TrackInDB::get_pk(station.id),
             sk: TrackInDB::get_sk(play.track_id),
-            play_id: play.id.to_string(),
+            latest_play_id: is_track_latest_play.then(|| play.id.to_string()),
+            earliest_play_timestamp: track_earliest_play.as_ref().map(ziso_timestamp),
+            details: serde_dynamo::to_item(&details)?,
             update_timestamp: ziso_timestamp(&timestamp),
+            locked_timestamp: placement
+                .track
+                .as_ref()
+                .map(|track| ziso_timestamp(&track.updated_ts)),
         },
     )?;
 
     // update station with latest play
     let station_update = build_station_update(
         table_name,
-        BuildStationUpdateInput {
-            pk: StationInDB::get_pk(),
-            sk: StationInDB::get_sk(station.id),
-            increment: StationUpdateIncrementType::Play,
-            latest_play: serde_dynamo::to_item(latest_play.clone())?,
-            first_play_id: None, // first play will always create new track
-            update_timestamp: ziso_timestamp(&timestamp),
-            locked_timestamp: Some(ziso_timestamp(&station.updated_ts)),
-        },
+        placement.station_update_input(
+            station,
+            play.id,
+            StationUpdateIncrementType::Play,
+            &timestamp,
+        )?,
     )?;
 
+    let mut items = vec![
+        TransactWriteItem::Put(play_put),
+        TransactWriteItem::Update(track_update),
+        TransactWriteItem::Update(station_update),
+    ];
+
+    if let Some(track_metadata) = track_metadata {
+        items.push(TransactWriteItem::Put(build_put(
+            table_name,
+            serde_dynamo::to_item(track_metadata)?,
+        )?));
+    }
+
     let play_id = play.id;
     let update_structs_callback =
         move |station: &mut StationInDB, track: Option<&mut TrackInDB>| {
-            station.updated_ts = timestamp;
-            station.latest_play = Some(latest_play);
-            station.play_count += 1;
+            placement.update_station(station, play_id, timestamp);
 
             if let Some(track) = track {
                 track.updated_ts = timestamp;
-                track.latest_play_id = Some(play_id);
+                if is_track_latest_play {
+                    track.latest_play_id = Some(play_id);
+                }
+                if track_earliest_play.is_some() {
+                    track.earliest_play_ts = track_earliest_play;
+                }
                 track.play_count += 1;
+                track.details.fill_missing(&details);
             }
         };
 
     Ok(PreparedTransaction {
-        items: vec![
-            TransactWriteItem::Put(play_put),
-            TransactWriteItem::Update(track_update),
-            TransactWriteItem::Update(station_update),
-        ],
+        items,
         callback: update_structs_callback,
     })
 }
@@ -285,9 +701,9 @@ fn build_new_track_and_play_transaction<'i>(
     track: &'i TrackInDB,
     track_metadata: &'i TrackMetadataCreateInDB,
     play: &'i PlayInDB,
-    latest_play: LatestPlay,
+    placement: PlayPlacement,
     timestamp: DateTime<Utc>,
-) -> Result<PreparedTransaction<impl FnOnce(&mut StationInDB) + use<>>, BuildTransactionError> {
+) -> Result<PreparedTransaction<impl FnOnce(&mut StationInDB) + use<>>> {
     let track_put = build_put(table_name, serde_dynamo::to_item(track)?)?;
     let track_metadata_put = build_put(table_name, serde_dynamo::to_item(track_metadata)?)?;
     let play_put = build_put(table_name, serde_dynamo::to_item(play)?)?;
@@ -295,29 +711,18 @@ fn build_new_track_and_play_transaction<'i>(
     // update station with latest play and track
     let station_update = build_station_update(
         table_name,
-        BuildStationUpdateInput {
-            pk: StationInDB::get_pk(),
-            sk: StationInDB::get_sk(station.id),
-            increment: StationUpdateIncrementType::PlayAndTrack,
-            latest_play: serde_dynamo::to_item(latest_play.clone())?,
-            first_play_id: match station.first_play_id {
-                None => Some(play.id.to_string()),
-                Some(_) => None,
-            },
-            update_timestamp: ziso_timestamp(&timestamp),
-            locked_timestamp: Some(ziso_timestamp(&station.updated_ts)),
-        },
+        placement.station_update_input(
+            station,
+            play.id,
+            StationUpdateIncrementType::PlayAndTrack,
+            &timestamp,
+        )?,
     )?;
 
     let play_id = play.id;
     let update_structs_callback = move |station: &mut StationInDB| {
-        station.updated_ts = timestamp;
-        station.latest_play = Some(latest_play);
-        station.play_count += 1;
+        placement.update_station(station, play_id, timestamp);
         station.track_count += 1;
-        if station.first_play_id.is_none() {
-            station.first_play_id = Some(play_id)
-        }
     };
 
     Ok(PreparedTransaction {
@@ -336,16 +741,40 @@ mod tests {
     use super::*;
 
     use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
 
     use aws_sdk_dynamodb::types::AttributeValue;
+    use chrono::Duration;
     use ulid::Ulid;
 
+    #[cfg(feature = "sqlite")]
+    use crate::backend::SqliteBackend;
+    use crate::backend::testing::{FixedClock, TestPlay, list_all_plays_of_track, memory_context};
+    use crate::backend::{GetItemConfig, Item, Key, MemoryBackend, ProjectedFields};
+    use crate::clock::{IdGenerator, UlidGenerator};
+    use crate::crud::play::CRUDPlay;
+    use crate::crud::shared::models::SortOrder;
+    use crate::crud::station::models::StationInDBCreate;
+    use crate::crud::track::normalize::NormalizationRules;
+    use models::AddPlayType;
+
+    /// Generates ULIDs with an increasing random part, so IDs are predictable.
+    #[derive(Default)]
+    struct SequentialIdGenerator(AtomicU64);
+
+    impl IdGenerator for SequentialIdGenerator {
+        fn generate(&self, datetime: DateTime<Utc>) -> Ulid {
+            let sequence = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            Ulid::from_parts(datetime.timestamp_millis() as u64, sequence.into())
+        }
+    }
+
     #[test]
     fn test_build_new_play_transaction() {
         let mut station = StationInDB::new_for_test();
         let track_id = Ulid::from_parts(1, 1).into();
 
-        let new_play = PlayInDB::new(station.id, track_id);
+        let new_play = PlayInDB::new(station.id, track_id, Utc::now(), &UlidGenerator);
 
         let latest_play = LatestPlay {
             id: Ulid::from_parts(2, 99).into(),
@@ -358,9 +787,15 @@ mod tests {
 
         let PreparedTransaction { items, callback } = build_new_play_transaction(
             "tablename",
-            &mut station,
+            &station,
             &new_play,
-            latest_play.clone(),
+            None,
+            TrackDetails::default(),
+            PlayPlacement {
+                latest_play: Some(latest_play.clone()),
+                is_first_play: false,
+                track: None,
+            },
             timestamp,
         )
         .unwrap();
@@ -458,4 +893,982 @@ mod tests {
 
         assert_eq!(station, expected_new_station);
     }
+
+    #[tokio::test]
+    async fn test_add_play_in_memory() {
+        let context = memory_context();
+
+        check_add_play_flow(context).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_add_play_sqlite() {
+        let context = Arc::new(Context::with_backend(
+            SqliteBackend::open_in_memory("tablename".to_owned()).unwrap(),
+        ));
+
+        check_add_play_flow(context).await;
+    }
+
+    async fn check_add_play_flow<B: StorageBackend>(context: Arc<Context<B>>) {
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_play = CRUDPlay::new(context.clone());
+        let crud_track = CRUDTrack::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                location: None,
+                fetcher: None,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let song_a = || TestPlay::new("artist a", "title a");
+        let song_b = TestPlay::new("artist b", "title b");
+
+        let first = crud_logger.add_play(&mut station, song_a()).await.unwrap();
+        assert!(matches!(first.add_type, AddPlayType::NewTrack));
+
+        let repeated = crud_logger.add_play(&mut station, song_a()).await.unwrap();
+        assert!(matches!(repeated.add_type, AddPlayType::ExistingPlay));
+        assert_eq!(repeated.play_id, first.play_id);
+
+        let other = crud_logger.add_play(&mut station, song_b).await.unwrap();
+        assert!(matches!(other.add_type, AddPlayType::NewTrack));
+
+        let returning = crud_logger.add_play(&mut station, song_a()).await.unwrap();
+        assert!(matches!(returning.add_type, AddPlayType::NewPlay));
+        assert_eq!(returning.track_id, first.track_id);
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, station);
+        assert_eq!(stored_station.play_count, 3);
+        assert_eq!(stored_station.track_count, 2);
+        assert_eq!(stored_station.first_play_id, Some(first.play_id));
+
+        let now = Utc::now();
+        let (plays, _) = crud_play
+            .list_plays(
+                station.id,
+                50,
+                now - Duration::hours(1),
+                now + Duration::minutes(1),
+                SortOrder::Asc,
+                None,
+            )
+            .await
+            .unwrap();
+        // plays logged within the same millisecond have no defined order
+        let mut play_ids: Vec<_> = plays.iter().map(|play| play.id).collect();
+        let mut expected_play_ids = vec![first.play_id, other.play_id, returning.play_id];
+        play_ids.sort_by_key(|play_id| play_id.0);
+        expected_play_ids.sort_by_key(|play_id| play_id.0);
+        assert_eq!(play_ids, expected_play_ids);
+
+        let track = crud_track
+            .get_track(station.id, first.track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track.play_count, 2);
+        assert_eq!(track.latest_play_id, Some(returning.play_id));
+    }
+
+    #[tokio::test]
+    async fn test_add_play_retries_after_concurrent_update() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                location: None,
+                fetcher: None,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut stale_station = station.clone();
+
+        let first = crud_logger
+            .add_play(&mut station, TestPlay::new("artist a", "title a"))
+            .await
+            .unwrap();
+        assert_eq!(first.retries, 0);
+
+        let second = crud_logger
+            .add_play(&mut stale_station, TestPlay::new("artist b", "title b"))
+            .await
+            .unwrap();
+        assert!(matches!(second.add_type, AddPlayType::NewTrack));
+        assert_eq!(second.retries, 1);
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, stale_station);
+        assert_eq!(stored_station.play_count, 2);
+        assert_eq!(stored_station.track_count, 2);
+        assert_eq!(stored_station.first_play_id, Some(first.play_id));
+    }
+
+    #[tokio::test]
+    async fn test_add_play_at_uses_played_at_partitions() {
+        let now = DateTime::parse_from_rfc3339("2001-02-03T04:05:06Z")
+            .unwrap()
+            .to_utc();
+        let played_at = DateTime::parse_from_rfc3339("2000-12-31T23:59:59Z")
+            .unwrap()
+            .to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(now))
+                .with_id_generator(SequentialIdGenerator::default()),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                location: None,
+                fetcher: None,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(*station.id, Ulid::from_parts(981173106000, 1));
+
+        let result = crud_logger
+            .add_play_at(
+                &mut station,
+                TestPlay::new("artist a", "title a"),
+                played_at,
+            )
+            .await
+            .unwrap();
+        assert_eq!(*result.track_id, Ulid::from_parts(978307199000, 2));
+        assert_eq!(*result.play_id, Ulid::from_parts(978307199000, 3));
+        assert_eq!(station.updated_ts, now);
+
+        let play_item = context
+            .backend
+            .get_item(
+                Key {
+                    pk: format!("STATION#{}#PLAYS#2000-12-31", *station.id),
+                    sk: format!("PLAY#{}", *result.play_id),
+                },
+                GetItemConfig {
+                    consistent_read: true,
+                    projected_fields: ProjectedFields::All,
+                },
+            )
+            .await
+            .unwrap()
+            .expect("play to be stored in the played_at day partition");
+        assert_eq!(
+            play_item.get("gsi1pk"),
+            Some(&AttributeValue::S(format!(
+                "TRACK#{}#2000-12",
+                *result.track_id
+            )))
+        );
+
+        let play: PlayInDB = serde_dynamo::from_item(play_item).unwrap();
+        assert_eq!(play.created_ts, played_at);
+    }
+
+    #[tokio::test]
+    async fn test_add_play_at_earlier_than_first_play() {
+        let now = DateTime::parse_from_rfc3339("2001-02-03T04:05:06Z")
+            .unwrap()
+            .to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(now)),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let first = crud_logger
+            .add_play_at(
+                &mut station,
+                TestPlay::new("artist a", "title a"),
+                now - Duration::hours(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(station.first_play_id, Some(first.play_id));
+
+        let earlier = crud_logger
+            .add_play_at(
+                &mut station,
+                TestPlay::new("artist b", "title b"),
+                now - Duration::hours(2),
+            )
+            .await
+            .unwrap();
+        assert_eq!(station.first_play_id, Some(earlier.play_id));
+        assert_eq!(
+            station.latest_play.as_ref().map(|play| play.id),
+            Some(first.play_id)
+        );
+
+        let later = crud_logger
+            .add_play_at(&mut station, TestPlay::new("artist c", "title c"), now)
+            .await
+            .unwrap();
+        assert_eq!(station.first_play_id, Some(earlier.play_id));
+        assert_eq!(
+            station.latest_play.as_ref().map(|play| play.id),
+            Some(later.play_id)
+        );
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, station);
+    }
+
+    #[tokio::test]
+    async fn test_add_play_at_behind_latest_play() {
+        let now = DateTime::parse_from_rfc3339("2001-02-03T04:05:06Z")
+            .unwrap()
+            .to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(now)),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                flap_window_seconds: Some(600),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let previous = crud_logger
+            .add_play_at(
+                &mut station,
+                TestPlay::new("artist a", "title a"),
+                now - Duration::hours(2),
+            )
+            .await
+            .unwrap();
+        let latest = crud_logger
+            .add_play_at(
+                &mut station,
+                TestPlay::new("artist b", "title b"),
+                now - Duration::hours(1),
+            )
+            .await
+            .unwrap();
+
+        // backfilled plays neither resume the previous play nor continue the latest play
+        for (artist, title, minutes) in [("artist a", "title a", 90), ("artist b", "title b", 80)] {
+            let result = crud_logger
+                .add_play_at(
+                    &mut station,
+                    TestPlay::new(artist, title),
+                    now - Duration::minutes(minutes),
+                )
+                .await
+                .unwrap();
+            assert!(matches!(result.add_type, AddPlayType::NewPlay));
+            assert_eq!(result.retracted_play_id, None);
+        }
+
+        assert_eq!(station.first_play_id, Some(previous.play_id));
+        assert_eq!(
+            station.latest_play.as_ref().map(|play| play.id),
+            Some(latest.play_id)
+        );
+        assert_eq!(
+            station.previous_play.as_ref().map(|play| play.id),
+            Some(previous.play_id)
+        );
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, station);
+
+        // the next live fetch still continues the latest play
+        let result = crud_logger
+            .add_play(&mut station, TestPlay::new("artist b", "title b"))
+            .await
+            .unwrap();
+        assert!(matches!(result.add_type, AddPlayType::ExistingPlay));
+        assert_eq!(result.play_id, latest.play_id);
+    }
+
+    #[tokio::test]
+    async fn test_add_play_uses_reported_start_time() {
+        let now = DateTime::parse_from_rfc3339("2001-02-03T04:05:06Z")
+            .unwrap()
+            .to_utc();
+        let started_at = now - Duration::seconds(96);
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(now)),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                location: None,
+                fetcher: None,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let reported = crud_logger
+            .add_play(
+                &mut station,
+                TestPlay {
+                    title: "title a",
+                    started_at: Some(started_at),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            DateTime::<Utc>::from(reported.play_id.datetime()),
+            started_at
+        );
+
+        // a start time ahead of our clock cannot be trusted
+        let future = crud_logger
+            .add_play(
+                &mut station,
+                TestPlay {
+                    title: "title b",
+                    started_at: Some(now + Duration::minutes(5)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(DateTime::<Utc>::from(future.play_id.datetime()), now);
+    }
+
+    #[tokio::test]
+    async fn test_add_play_clamps_start_time_to_latest_play() {
+        let now = DateTime::parse_from_rfc3339("2001-02-03T04:05:06Z")
+            .unwrap()
+            .to_utc();
+        let latest_played_at = now - Duration::minutes(2);
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(now)),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let latest = crud_logger
+            .add_play(
+                &mut station,
+                TestPlay {
+                    title: "title a",
+                    started_at: Some(latest_played_at),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // the source reports a start before the play we already logged as on air
+        let late = crud_logger
+            .add_play(
+                &mut station,
+                TestPlay {
+                    title: "title b",
+                    started_at: Some(latest_played_at - Duration::minutes(3)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            DateTime::<Utc>::from(late.play_id.datetime()),
+            latest_played_at + Duration::milliseconds(1)
+        );
+        assert_eq!(station.first_play_id, Some(latest.play_id));
+        assert_eq!(
+            station.latest_play.as_ref().map(|play| play.id),
+            Some(late.play_id)
+        );
+
+        let (plays, _) = crud_play
+            .list_plays(
+                station.id,
+                10,
+                now - Duration::hours(1),
+                now,
+                SortOrder::Desc,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            plays.iter().map(|play| play.id).collect::<Vec<_>>(),
+            [late.play_id, latest.play_id]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_play_fills_in_track_details() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+        let crud_logger = CRUDLogger::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                location: None,
+                fetcher: None,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let first = crud_logger
+            .add_play(
+                &mut station,
+                TestPlay {
+                    title: "title a",
+                    album: Some("album a"),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let track = crud_track
+            .get_track(station.id, first.track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track.details.album.as_deref(), Some("album a"));
+        assert_eq!(track.details.isrc, None);
+
+        // another track in between so the next play is a new play of the same track
+        crud_logger
+            .add_play(
+                &mut station,
+                TestPlay {
+                    title: "title b",
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let second = crud_logger
+            .add_play(
+                &mut station,
+                TestPlay {
+                    title: "title a",
+                    album: Some("other album"),
+                    isrc: Some("isrc a"),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(matches!(second.add_type, AddPlayType::NewPlay));
+        assert_eq!(second.track_id, first.track_id);
+
+        // missing details are filled in, existing ones are kept
+        let track = crud_track
+            .get_track(station.id, first.track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track.details.album.as_deref(), Some("album a"));
+        assert_eq!(track.details.isrc.as_deref(), Some("isrc a"));
+        assert_eq!(track.play_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_add_play_matches_normalized_metadata() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+        let crud_logger = CRUDLogger::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let plays = [
+            ("Artist feat. X", "Title"),
+            ("Other", "Other"),
+            ("ARTIST ft X ", "title"),
+            ("Other", "Other"),
+            ("Artist  Featuring X", "TITLE"),
+        ];
+
+        let mut results = vec![];
+        for (artist, title) in plays {
+            results.push(
+                crud_logger
+                    .add_play(&mut station, TestPlay::new(artist, title))
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        assert!(matches!(results[2].add_type, AddPlayType::NewPlay));
+        assert!(matches!(results[4].add_type, AddPlayType::NewPlay));
+        assert_eq!(results[2].track_id, results[0].track_id);
+        assert_eq!(results[4].track_id, results[0].track_id);
+        assert_eq!(station.track_count, 2);
+
+        // the original spelling is kept for display
+        let track = crud_track
+            .get_track(station.id, results[0].track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track.artist, "Artist feat. X");
+        assert_eq!(track.title, "Title");
+        assert_eq!(station.latest_play.unwrap().artist, "Artist  Featuring X");
+    }
+
+    #[tokio::test]
+    async fn test_add_play_adds_normalized_metadata_to_verbatim_track() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+        let crud_logger = CRUDLogger::new(context);
+
+        // tracks created before normalization are keyed by their verbatim metadata
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                normalization: NormalizationRules::verbatim(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let first = crud_logger
+            .add_play(&mut station, TestPlay::new("Artist ft X", "Title"))
+            .await
+            .unwrap();
+        crud_logger
+            .add_play(&mut station, TestPlay::new("Other", "Other"))
+            .await
+            .unwrap();
+
+        station.normalization = NormalizationRules::default();
+        assert!(
+            crud_track
+                .get_track_metadata(station.id, "artist feat. x", "title")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let second = crud_logger
+            .add_play(&mut station, TestPlay::new("Artist ft X", "Title"))
+            .await
+            .unwrap();
+        assert!(matches!(second.add_type, AddPlayType::NewPlay));
+        assert_eq!(second.track_id, first.track_id);
+
+        let track_metadata = crud_track
+            .get_track_metadata(station.id, "artist feat. x", "title")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_metadata.track_id, first.track_id);
+    }
+
+    #[tokio::test]
+    async fn test_add_play_keeps_verbatim_keys_of_station_stored_without_rules() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                normalization: NormalizationRules::verbatim(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let first = crud_logger
+            .add_play(&mut station, TestPlay::new("Artist ft X", "Title"))
+            .await
+            .unwrap();
+        crud_logger
+            .add_play(&mut station, TestPlay::new("Other", "Other"))
+            .await
+            .unwrap();
+
+        // the station item as written before normalization rules were stored on it
+        let mut station_item: Item = serde_dynamo::to_item(&station).unwrap();
+        station_item.remove("normalization");
+        context.backend.put_item(station_item).await.unwrap();
+
+        let mut station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(station.normalization, NormalizationRules::verbatim());
+
+        let second = crud_logger
+            .add_play(&mut station, TestPlay::new("Artist ft X", "Title"))
+            .await
+            .unwrap();
+        assert!(matches!(second.add_type, AddPlayType::NewPlay));
+        assert_eq!(second.track_id, first.track_id);
+
+        let (tracks, _) = crud_track
+            .list_tracks_by_artist(&station, "Artist ft X", 10, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            tracks.iter().map(|track| track.id).collect::<Vec<_>>(),
+            [first.track_id]
+        );
+
+        // no lookup items with normalized keys are added behind the back of the station
+        assert!(
+            crud_track
+                .get_track_metadata(station.id, "artist feat. x", "title")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_play_resumes_play_within_flap_window() {
+        let start = DateTime::parse_from_rfc3339("2001-02-03T04:05:06Z")
+            .unwrap()
+            .to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(start + Duration::hours(1)))
+                .with_id_generator(SequentialIdGenerator::default()),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                flap_window_seconds: Some(60),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let song = |artist: &'static str| TestPlay::new(artist, "title");
+        let add_play = async |station: &mut StationInDB, artist, seconds| {
+            crud_logger
+                .add_play_at(station, song(artist), start + Duration::seconds(seconds))
+                .await
+                .unwrap()
+        };
+
+        let first_b = add_play(&mut station, "artist b", 0).await;
+        let first_a = add_play(&mut station, "artist a", 100).await;
+        let second_b = add_play(&mut station, "artist b", 200).await;
+        assert!(matches!(second_b.add_type, AddPlayType::NewPlay));
+
+        // B flickered in between A, the retracted play leaves the earlier play of its track
+        let resumed = add_play(&mut station, "artist a", 220).await;
+        assert!(matches!(resumed.add_type, AddPlayType::ResumedPlay));
+        assert_eq!(resumed.play_id, first_a.play_id);
+        assert_eq!(resumed.retracted_play_id, Some(second_b.play_id));
+
+        let track_b = crud_track
+            .get_track(station.id, first_b.track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_b.play_count, 1);
+        assert_eq!(track_b.latest_play_id, Some(first_b.play_id));
+
+        let retracted_play = context
+            .backend
+            .get_item(
+                Key {
+                    pk: PlayInDB::get_pk(station.id, &(start + Duration::seconds(200))),
+                    sk: PlayInDB::get_sk(second_b.play_id),
+                },
+                GetItemConfig {
+                    consistent_read: true,
+                    projected_fields: ProjectedFields::All,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(retracted_play.is_none());
+
+        // a flicker to a new track removes the track as well
+        let new_c = add_play(&mut station, "artist c", 240).await;
+        let resumed = add_play(&mut station, "artist a", 250).await;
+        assert!(matches!(resumed.add_type, AddPlayType::ResumedPlay));
+        assert_eq!(resumed.retracted_play_id, Some(new_c.play_id));
+
+        assert!(
+            crud_track
+                .get_track(station.id, new_c.track_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // plays older than the window are kept
+        add_play(&mut station, "artist d", 300).await;
+        let returning = add_play(&mut station, "artist a", 400).await;
+        assert!(matches!(returning.add_type, AddPlayType::NewPlay));
+
+        assert!(
+            crud_track
+                .get_track_by_metadata(&station, "artist c", "title")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, station);
+        assert_eq!(stored_station.play_count, 4);
+        assert_eq!(stored_station.track_count, 3);
+        assert_eq!(
+            stored_station.latest_play.map(|play| play.id),
+            Some(returning.play_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_insert_historical_play() {
+        let start = DateTime::parse_from_rfc3339("2001-02-03T04:05:06Z")
+            .unwrap()
+            .to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(start + Duration::hours(1)))
+                .with_id_generator(SequentialIdGenerator::default()),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let song = |artist| TestPlay::new(artist, "title");
+
+        let first_a = crud_logger
+            .add_play_at(&mut station, song("artist a"), start)
+            .await
+            .unwrap();
+        let latest_b = crud_logger
+            .add_play_at(
+                &mut station,
+                song("artist b"),
+                start + Duration::minutes(30),
+            )
+            .await
+            .unwrap();
+
+        // an earlier play of a new track becomes the first play, the latest play stays
+        let earlier_c = crud_logger
+            .insert_historical_play(&mut station, song("artist c"), start - Duration::days(1))
+            .await
+            .unwrap();
+        assert!(matches!(earlier_c.add_type, AddPlayType::NewTrack));
+
+        // a play between the live plays counts for its track without replacing the latest play
+        // of the station, it is the latest play of track a though
+        let between_a = crud_logger
+            .insert_historical_play(
+                &mut station,
+                song("artist a"),
+                start + Duration::minutes(10),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(between_a.add_type, AddPlayType::NewPlay));
+        assert_eq!(between_a.track_id, first_a.track_id);
+
+        // the same metadata as the latest play still makes a separate play
+        let between_b = crud_logger
+            .insert_historical_play(
+                &mut station,
+                song("artist b"),
+                start + Duration::minutes(20),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(between_b.add_type, AddPlayType::NewPlay));
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, station);
+        assert_eq!(stored_station.play_count, 5);
+        assert_eq!(stored_station.track_count, 3);
+        assert_eq!(stored_station.first_play_id, Some(earlier_c.play_id));
+        assert_eq!(
+            stored_station.latest_play.map(|latest_play| latest_play.id),
+            Some(latest_b.play_id)
+        );
+        assert_eq!(
+            stored_station
+                .previous_play
+                .map(|previous_play| previous_play.id),
+            Some(first_a.play_id)
+        );
+
+        let track_a = crud_track
+            .get_track(station.id, first_a.track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_a.play_count, 2);
+        assert_eq!(track_a.latest_play_id, Some(between_a.play_id));
+
+        let track_b = crud_track
+            .get_track(station.id, latest_b.track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_b.play_count, 2);
+        assert_eq!(track_b.latest_play_id, Some(latest_b.play_id));
+    }
+
+    #[tokio::test]
+    async fn test_insert_historical_play_older_than_track() {
+        let datetime = |value: &str| DateTime::parse_from_rfc3339(value).unwrap().to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(datetime("2001-06-15T00:00:00Z"))),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let live = crud_logger
+            .add_play_at(
+                &mut station,
+                TestPlay::new("artist a", "title"),
+                datetime("2001-05-10T00:00:00Z"),
+            )
+            .await
+            .unwrap();
+
+        // a backfilled play months before the track was created
+        let backfilled = crud_logger
+            .insert_historical_play(
+                &mut station,
+                TestPlay::new("artist a", "title"),
+                datetime("2001-01-10T00:00:00Z"),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(backfilled.add_type, AddPlayType::NewPlay));
+        assert_eq!(backfilled.track_id, live.track_id);
+
+        let track = crud_track
+            .get_track(station.id, live.track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track.play_count, 2);
+        assert_eq!(track.latest_play_id, Some(live.play_id));
+        assert_eq!(
+            track.earliest_play_ts,
+            Some(datetime("2001-01-10T00:00:00Z"))
+        );
+        assert_eq!(
+            list_all_plays_of_track(&crud_track, station.id, live.track_id).await,
+            [live.play_id, backfilled.play_id]
+        );
+
+        // a play added at an even older time moves the earliest play back again
+        crud_logger
+            .add_play_at(
+                &mut station,
+                TestPlay::new("other", "title"),
+                datetime("2000-11-01T00:00:00Z"),
+            )
+            .await
+            .unwrap();
+        let older = crud_logger
+            .add_play_at(
+                &mut station,
+                TestPlay::new("artist a", "title"),
+                datetime("2000-12-01T00:00:00Z"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(older.track_id, live.track_id);
+        assert_eq!(
+            list_all_plays_of_track(&crud_track, station.id, live.track_id).await,
+            [live.play_id, backfilled.play_id, older.play_id]
+        );
+
+        let track = crud_track
+            .get_track(station.id, live.track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track.play_count, 3);
+        assert_eq!(track.latest_play_id, Some(live.play_id));
+    }
 }
@@ -1,12 +1,56 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 
 use crate::crud::play::models::PlayId;
-use crate::crud::track::models::TrackId;
+use crate::crud::station::models::LatestPlay;
+use crate::crud::track::models::{TrackDetails, TrackId};
 
 pub trait Play {
     fn get_title(&self) -> &str;
     fn get_artist(&self) -> &str;
     fn is_song(&self) -> bool;
+
+    /// When the source reports the play started, if it does.
+    fn get_started_at(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    fn get_album(&self) -> Option<&str> {
+        None
+    }
+
+    fn get_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn get_isrc(&self) -> Option<&str> {
+        None
+    }
+
+    fn get_artwork_url(&self) -> Option<&str> {
+        None
+    }
+
+    /// ID of the track in the catalog of the source.
+    fn get_provider_track_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<P: Play + ?Sized> From<&P> for TrackDetails {
+    fn from(play: &P) -> Self {
+        Self {
+            album: play.get_album().map(str::to_owned),
+            duration_ms: play
+                .get_duration()
+                .and_then(|duration| u64::try_from(duration.as_millis()).ok()),
+            isrc: play.get_isrc().map(str::to_owned),
+            artwork_url: play.get_artwork_url().map(str::to_owned),
+            provider_track_id: play.get_provider_track_id().map(str::to_owned),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -16,12 +60,18 @@ pub struct AddPlayResult {
     pub play_id: PlayId,
     pub track_id: TrackId,
     pub(super) metadata: AddPlayMetadata,
+    /// The flicker play taken back when the play before it was resumed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retracted_play_id: Option<PlayId>,
+    /// Number of times the play had to be retried after losing the station optimistic lock.
+    pub retries: u32,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum AddPlayType {
     ExistingPlay,
+    ResumedPlay,
     NewPlay,
     NewTrack,
 }
@@ -30,6 +80,7 @@ impl From<AddPlayTypeInternal> for AddPlayType {
     fn from(value: AddPlayTypeInternal) -> Self {
         match value {
             AddPlayTypeInternal::ExistingPlay { .. } => AddPlayType::ExistingPlay,
+            AddPlayTypeInternal::ResumedPlay { .. } => AddPlayType::ResumedPlay,
             AddPlayTypeInternal::NewPlay { .. } => AddPlayType::NewPlay,
             AddPlayTypeInternal::NewTrack => AddPlayType::NewTrack,
         }
@@ -38,8 +89,22 @@ impl From<AddPlayTypeInternal> for AddPlayType {
 
 #[derive(Debug)]
 pub(super) enum AddPlayTypeInternal {
-    ExistingPlay { track_id: TrackId, play_id: PlayId },
-    NewPlay { track_id: TrackId },
+    ExistingPlay {
+        track_id: TrackId,
+        play_id: PlayId,
+    },
+    /// The play before the latest play came back within the flap window of the station.
+    ResumedPlay {
+        track_id: TrackId,
+        play_id: PlayId,
+        retracted: LatestPlay,
+    },
+    NewPlay {
+        track_id: TrackId,
+        /// The track was found by its verbatim metadata key only, its normalized lookup item
+        /// has to be written.
+        add_normalized_metadata: bool,
+    },
     NewTrack,
 }
 
@@ -1,23 +1,9 @@
 use std::collections::HashMap;
-use std::sync::Arc;
-
-use aws_sdk_dynamodb::error::{BuildError, SdkError};
-use aws_sdk_dynamodb::operation::transact_write_items::{
-    TransactWriteItemsError, TransactWriteItemsOutput,
-};
-use aws_sdk_dynamodb::operation::update_item::{UpdateItemError, UpdateItemOutput};
-use aws_sdk_dynamodb::types::{
-    AttributeValue, Put, TransactWriteItem as DDBTransactWriteItem, Update,
-};
-use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
-
-use crate::crud::Context;
-
-pub(super) struct DynamoDBProvider {
-    context: Arc<Context>,
-}
 
-pub(super) struct UpdatePlayInput {
+use aws_sdk_dynamodb::error::BuildError;
+use aws_sdk_dynamodb::types::{AttributeValue, Put, Update};
+
+pub(super) struct BuildPlayUpdateInput {
     pub pk: String,
     pub sk: String,
     pub play_id: String,
@@ -25,57 +11,20 @@ pub(super) struct UpdatePlayInput {
     pub update_timestamp: String,
 }
 
-#[derive(Debug, PartialEq)]
-pub(super) enum TransactWriteItem {
-    Put(Put),
-    Update(Update),
-}
-
-impl DynamoDBProvider {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
-    }
-
-    pub fn table_name(&self) -> &str {
-        &self.context.db_table
-    }
-
-    pub async fn update_play(
-        &self,
-        input: UpdatePlayInput,
-    ) -> Result<UpdateItemOutput, SdkError<UpdateItemError, HttpResponse>> {
-        self.context
-            .db_client
-            .update_item()
-            .table_name(&self.context.db_table)
-            .key("pk", AttributeValue::S(input.pk))
-            .key("sk", AttributeValue::S(input.sk))
-            .condition_expression("id = :play_id AND track_id = :track_id")
-            .update_expression("SET updated_ts = :ts")
-            .expression_attribute_values(":play_id", AttributeValue::S(input.play_id))
-            .expression_attribute_values(":track_id", AttributeValue::S(input.track_id))
-            .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
-            .send()
-            .await
-    }
-
-    pub async fn transact_write_items(
-        &self,
-        items: impl IntoIterator<Item = TransactWriteItem>,
-    ) -> Result<TransactWriteItemsOutput, SdkError<TransactWriteItemsError, HttpResponse>> {
-        let mut transaction = self.context.db_client.transact_write_items();
-
-        for item in items.into_iter() {
-            transaction = transaction.transact_items(match item {
-                TransactWriteItem::Put(put) => DDBTransactWriteItem::builder().put(put).build(),
-                TransactWriteItem::Update(update) => {
-                    DDBTransactWriteItem::builder().update(update).build()
-                }
-            });
-        }
-
-        transaction.send().await
-    }
+pub fn build_play_update(
+    table_name: &str,
+    input: BuildPlayUpdateInput,
+) -> Result<Update, BuildError> {
+    Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("id = :play_id AND track_id = :track_id")
+        .update_expression("SET updated_ts = :ts")
+        .expression_attribute_values(":play_id", AttributeValue::S(input.play_id))
+        .expression_attribute_values(":track_id", AttributeValue::S(input.track_id))
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .build()
 }
 
 pub fn build_put(
@@ -91,24 +40,59 @@ pub fn build_put(
 pub(super) struct BuildTrackUpdateInput {
     pub pk: String,
     pub sk: String,
-    pub play_id: String,
+    /// Replaces the latest play of the track, `None` for a play older than it.
+    pub latest_play_id: Option<String>,
+    /// Replaces the earliest play time of the track, if the play is older than its plays so far.
+    pub earliest_play_timestamp: Option<String>,
+    /// Track attributes to set if the track does not have them yet.
+    pub details: HashMap<String, AttributeValue>,
     pub update_timestamp: String,
+    pub locked_timestamp: Option<String>,
 }
 
 pub fn build_track_update(
     table_name: &str,
     input: BuildTrackUpdateInput,
 ) -> Result<Update, BuildError> {
-    Update::builder()
+    let mut update_builder = Update::builder()
         .table_name(table_name)
         .key("pk", AttributeValue::S(input.pk))
         .key("sk", AttributeValue::S(input.sk))
-        .update_expression(
-            "SET updated_ts = :ts, latest_play_id = :play_id, play_count = play_count + :inc",
-        )
         .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
-        .expression_attribute_values(":play_id", AttributeValue::S(input.play_id))
-        .expression_attribute_values(":inc", AttributeValue::N("1".to_string()))
+        .expression_attribute_values(":inc", AttributeValue::N("1".to_string()));
+
+    let mut update_expression_parts = vec!["updated_ts = :ts".to_owned()];
+    if let Some(latest_play_id) = input.latest_play_id {
+        update_expression_parts.push("latest_play_id = :play_id".to_owned());
+        update_builder = update_builder
+            .expression_attribute_values(":play_id", AttributeValue::S(latest_play_id));
+    }
+    if let Some(earliest_play_timestamp) = input.earliest_play_timestamp {
+        update_expression_parts.push("earliest_play_ts = :earliest_play_ts".to_owned());
+        update_builder = update_builder.expression_attribute_values(
+            ":earliest_play_ts",
+            AttributeValue::S(earliest_play_timestamp),
+        );
+    }
+    update_expression_parts.push("play_count = play_count + :inc".to_owned());
+
+    if let Some(locked_timestamp) = input.locked_timestamp {
+        update_builder = update_builder
+            .condition_expression("updated_ts = :locked_ts")
+            .expression_attribute_values(":locked_ts", AttributeValue::S(locked_timestamp));
+    }
+
+    let mut details: Vec<_> = input.details.into_iter().collect();
+    details.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (name, value) in details {
+        update_expression_parts.push(format!("#{name} = if_not_exists(#{name}, :{name})"));
+        update_builder = update_builder
+            .expression_attribute_names(format!("#{name}"), &name)
+            .expression_attribute_values(format!(":{name}"), value);
+    }
+
+    update_builder
+        .update_expression(format!("SET {}", update_expression_parts.join(", ")))
         .build()
 }
 
@@ -118,12 +102,24 @@ pub(super) enum StationUpdateIncrementType {
     PlayAndTrack,
 }
 
+#[derive(Clone, Debug)]
+pub(super) enum FirstPlayUpdate {
+    Keep,
+    /// Sets the first play of a station without plays.
+    Initial(String),
+    /// Replaces the first play with an earlier play.
+    Earlier(String),
+}
+
 pub(super) struct BuildStationUpdateInput {
     pub pk: String,
     pub sk: String,
     pub increment: StationUpdateIncrementType,
-    pub latest_play: HashMap<String, AttributeValue>,
-    pub first_play_id: Option<String>,
+    /// Replaces the latest play, `None` for a play older than it.
+    pub latest_play: Option<HashMap<String, AttributeValue>>,
+    /// The replaced latest play, if the station had one.
+    pub previous_play: Option<HashMap<String, AttributeValue>>,
+    pub first_play_id: FirstPlayUpdate,
     pub update_timestamp: String,
     pub locked_timestamp: Option<String>,
 }
@@ -137,10 +133,15 @@ pub fn build_station_update(
         .key("pk", AttributeValue::S(input.pk))
         .key("sk", AttributeValue::S(input.sk));
 
-    let mut update_expression_parts = vec!["updated_ts = :ts", "latest_play = :latest_play"];
+    let mut update_expression_parts = vec!["updated_ts = :ts"];
     update_builder = update_builder
-        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
-        .expression_attribute_values(":latest_play", AttributeValue::M(input.latest_play));
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp));
+
+    if let Some(latest_play) = input.latest_play {
+        update_expression_parts.push("latest_play = :latest_play");
+        update_builder = update_builder
+            .expression_attribute_values(":latest_play", AttributeValue::M(latest_play));
+    }
 
     update_expression_parts.extend_from_slice(match input.increment {
         StationUpdateIncrementType::Play => &["play_count = play_count + :inc"],
@@ -152,13 +153,29 @@ pub fn build_station_update(
     update_builder =
         update_builder.expression_attribute_values(":inc", AttributeValue::N("1".to_string()));
 
-    let mut condition_expression_parts = vec![];
-    if let Some(play_id) = input.first_play_id {
-        update_expression_parts.push("first_play_id = :play_id");
-        condition_expression_parts.push("first_play_id = :null");
+    if let Some(previous_play) = input.previous_play {
+        update_expression_parts.push("previous_play = :previous_play");
         update_builder = update_builder
-            .expression_attribute_values(":play_id", AttributeValue::S(play_id.to_string()))
-            .expression_attribute_values(":null", AttributeValue::Null(true));
+            .expression_attribute_values(":previous_play", AttributeValue::M(previous_play));
+    }
+
+    let mut condition_expression_parts = vec![];
+    match input.first_play_id {
+        FirstPlayUpdate::Keep => {}
+        FirstPlayUpdate::Initial(play_id) => {
+            update_expression_parts.push("first_play_id = :play_id");
+            condition_expression_parts.push("first_play_id = :null");
+            update_builder = update_builder
+                .expression_attribute_values(":play_id", AttributeValue::S(play_id))
+                .expression_attribute_values(":null", AttributeValue::Null(true));
+        }
+        FirstPlayUpdate::Earlier(play_id) => {
+            // play IDs are ULIDs, which sort by time
+            update_expression_parts.push("first_play_id = :play_id");
+            condition_expression_parts.push("first_play_id > :play_id");
+            update_builder =
+                update_builder.expression_attribute_values(":play_id", AttributeValue::S(play_id));
+        }
     }
 
     if let Some(locked_timestamp) = input.locked_timestamp {
@@ -178,6 +195,50 @@ pub fn build_station_update(
     update_builder.build()
 }
 
+pub(super) struct BuildStationPlayRetractedUpdateInput {
+    pub pk: String,
+    pub sk: String,
+    /// The retracted play was the only play of its track, which is removed as well.
+    pub track_removed: bool,
+    /// The resumed play.
+    pub latest_play: HashMap<String, AttributeValue>,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
+}
+
+/// Takes the retracted latest play off the station and resumes the play before it.
+pub fn build_station_play_retracted_update(
+    table_name: &str,
+    input: BuildStationPlayRetractedUpdateInput,
+) -> Result<Update, BuildError> {
+    let mut update_expression_parts = vec![
+        "updated_ts = :ts",
+        "latest_play = :latest_play",
+        "play_count = play_count - :inc",
+    ];
+    if input.track_removed {
+        update_expression_parts.push("track_count = track_count - :inc");
+    }
+
+    Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .update_expression(format!(
+            "SET {} REMOVE previous_play",
+            update_expression_parts.join(", ")
+        ))
+        .condition_expression("updated_ts = :station_locked_ts")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(":latest_play", AttributeValue::M(input.latest_play))
+        .expression_attribute_values(":inc", AttributeValue::N("1".to_owned()))
+        .expression_attribute_values(
+            ":station_locked_ts",
+            AttributeValue::S(input.locked_timestamp),
+        )
+        .build()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -213,8 +274,11 @@ mod tests {
             BuildTrackUpdateInput {
                 pk: "pkvalue".to_owned(),
                 sk: "skvalue".to_owned(),
-                play_id: "playid".to_owned(),
+                latest_play_id: Some("playid".to_owned()),
+                earliest_play_timestamp: None,
+                details: HashMap::new(),
                 update_timestamp: "12345".to_owned(),
+                locked_timestamp: None,
             },
         )
         .unwrap();
@@ -236,13 +300,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_build_track_update_with_details() {
+        let update = build_track_update(
+            "tablename",
+            BuildTrackUpdateInput {
+                pk: "pkvalue".to_owned(),
+                sk: "skvalue".to_owned(),
+                latest_play_id: Some("playid".to_owned()),
+                earliest_play_timestamp: None,
+                details: HashMap::from([
+                    ("isrc".to_owned(), AttributeValue::S("isrcvalue".to_owned())),
+                    (
+                        "album".to_owned(),
+                        AttributeValue::S("albumvalue".to_owned()),
+                    ),
+                ]),
+                update_timestamp: "12345".to_owned(),
+                locked_timestamp: None,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            update,
+            Update::builder()
+                .table_name("tablename")
+                .key("pk", AttributeValue::S("pkvalue".to_owned()))
+                .key("sk", AttributeValue::S("skvalue".to_owned()))
+                .update_expression(
+                    "SET updated_ts = :ts, latest_play_id = :play_id, play_count = play_count + :inc, #album = if_not_exists(#album, :album), #isrc = if_not_exists(#isrc, :isrc)",
+                )
+                .expression_attribute_names("#album", "album")
+                .expression_attribute_names("#isrc", "isrc")
+                .expression_attribute_values(":ts", AttributeValue::S("12345".to_owned()))
+                .expression_attribute_values(":play_id", AttributeValue::S("playid".to_owned()))
+                .expression_attribute_values(":inc", AttributeValue::N("1".to_string()))
+                .expression_attribute_values(":album", AttributeValue::S("albumvalue".to_owned()))
+                .expression_attribute_values(":isrc", AttributeValue::S("isrcvalue".to_owned()))
+                .build()
+                .unwrap()
+        );
+    }
+
     fn base_build_station_update_input() -> BuildStationUpdateInput {
         BuildStationUpdateInput {
             pk: "pkvalue".to_owned(),
             sk: "skvalue".to_owned(),
             increment: StationUpdateIncrementType::Play,
-            latest_play: HashMap::from_iter([("a".to_owned(), AttributeValue::S("b".to_owned()))]),
-            first_play_id: Some("firstplayid".to_owned()),
+            latest_play: Some(HashMap::from_iter([(
+                "a".to_owned(),
+                AttributeValue::S("b".to_owned()),
+            )])),
+            previous_play: None,
+            first_play_id: FirstPlayUpdate::Initial("firstplayid".to_owned()),
             update_timestamp: "12345".to_owned(),
             locked_timestamp: Some("67890".to_owned()),
         }
@@ -286,20 +397,27 @@ mod tests {
         )]
         increment: StationUpdateIncrementType,
         #[values(
-            None,
-            Some("firstplayid".to_owned()),
+            FirstPlayUpdate::Keep,
+            FirstPlayUpdate::Initial("firstplayid".to_owned()),
+            FirstPlayUpdate::Earlier("firstplayid".to_owned()),
         )]
-        first_play_id: Option<String>,
+        first_play_id: FirstPlayUpdate,
         #[values(
             None,
             Some("lockedtimestamp".to_owned()),
         )]
         locked_timestamp: Option<String>,
+        #[values(
+            None,
+            Some(HashMap::from_iter([("c".to_owned(), AttributeValue::S("d".to_owned()))])),
+        )]
+        previous_play: Option<HashMap<String, AttributeValue>>,
     ) {
         let update = build_station_update(
             "tablename",
             BuildStationUpdateInput {
                 increment,
+                previous_play: previous_play.clone(),
                 first_play_id: first_play_id.clone(),
                 locked_timestamp: locked_timestamp.clone(),
                 ..base_build_station_update_input()
@@ -338,7 +456,7 @@ mod tests {
         );
 
         match first_play_id {
-            Some(play_id) => {
+            FirstPlayUpdate::Initial(play_id) => {
                 assert!(update_expression_parts.contains(&"first_play_id = :play_id"));
                 assert!(condition_expression_parts.contains(&"first_play_id = :null"));
                 assert_eq!(
@@ -350,7 +468,16 @@ mod tests {
                     &AttributeValue::Null(true),
                 );
             }
-            None => {
+            FirstPlayUpdate::Earlier(play_id) => {
+                assert!(update_expression_parts.contains(&"first_play_id = :play_id"));
+                assert!(condition_expression_parts.contains(&"first_play_id > :play_id"));
+                assert_eq!(
+                    expression_attribute_values.get(":play_id").unwrap(),
+                    &AttributeValue::S(play_id),
+                );
+                assert!(!expression_attribute_values.contains_key(":null"));
+            }
+            FirstPlayUpdate::Keep => {
                 assert!(!update_expression_parts.contains(&"first_play_id = :play_id"));
                 assert!(!condition_expression_parts.contains(&"first_play_id = :null"));
                 assert!(!expression_attribute_values.contains_key(":play_id"));
@@ -373,5 +500,47 @@ mod tests {
                 assert!(!expression_attribute_values.contains_key(":station_locked_ts"));
             }
         }
+
+        match previous_play {
+            Some(previous_play) => {
+                assert!(update_expression_parts.contains(&"previous_play = :previous_play"));
+                assert_eq!(
+                    expression_attribute_values.get(":previous_play").unwrap(),
+                    &AttributeValue::M(previous_play),
+                );
+            }
+            None => {
+                assert!(!update_expression_parts.contains(&"previous_play = :previous_play"));
+                assert!(!expression_attribute_values.contains_key(":previous_play"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_station_play_retracted_update() {
+        let update = build_station_play_retracted_update(
+            "tablename",
+            BuildStationPlayRetractedUpdateInput {
+                pk: "pkvalue".to_owned(),
+                sk: "skvalue".to_owned(),
+                track_removed: true,
+                latest_play: HashMap::from_iter([(
+                    "a".to_owned(),
+                    AttributeValue::S("b".to_owned()),
+                )]),
+                update_timestamp: "12345".to_owned(),
+                locked_timestamp: "67890".to_owned(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            update.update_expression(),
+            "SET updated_ts = :ts, latest_play = :latest_play, play_count = play_count - :inc, track_count = track_count - :inc REMOVE previous_play"
+        );
+        assert_eq!(
+            update.condition_expression(),
+            Some("updated_ts = :station_locked_ts")
+        );
     }
 }
@@ -1,21 +1,55 @@
+use std::sync::Arc;
+
 use aws_sdk_dynamodb::Client;
 
+use crate::backend::{Backend, DynamoDBBackend, StorageBackend};
+use crate::clock::{Clock, IdGenerator, SystemClock, UlidGenerator};
+use crate::crud::shared::cursor::CursorCodec;
+
 pub mod logger;
 pub mod play;
 pub mod shared;
 pub mod station;
 pub mod track;
 
-pub struct Context {
-    pub(crate) db_client: Client,
-    pub(crate) db_table: String,
+pub struct Context<B: StorageBackend = Backend> {
+    pub(crate) backend: B,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) id_generator: Arc<dyn IdGenerator>,
+    pub(crate) cursor_codec: CursorCodec,
 }
 
 impl Context {
     pub fn new(db_client: Client, db_table: String) -> Self {
+        Self::with_backend(Backend::DynamoDB(DynamoDBBackend::new(db_client, db_table)))
+    }
+}
+
+impl<B: StorageBackend> Context<B> {
+    pub fn with_backend(backend: B) -> Self {
         Self {
-            db_client,
-            db_table,
+            backend,
+            clock: Arc::new(SystemClock),
+            id_generator: Arc::new(UlidGenerator),
+            cursor_codec: CursorCodec::default(),
         }
     }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn with_id_generator(mut self, id_generator: impl IdGenerator + 'static) -> Self {
+        self.id_generator = Arc::new(id_generator);
+        self
+    }
+
+    /// Signs the `next_token` of list methods with `secret`. Every instance serving the same
+    /// clients needs the same secret, or tokens from one are rejected by the others. Without
+    /// it tokens are signed with a secret random to this process.
+    pub fn with_cursor_secret(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.cursor_codec = CursorCodec::new(secret.as_ref());
+        self
+    }
 }
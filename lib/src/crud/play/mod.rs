@@ -3,106 +3,1038 @@ mod provider;
 
 use std::sync::Arc;
 
-use anyhow::Result;
-use chrono::{DateTime, Duration, Utc};
+use crate::{Error, Result};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
 use ulid::Ulid;
 
+use crate::backend::{
+    Backend, GetItemConfig, Key, ProjectedFields, QueryConfig, QueryPrefixInput, QueryRangeInput,
+    StorageBackend, TransactWriteItem,
+};
 use crate::crud::Context;
-use crate::crud::shared::models::PaginateKey;
-use crate::crud::station::models::StationId;
-use crate::helpers::truncate_datetime_to_days;
-use models::PlayInDB;
-use provider::{DynamoDBProvider, ExclusiveStartKey, QueryRangeConfig, QueryRangeInput};
-
-pub struct CRUDPlay {
-    provider: DynamoDBProvider,
+use crate::crud::shared::cursor::{Cursor, Direction};
+use crate::crud::shared::models::SortOrder;
+use crate::crud::station::CRUDStation;
+use crate::crud::station::models::{LatestPlay, StationId, StationInDB};
+use crate::crud::track::CRUDTrack;
+use crate::crud::track::models::TrackId;
+use crate::helpers::{truncate_datetime_to_days, ziso_timestamp};
+use models::{PlayId, PlayInDB};
+use provider::{
+    BuildStationPlayDeletedUpdateInput, BuildStationPlayReassignedUpdateInput,
+    build_station_play_deleted_update, build_station_play_reassigned_update,
+};
+
+/// Queries one `list_plays` call makes at most, enough to skip a month of quiet days.
+const LIST_PLAYS_MAX_QUERIES: usize = 31;
+
+pub struct CRUDPlay<B: StorageBackend = Backend> {
+    context: Arc<Context<B>>,
+    crud_station: CRUDStation<B>,
+    crud_track: CRUDTrack<B>,
 }
 
-impl CRUDPlay {
-    pub fn new(context: Arc<Context>) -> Self {
+impl<B: StorageBackend> CRUDPlay<B> {
+    pub fn new(context: Arc<Context<B>>) -> Self {
         Self {
-            provider: DynamoDBProvider::new(context),
+            crud_station: CRUDStation::new(context.clone()),
+            crud_track: CRUDTrack::new(context.clone()),
+            context,
         }
     }
 
+    /// Lists the plays of a station between `start` and `end` in `order`, walking the day
+    /// partitions forward from `start` or backward from `end`. Successive partitions are
+    /// queried until `limit` plays are found or the other end of the range is reached. The
+    /// queries per call are capped, so a page can still be short while more plays follow.
+    ///
+    /// Fails with [`Error::InvalidInput`] if `limit` is below 1.
     pub async fn list_plays(
         &self,
         station_id: StationId,
         limit: i32,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-        next_key: Option<Ulid>,
-    ) -> Result<(Vec<PlayInDB>, Option<Ulid>)> {
-        let partition_datetime = if let Some(next_key) = next_key {
-            next_key.datetime().into()
+        order: SortOrder,
+        next_token: Option<&str>,
+    ) -> Result<(Vec<PlayInDB>, Option<String>)> {
+        if limit < 1 {
+            return Err(Error::InvalidInput("limit must be at least 1"));
+        }
+
+        let direction = Direction::from(order);
+        let mut cursor = if let Some(next_token) = next_token {
+            let cursor = self.context.cursor_codec.decode(next_token)?;
+            cursor.ensure(direction, |p| {
+                PlayInDB::parse_pk(station_id, p)
+                    .is_some_and(|day| start.date_naive() <= day && day <= end.date_naive())
+            })?;
+            cursor
         } else {
-            start
+            let first_partition = match direction {
+                Direction::Forward => start,
+                Direction::Backward => end,
+            };
+            Cursor::start_of(PlayInDB::get_pk(station_id, &first_partition), direction)
         };
 
-        let start_ulid = Ulid::from_parts(start.timestamp_millis().try_into()?, 0);
-        let end_ulid = Ulid::from_parts(end.timestamp_millis().try_into()?, u128::MAX);
+        let start_ulid = Ulid::from_parts(unix_timestamp_millis(&start)?, 0);
+        let end_ulid = Ulid::from_parts(unix_timestamp_millis(&end)?, u128::MAX);
+        let limit_items = usize::try_from(limit).unwrap_or(0);
 
-        let exclusive_start_key = if let Some(next_key) = next_key {
-            // assume no exclusive_start_key if next_key random part is 0
-            if next_key.random() != 0 {
-                let next_key_datetime: DateTime<Utc> = next_key.datetime().into();
-                let play_id = next_key.into();
+        let mut plays: Vec<PlayInDB> = vec![];
+        let mut queries = 0;
+        let next_cursor = loop {
+            let partition_day =
+                PlayInDB::parse_pk(station_id, &cursor.partition).expect("parse play partition");
 
-                Some(ExclusiveStartKey {
-                    pk: PlayInDB::get_pk(station_id, &next_key_datetime),
-                    sk: PlayInDB::get_sk(play_id),
-                })
+            let query_result = self
+                .context
+                .backend
+                .query_range(
+                    QueryRangeInput {
+                        pk: cursor.partition.clone(),
+                        start_sk: PlayInDB::get_sk_prefix() + &start_ulid.to_string(),
+                        end_sk: PlayInDB::get_sk_prefix() + &end_ulid.to_string(),
+                        scan_forward: cursor.direction.scan_forward(),
+                        exclusive_start_key: cursor.table_key(),
+                    },
+                    QueryConfig {
+                        limit: limit - i32::try_from(plays.len()).expect("page length into i32"),
+                        projected_fields: ProjectedFields::All,
+                    },
+                )
+                .await?;
+            plays.extend(serde_dynamo::from_items::<_, PlayInDB>(query_result.items)?);
+            queries += 1;
+
+            let next_cursor = if let Some(last_evaluated_key) = query_result.last_evaluated_key {
+                Some(Cursor::after(
+                    cursor.partition,
+                    last_evaluated_key,
+                    cursor.direction,
+                )?)
             } else {
-                None
+                let next_partition_day = match cursor.direction {
+                    Direction::Forward if partition_day < end.date_naive() => {
+                        partition_day.succ_opt()
+                    }
+                    Direction::Backward if partition_day > start.date_naive() => {
+                        partition_day.pred_opt()
+                    }
+                    _ => None,
+                };
+
+                next_partition_day.map(|next_partition_day| {
+                    let next_partition_datetime =
+                        next_partition_day.and_time(NaiveTime::MIN).and_utc();
+
+                    Cursor::start_of(
+                        PlayInDB::get_pk(station_id, &next_partition_datetime),
+                        cursor.direction,
+                    )
+                })
+            };
+
+            match next_cursor {
+                Some(next_cursor)
+                    if plays.len() < limit_items && queries < LIST_PLAYS_MAX_QUERIES =>
+                {
+                    cursor = next_cursor;
+                }
+                next_cursor => break next_cursor,
+            }
+        };
+
+        Ok((
+            plays,
+            next_cursor.map(|next_cursor| self.context.cursor_codec.encode(&next_cursor)),
+        ))
+    }
+
+    /// Deletes a play and takes it off the play counts of its track and station in one
+    /// transaction. The latest and first play of the station are replaced with the plays next
+    /// to the deleted one, and a track left without plays is deleted with its lookup items.
+    ///
+    /// Fails with [`Error::ConditionFailed`] if the station or track changed meanwhile.
+    pub async fn delete_play(&self, station_id: StationId, play_id: PlayId) -> Result<()> {
+        let station = self
+            .crud_station
+            .get_station_consistent(station_id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        let play = self
+            .get_play_consistent(station_id, play_id)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        let now = self.context.clock.now();
+        let removal = self
+            .crud_track
+            .prepare_play_removal(&station, play_id, play.track_id, now)
+            .await?;
+        let mut items = removal.items;
+
+        let is_latest_play = station
+            .latest_play
+            .as_ref()
+            .is_some_and(|latest_play| latest_play.id == play_id);
+        let is_first_play = station.first_play_id == Some(play_id);
+
+        let latest_play = if is_latest_play {
+            let until = station
+                .first_play_id
+                .map_or(station.created_ts, |first_play_id| {
+                    first_play_id.datetime().into()
+                });
+
+            match self
+                .find_adjacent_play(station_id, &play, false, until, is_first_play)
+                .await?
+            {
+                Some(latest_play) => Some(Some(self.to_latest_play(&station, latest_play).await?)),
+                None => Some(None),
             }
         } else {
             None
         };
 
-        let query_result = self
-            .provider
-            .query_range(
-                QueryRangeInput {
-                    pk: PlayInDB::get_pk(station_id, &partition_datetime),
-                    start_sk: PlayInDB::get_sk_prefix() + &start_ulid.to_string(),
-                    end_sk: PlayInDB::get_sk_prefix() + &end_ulid.to_string(),
-                    exclusive_start_key,
-                },
-                QueryRangeConfig { limit },
-            )
-            .await?;
+        let first_play_id = if is_first_play {
+            let until = station
+                .latest_play
+                .as_ref()
+                .map_or(now, |latest_play| latest_play.id.datetime().into());
 
-        let new_next_key = if let Some(last_evaluated_key) = query_result.last_evaluated_key {
-            let paginate_key: PaginateKey = serde_dynamo::from_item(last_evaluated_key)?;
             Some(
-                Ulid::from_string(
-                    paginate_key
-                        .sk
-                        .strip_prefix(&PlayInDB::get_sk_prefix())
-                        .expect("parse next key"),
-                )
-                .expect("next key into ulid"),
+                self.find_adjacent_play(station_id, &play, true, until, is_latest_play)
+                    .await?
+                    .map(|first_play| first_play.id),
             )
-        } else if !PlayInDB::is_same_partition(&partition_datetime, &end_ulid.datetime().into()) {
-            let next_partition_datetime = truncate_datetime_to_days(partition_datetime)
-                .expect("truncate partition datetime to days")
-                + Duration::days(1);
-
-            Some(Ulid::from_parts(
-                truncate_datetime_to_days(next_partition_datetime)
-                    .expect("truncate partition datetime to days")
-                    .timestamp_millis()
-                    .try_into()?,
-                0,
-            ))
         } else {
             None
         };
 
-        if let Some(items) = query_result.items {
-            Ok((serde_dynamo::from_items(items.to_vec())?, new_next_key))
+        // the play before the latest is unknown once the latest play is replaced
+        let remove_previous_play = is_latest_play
+            || station
+                .previous_play
+                .as_ref()
+                .is_some_and(|previous_play| previous_play.id == play_id);
+
+        items.push(TransactWriteItem::Update(
+            build_station_play_deleted_update(
+                self.context.backend.table_name(),
+                BuildStationPlayDeletedUpdateInput {
+                    pk: StationInDB::get_pk(),
+                    sk: StationInDB::get_sk(station_id),
+                    track_removed: removal.track_deleted,
+                    latest_play: latest_play
+                        .map(|latest_play| latest_play.map(serde_dynamo::to_item).transpose())
+                        .transpose()?,
+                    first_play_id: first_play_id
+                        .map(|first_play_id| first_play_id.map(|play_id| play_id.to_string())),
+                    remove_previous_play,
+                    update_timestamp: ziso_timestamp(&now),
+                    locked_timestamp: ziso_timestamp(&station.updated_ts),
+                },
+            )?,
+        ));
+
+        self.context.backend.transact_write_items(items).await
+    }
+
+    /// Points a play at another track, e.g. after the fetcher attributed it to the wrong song,
+    /// and moves it from the play count of its track to `new_track_id` in one transaction.
+    /// A track left without plays is deleted with its lookup items.
+    ///
+    /// Fails with [`Error::ConditionFailed`] if the station or either track changed meanwhile.
+    pub async fn reassign_play(
+        &self,
+        station_id: StationId,
+        play_id: PlayId,
+        new_track_id: TrackId,
+    ) -> Result<()> {
+        let station = self
+            .crud_station
+            .get_station_consistent(station_id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        let play = self
+            .get_play_consistent(station_id, play_id)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        let now = self.context.clock.now();
+        let reassignment = self
+            .crud_track
+            .prepare_play_reassignment(&station, play_id, play.track_id, new_track_id, now)
+            .await?;
+        let mut items = reassignment.items;
+
+        // the station plays keep their fetched artist and title, so a repeat of the same
+        // metadata stays on the play that was reassigned
+        let is_latest_play = station
+            .latest_play
+            .as_ref()
+            .is_some_and(|latest_play| latest_play.id == play_id);
+        let is_previous_play = station
+            .previous_play
+            .as_ref()
+            .is_some_and(|previous_play| previous_play.id == play_id);
+
+        items.push(TransactWriteItem::Update(
+            build_station_play_reassigned_update(
+                self.context.backend.table_name(),
+                BuildStationPlayReassignedUpdateInput {
+                    pk: StationInDB::get_pk(),
+                    sk: StationInDB::get_sk(station_id),
+                    track_removed: reassignment.track_deleted,
+                    latest_play_track_id: is_latest_play.then(|| new_track_id.to_string()),
+                    previous_play_track_id: is_previous_play.then(|| new_track_id.to_string()),
+                    update_timestamp: ziso_timestamp(&now),
+                    locked_timestamp: ziso_timestamp(&station.updated_ts),
+                },
+            )?,
+        ));
+
+        self.context.backend.transact_write_items(items).await
+    }
+
+    async fn get_play_consistent(
+        &self,
+        station_id: StationId,
+        play_id: PlayId,
+    ) -> Result<Option<PlayInDB>> {
+        let play_datetime: DateTime<Utc> = play_id.datetime().into();
+
+        let resp = self
+            .context
+            .backend
+            .get_item(
+                Key {
+                    pk: PlayInDB::get_pk(station_id, &play_datetime),
+                    sk: PlayInDB::get_sk(play_id),
+                },
+                GetItemConfig {
+                    consistent_read: true,
+                    projected_fields: ProjectedFields::All,
+                },
+            )
+            .await?;
+
+        if let Some(item) = resp {
+            Ok(Some(serde_dynamo::from_item(item)?))
         } else {
-            Ok((vec![], new_next_key))
+            Ok(None)
+        }
+    }
+
+    /// Finds the play of the station right after `play`, or right before it unless
+    /// `scan_forward`, walking the day partitions up to the day of `until`. With `is_last`
+    /// the caller already knows there is no such play.
+    async fn find_adjacent_play(
+        &self,
+        station_id: StationId,
+        play: &PlayInDB,
+        scan_forward: bool,
+        until: DateTime<Utc>,
+        is_last: bool,
+    ) -> Result<Option<PlayInDB>> {
+        if is_last {
+            return Ok(None);
+        }
+
+        let until_partition = PlayInDB::get_partition(&until);
+        let mut partition_datetime: DateTime<Utc> = play.id.datetime().into();
+        let mut exclusive_start_key = Some(Key {
+            pk: PlayInDB::get_pk(station_id, &partition_datetime),
+            sk: PlayInDB::get_sk(play.id),
+        });
+
+        loop {
+            let resp = self
+                .context
+                .backend
+                .query_prefix(
+                    QueryPrefixInput {
+                        pk: PlayInDB::get_pk(station_id, &partition_datetime),
+                        sk_prefix: PlayInDB::get_sk_prefix(),
+                        scan_forward,
+                        exclusive_start_key,
+                    },
+                    QueryConfig {
+                        limit: 1,
+                        projected_fields: ProjectedFields::All,
+                    },
+                )
+                .await?;
+
+            let plays: Vec<PlayInDB> = serde_dynamo::from_items(resp.items)?;
+            if let Some(play) = plays.into_iter().next() {
+                return Ok(Some(play));
+            }
+
+            if PlayInDB::get_partition(&partition_datetime) == until_partition {
+                return Ok(None);
+            }
+
+            let day = truncate_datetime_to_days(partition_datetime)
+                .expect("truncate partition datetime to days");
+            partition_datetime = if scan_forward {
+                day + Duration::days(1)
+            } else {
+                day - Duration::nanoseconds(1)
+            };
+            exclusive_start_key = None;
+        }
+    }
+
+    async fn to_latest_play(&self, station: &StationInDB, play: PlayInDB) -> Result<LatestPlay> {
+        let track = self
+            .crud_track
+            .get_track(station.id, play.track_id)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        Ok(LatestPlay {
+            id: play.id,
+            track_id: track.id,
+            artist: track.artist,
+            title: track.title,
+        })
+    }
+}
+
+fn unix_timestamp_millis(datetime: &DateTime<Utc>) -> Result<u64> {
+    datetime
+        .timestamp_millis()
+        .try_into()
+        .map_err(|_| Error::InvalidInput("datetime must not be before the unix epoch"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::MemoryBackend;
+    use crate::backend::testing::{FixedClock, TestPlay, list_all_plays_of_track, memory_context};
+    use crate::crud::logger::CRUDLogger;
+    use crate::crud::station::models::StationInDBCreate;
+
+    #[tokio::test]
+    async fn test_list_plays_local_day() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                timezone: Some(chrono_tz::Tz::Asia__Bangkok),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut play_ids = vec![];
+        for (artist, played_at) in [
+            ("artist a", "2024-01-01T16:30:00Z"),
+            ("artist b", "2024-01-01T17:30:00Z"),
+            ("artist c", "2024-01-01T23:00:00Z"),
+            ("artist d", "2024-01-02T01:00:00Z"),
+            ("artist e", "2024-01-02T16:30:00Z"),
+            ("artist f", "2024-01-02T17:30:00Z"),
+        ] {
+            let result = crud_logger
+                .add_play_at(
+                    &mut station,
+                    TestPlay::new(artist, "title"),
+                    played_at.parse().unwrap(),
+                )
+                .await
+                .unwrap();
+            play_ids.push(result.play_id);
+        }
+
+        // the day in Bangkok spans two day partitions
+        let (start, end) = station
+            .get_day_range("2024-01-02".parse().unwrap())
+            .unwrap();
+
+        let mut listed = vec![];
+        let mut next_token = None;
+        loop {
+            let (plays, page_next_token) = crud_play
+                .list_plays(
+                    station.id,
+                    2,
+                    start,
+                    end,
+                    SortOrder::Asc,
+                    next_token.as_deref(),
+                )
+                .await
+                .unwrap();
+            listed.extend(plays.into_iter().map(|play| play.id));
+
+            next_token = page_next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        assert_eq!(listed, play_ids[1..5]);
+    }
+
+    #[tokio::test]
+    async fn test_list_plays_fills_page_across_days() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let start: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+        let mut play_ids = vec![];
+        for (artist, days) in [
+            ("artist a", 0),
+            ("artist b", 3),
+            ("artist c", 5),
+            ("artist d", 60),
+        ] {
+            let result = crud_logger
+                .add_play_at(
+                    &mut station,
+                    TestPlay::new(artist, "title"),
+                    start + Duration::days(days) + Duration::hours(12),
+                )
+                .await
+                .unwrap();
+            play_ids.push(result.play_id);
+        }
+
+        // quiet days in between are skipped within one call
+        let (plays, next_token) = crud_play
+            .list_plays(
+                station.id,
+                2,
+                start,
+                start + Duration::days(7),
+                SortOrder::Asc,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            plays.iter().map(|play| play.id).collect::<Vec<_>>(),
+            play_ids[..2]
+        );
+
+        let (plays, next_token) = crud_play
+            .list_plays(
+                station.id,
+                2,
+                start,
+                start + Duration::days(7),
+                SortOrder::Asc,
+                next_token.as_deref(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            plays.iter().map(|play| play.id).collect::<Vec<_>>(),
+            play_ids[2..3]
+        );
+        assert!(next_token.is_none());
+
+        // a long quiet stretch ends the page early, the next page continues after it
+        let end = start + Duration::days(90);
+        let (plays, next_token) = crud_play
+            .list_plays(
+                station.id,
+                10,
+                start + Duration::days(6),
+                end,
+                SortOrder::Asc,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(plays.is_empty());
+
+        let (plays, _) = crud_play
+            .list_plays(
+                station.id,
+                10,
+                start + Duration::days(6),
+                end,
+                SortOrder::Asc,
+                next_token.as_deref(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            plays.iter().map(|play| play.id).collect::<Vec<_>>(),
+            play_ids[3..]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_plays_rejects_invalid_limit() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let start: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+        for limit in [0, -1] {
+            assert!(matches!(
+                crud_play
+                    .list_plays(
+                        station.id,
+                        limit,
+                        start,
+                        start + Duration::days(1),
+                        SortOrder::Asc,
+                        None,
+                    )
+                    .await,
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_plays_desc() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let start: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+        let mut play_ids = vec![];
+        for (artist, hours) in [
+            ("artist a", 1),
+            ("artist b", 2),
+            ("artist c", 30),
+            ("artist d", 80),
+        ] {
+            let result = crud_logger
+                .add_play_at(
+                    &mut station,
+                    TestPlay::new(artist, "title"),
+                    start + Duration::hours(hours),
+                )
+                .await
+                .unwrap();
+            play_ids.push(result.play_id);
+        }
+
+        let end = start + Duration::hours(72);
+        let mut pages = vec![];
+        let mut next_token = None;
+        loop {
+            let (plays, page_next_token) = crud_play
+                .list_plays(
+                    station.id,
+                    2,
+                    start,
+                    end,
+                    SortOrder::Desc,
+                    next_token.as_deref(),
+                )
+                .await
+                .unwrap();
+            pages.push(plays.into_iter().map(|play| play.id).collect::<Vec<_>>());
+
+            next_token = page_next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        // newest first from `end`, leaving out the play after it
+        assert_eq!(pages.concat(), [play_ids[2], play_ids[1], play_ids[0]]);
+        assert_eq!(pages[0], [play_ids[2], play_ids[1]]);
+
+        // a token only continues a listing in the same order
+        let (_, next_token) = crud_play
+            .list_plays(station.id, 1, start, end, SortOrder::Desc, None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            crud_play
+                .list_plays(
+                    station.id,
+                    1,
+                    start,
+                    end,
+                    SortOrder::Asc,
+                    next_token.as_deref(),
+                )
+                .await,
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_play() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let start = Utc::now() - Duration::days(3);
+        let mut results = vec![];
+        for (artist, hours) in [
+            ("artist a", 0),
+            ("artist b", 1),
+            ("artist a", 2),
+            ("artist c", 50),
+        ] {
+            results.push(
+                crud_logger
+                    .add_play_at(
+                        &mut station,
+                        TestPlay::new(artist, "title"),
+                        start + Duration::hours(hours),
+                    )
+                    .await
+                    .unwrap(),
+            );
         }
+
+        // the latest play was the only play of its track, the play two days before takes over
+        crud_play
+            .delete_play(station.id, results[3].play_id)
+            .await
+            .unwrap();
+
+        assert!(
+            crud_track
+                .get_track(station.id, results[3].track_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            crud_track
+                .get_track_by_metadata(&station, "artist c", "title")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station.play_count, 3);
+        assert_eq!(stored_station.track_count, 2);
+        assert_eq!(
+            stored_station.latest_play,
+            Some(LatestPlay {
+                id: results[2].play_id,
+                track_id: results[2].track_id,
+                artist: "artist a".to_owned(),
+                title: "title".to_owned(),
+            })
+        );
+        assert_eq!(stored_station.previous_play, None);
+
+        // the first play moves on to the next play of the station
+        crud_play
+            .delete_play(station.id, results[0].play_id)
+            .await
+            .unwrap();
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station.play_count, 2);
+        assert_eq!(stored_station.track_count, 2);
+        assert_eq!(stored_station.first_play_id, Some(results[1].play_id));
+
+        let track_a = crud_track
+            .get_track(station.id, results[0].track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_a.play_count, 1);
+        assert_eq!(track_a.latest_play_id, Some(results[2].play_id));
+
+        assert!(matches!(
+            crud_play.delete_play(station.id, results[0].play_id).await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_play_removes_redirects_to_deleted_track() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let start = Utc::now() - Duration::days(3);
+        let mut results = vec![];
+        for (artist, hours) in [
+            ("artist a", 0),
+            ("artist b", 1),
+            ("artist c", 2),
+            ("artist d", 3),
+            ("artist e", 4),
+        ] {
+            results.push(
+                crud_logger
+                    .add_play_at(
+                        &mut station,
+                        TestPlay::new(artist, "title"),
+                        start + Duration::hours(hours),
+                    )
+                    .await
+                    .unwrap(),
+            );
+        }
+        let [a, b, c, d, e] = [0, 1, 2, 3, 4].map(|index| results[index].track_id);
+
+        // c is merged into b, which is merged into a, and e into d
+        for (keep, absorb) in [(b, c), (a, b), (d, e)] {
+            crud_track
+                .merge_tracks(&mut station, keep, absorb)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            crud_track.get_track_redirect(station.id, c).await.unwrap(),
+            Some(b)
+        );
+
+        for result in &results[..3] {
+            crud_play
+                .delete_play(station.id, result.play_id)
+                .await
+                .unwrap();
+        }
+
+        assert!(crud_track.get_track(station.id, a).await.unwrap().is_none());
+        for track_id in [b, c] {
+            assert_eq!(
+                crud_track
+                    .get_track_redirect(station.id, track_id)
+                    .await
+                    .unwrap(),
+                None
+            );
+        }
+        assert_eq!(
+            crud_track.get_track_redirect(station.id, e).await.unwrap(),
+            Some(d)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reassign_play() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let start = Utc::now() - Duration::days(3);
+        let mut results = vec![];
+        for (artist, hours) in [
+            ("artist a", 0),
+            ("artist b", 1),
+            ("artist a", 2),
+            ("artist c", 3),
+        ] {
+            results.push(
+                crud_logger
+                    .add_play_at(
+                        &mut station,
+                        TestPlay::new(artist, "title"),
+                        start + Duration::hours(hours),
+                    )
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        // the latest play of track a moves to track b
+        crud_play
+            .reassign_play(station.id, results[2].play_id, results[1].track_id)
+            .await
+            .unwrap();
+
+        let track_a = crud_track
+            .get_track(station.id, results[0].track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_a.play_count, 1);
+        assert_eq!(track_a.latest_play_id, Some(results[0].play_id));
+
+        let track_b = crud_track
+            .get_track(station.id, results[1].track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_b.play_count, 2);
+        assert_eq!(track_b.latest_play_id, Some(results[2].play_id));
+
+        let play = crud_play
+            .get_play_consistent(station.id, results[2].play_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(play.track_id, results[1].track_id);
+
+        // the only play of track c moves to track a, track c is removed
+        crud_play
+            .reassign_play(station.id, results[3].play_id, results[0].track_id)
+            .await
+            .unwrap();
+
+        assert!(
+            crud_track
+                .get_track(station.id, results[3].track_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station.play_count, 4);
+        assert_eq!(stored_station.track_count, 2);
+        assert_eq!(
+            stored_station
+                .latest_play
+                .map(|latest_play| latest_play.track_id),
+            Some(results[0].track_id)
+        );
+
+        assert!(matches!(
+            crud_play
+                .reassign_play(station.id, results[3].play_id, results[0].track_id)
+                .await,
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reassign_play_to_newer_track() {
+        let datetime = |value: &str| DateTime::parse_from_rfc3339(value).unwrap().to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(datetime("2001-06-15T00:00:00Z"))),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context.clone());
+        let crud_play = CRUDPlay::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut results = vec![];
+        for (artist, played_at) in [
+            ("artist a", "2001-01-10T00:00:00Z"),
+            ("artist b", "2001-05-10T00:00:00Z"),
+        ] {
+            results.push(
+                crud_logger
+                    .add_play_at(
+                        &mut station,
+                        TestPlay::new(artist, "title"),
+                        datetime(played_at),
+                    )
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        // the play from January moves to track b, which was created in May
+        crud_play
+            .reassign_play(station.id, results[0].play_id, results[1].track_id)
+            .await
+            .unwrap();
+
+        let track_b = crud_track
+            .get_track(station.id, results[1].track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_b.play_count, 2);
+        assert_eq!(
+            track_b.earliest_play_ts,
+            Some(datetime("2001-01-10T00:00:00Z"))
+        );
+        assert_eq!(
+            list_all_plays_of_track(&crud_track, station.id, results[1].track_id).await,
+            [results[1].play_id, results[0].play_id]
+        );
+
+        // deleting the newer play finds the moved play as the latest play of track b
+        crud_play
+            .delete_play(station.id, results[1].play_id)
+            .await
+            .unwrap();
+        let track_b = crud_track
+            .get_track(station.id, results[1].track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_b.latest_play_id, Some(results[0].play_id));
     }
 }
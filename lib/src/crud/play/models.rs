@@ -1,10 +1,11 @@
 use std::ops::Deref;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 use utoipa::ToSchema;
 
+use crate::clock::IdGenerator;
 use crate::crud::station::models::StationId;
 use crate::crud::track::models::TrackId;
 
@@ -50,6 +51,13 @@ impl PlayInDB {
         format!("STATION#{}", station_id.0)
     }
 
+    /// Returns the day of a play partition `pk` of the station.
+    pub(crate) fn parse_pk(station_id: StationId, pk: &str) -> Option<NaiveDate> {
+        let day = pk.strip_prefix(&format!("STATION#{}#PLAYS#", station_id.0))?;
+
+        NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()
+    }
+
     pub(crate) fn get_sk(play_id: PlayId) -> String {
         format!("PLAY#{}", play_id.0)
     }
@@ -67,25 +75,24 @@ impl PlayInDB {
         datetime.format("%Y-%m-%d").to_string()
     }
 
-    pub(crate) fn is_same_partition(left: &DateTime<Utc>, right: &DateTime<Utc>) -> bool {
-        let left_partition = Self::get_partition(left);
-        let right_partition = Self::get_partition(right);
-
-        left_partition == right_partition
-    }
-
-    pub fn new(station_id: StationId, track_id: TrackId) -> Self {
-        let now = Utc::now();
-        let play_id = Ulid::generate().into();
+    /// Creates a play that was played at `played_at`, which decides its day partition and
+    /// the month of its track index partition.
+    pub fn new(
+        station_id: StationId,
+        track_id: TrackId,
+        played_at: DateTime<Utc>,
+        id_generator: &dyn IdGenerator,
+    ) -> Self {
+        let play_id = id_generator.generate(played_at).into();
 
         PlayInDB {
-            pk: Self::get_pk(station_id, &now),
+            pk: Self::get_pk(station_id, &played_at),
             sk: Self::get_sk(play_id),
-            gsi1pk: Self::get_gsi1pk(track_id, &now),
+            gsi1pk: Self::get_gsi1pk(track_id, &played_at),
             id: play_id,
             track_id,
-            created_ts: now,
-            updated_ts: now,
+            created_ts: played_at,
+            updated_ts: played_at,
         }
     }
 }
@@ -1,60 +1,159 @@
-use std::sync::Arc;
+use std::collections::HashMap;
 
-use aws_sdk_dynamodb::error::SdkError;
-use aws_sdk_dynamodb::operation::query::{QueryError, QueryOutput};
-use aws_sdk_dynamodb::types::{AttributeValue, Select};
-use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_sdk_dynamodb::error::BuildError;
+use aws_sdk_dynamodb::types::{AttributeValue, Update};
 
-use crate::crud::Context;
+pub(super) struct BuildStationPlayDeletedUpdateInput {
+    pub pk: String,
+    pub sk: String,
+    /// The deleted play was the only play of its track, which is removed as well.
+    pub track_removed: bool,
+    /// Replaces the latest play if the deleted play was the latest, `Some(None)` if the
+    /// station has no plays left.
+    pub latest_play: Option<Option<HashMap<String, AttributeValue>>>,
+    /// Replaces the first play if the deleted play was the first.
+    pub first_play_id: Option<Option<String>>,
+    pub remove_previous_play: bool,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
+}
+
+/// Takes a deleted play off the station counts and replaces any reference to it.
+pub fn build_station_play_deleted_update(
+    table_name: &str,
+    input: BuildStationPlayDeletedUpdateInput,
+) -> Result<Update, BuildError> {
+    let mut update_builder = Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :station_locked_ts")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(
+            ":station_locked_ts",
+            AttributeValue::S(input.locked_timestamp),
+        )
+        .expression_attribute_values(":inc", AttributeValue::N("1".to_owned()));
+
+    let mut update_expression_parts = vec!["updated_ts = :ts", "play_count = play_count - :inc"];
+    if input.track_removed {
+        update_expression_parts.push("track_count = track_count - :inc");
+    }
+
+    if let Some(latest_play) = input.latest_play {
+        update_expression_parts.push("latest_play = :latest_play");
+        update_builder = update_builder.expression_attribute_values(
+            ":latest_play",
+            latest_play.map_or(AttributeValue::Null(true), AttributeValue::M),
+        );
+    }
+
+    if let Some(first_play_id) = input.first_play_id {
+        update_expression_parts.push("first_play_id = :first_play_id");
+        update_builder = update_builder.expression_attribute_values(
+            ":first_play_id",
+            first_play_id.map_or(AttributeValue::Null(true), AttributeValue::S),
+        );
+    }
+
+    let mut update_expression = format!("SET {}", update_expression_parts.join(", "));
+    if input.remove_previous_play {
+        update_expression.push_str(" REMOVE previous_play");
+    }
 
-pub(super) struct DynamoDBProvider {
-    context: Arc<Context>,
+    update_builder.update_expression(update_expression).build()
 }
 
-pub(super) struct ExclusiveStartKey {
+pub(super) struct BuildStationPlayReassignedUpdateInput {
     pub pk: String,
     pub sk: String,
+    /// The play was the only play of its previous track, which is removed.
+    pub track_removed: bool,
+    /// Replaces the track of the station latest play, if that was the reassigned play.
+    pub latest_play_track_id: Option<String>,
+    /// Same for the play before the latest play.
+    pub previous_play_track_id: Option<String>,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
 }
 
-pub(super) struct QueryRangeInput {
-    pub pk: String,
-    pub start_sk: String,
-    pub end_sk: String,
-    pub exclusive_start_key: Option<ExclusiveStartKey>,
-}
+pub fn build_station_play_reassigned_update(
+    table_name: &str,
+    input: BuildStationPlayReassignedUpdateInput,
+) -> Result<Update, BuildError> {
+    let mut update_builder = Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :station_locked_ts")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(
+            ":station_locked_ts",
+            AttributeValue::S(input.locked_timestamp),
+        );
+
+    let mut update_expression_parts = vec!["updated_ts = :ts"];
+    if input.track_removed {
+        update_expression_parts.push("track_count = track_count - :inc");
+        update_builder =
+            update_builder.expression_attribute_values(":inc", AttributeValue::N("1".to_owned()));
+    }
+
+    if let Some(track_id) = input.latest_play_track_id {
+        update_expression_parts.push("latest_play.track_id = :track_id");
+        update_builder =
+            update_builder.expression_attribute_values(":track_id", AttributeValue::S(track_id));
+    }
 
-pub(super) struct QueryRangeConfig {
-    pub limit: i32,
+    if let Some(track_id) = input.previous_play_track_id {
+        update_expression_parts.push("previous_play.track_id = :previous_track_id");
+        update_builder = update_builder
+            .expression_attribute_values(":previous_track_id", AttributeValue::S(track_id));
+    }
+
+    update_builder
+        .update_expression(format!("SET {}", update_expression_parts.join(", ")))
+        .build()
 }
 
-impl DynamoDBProvider {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
-    }
-
-    pub async fn query_range(
-        &self,
-        input: QueryRangeInput,
-        config: QueryRangeConfig,
-    ) -> Result<QueryOutput, SdkError<QueryError, HttpResponse>> {
-        let mut query = self
-            .context
-            .db_client
-            .query()
-            .table_name(&self.context.db_table)
-            .key_condition_expression("pk = :pk AND sk BETWEEN :start_sk AND :end_sk")
-            .expression_attribute_values(":pk", AttributeValue::S(input.pk))
-            .expression_attribute_values(":start_sk", AttributeValue::S(input.start_sk))
-            .expression_attribute_values(":end_sk", AttributeValue::S(input.end_sk))
-            .select(Select::AllAttributes)
-            .limit(config.limit);
-
-        if let Some(exclusive_start_key) = input.exclusive_start_key {
-            query = query
-                .exclusive_start_key("pk", AttributeValue::S(exclusive_start_key.pk))
-                .exclusive_start_key("sk", AttributeValue::S(exclusive_start_key.sk));
-        }
-
-        query.send().await
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_station_play_deleted_update_last_play() {
+        let update = build_station_play_deleted_update(
+            "tablename",
+            BuildStationPlayDeletedUpdateInput {
+                pk: "pkvalue".to_owned(),
+                sk: "skvalue".to_owned(),
+                track_removed: true,
+                latest_play: Some(None),
+                first_play_id: Some(None),
+                remove_previous_play: true,
+                update_timestamp: "12345".to_owned(),
+                locked_timestamp: "67890".to_owned(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            update.update_expression(),
+            "SET updated_ts = :ts, play_count = play_count - :inc, track_count = track_count - :inc, latest_play = :latest_play, first_play_id = :first_play_id REMOVE previous_play"
+        );
+        assert_eq!(
+            update.condition_expression(),
+            Some("updated_ts = :station_locked_ts")
+        );
+
+        let values = update.expression_attribute_values().unwrap();
+        assert_eq!(
+            values.get(":latest_play"),
+            Some(&AttributeValue::Null(true))
+        );
+        assert_eq!(
+            values.get(":first_play_id"),
+            Some(&AttributeValue::Null(true))
+        );
     }
 }
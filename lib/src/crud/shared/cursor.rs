@@ -0,0 +1,273 @@
+//! Pagination cursors, handed to callers of the list methods as opaque `next_token` strings.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use ulid::Ulid;
+
+use crate::backend::{Gsi1Key, Item, Key};
+use crate::crud::shared::models::SortOrder;
+use crate::{Error, Result};
+
+/// Bumped whenever the cursor payload changes, tokens of other versions are rejected.
+const CURSOR_VERSION: u8 = 1;
+/// Bytes of the HMAC-SHA256 tag appended to the payload.
+const TAG_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Direction {
+    #[serde(rename = "f")]
+    Forward,
+    #[serde(rename = "b")]
+    Backward,
+}
+
+impl Direction {
+    pub(crate) fn scan_forward(self) -> bool {
+        self == Self::Forward
+    }
+}
+
+impl From<SortOrder> for Direction {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Asc => Self::Forward,
+            SortOrder::Desc => Self::Backward,
+        }
+    }
+}
+
+/// Key of the item a query continues after, `gsi1pk` is set for `gsi1` queries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CursorKey {
+    pk: String,
+    sk: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gsi1pk: Option<String>,
+}
+
+/// Position of a listing: the partition to query next, the item to continue after within
+/// it, and the direction the listing walks partitions and items in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Cursor {
+    #[serde(rename = "p")]
+    pub(crate) partition: String,
+    #[serde(rename = "k", default, skip_serializing_if = "Option::is_none")]
+    exclusive_start_key: Option<CursorKey>,
+    #[serde(rename = "d")]
+    pub(crate) direction: Direction,
+}
+
+impl Cursor {
+    /// Starts at the first item of `partition` in `direction`.
+    pub(crate) fn start_of(partition: String, direction: Direction) -> Self {
+        Self {
+            partition,
+            exclusive_start_key: None,
+            direction,
+        }
+    }
+
+    /// Continues after the `LastEvaluatedKey` of a query of `partition`.
+    pub(crate) fn after(
+        partition: String,
+        last_evaluated_key: Item,
+        direction: Direction,
+    ) -> Result<Self> {
+        Ok(Self {
+            partition,
+            exclusive_start_key: Some(serde_dynamo::from_item(last_evaluated_key)?),
+            direction,
+        })
+    }
+
+    /// Rejects a decoded cursor that walks in another direction, or whose partition does
+    /// not belong to the listing it was passed to.
+    pub(crate) fn ensure(
+        &self,
+        direction: Direction,
+        is_listed_partition: impl FnOnce(&str) -> bool,
+    ) -> Result<()> {
+        let key_in_partition = self
+            .exclusive_start_key
+            .as_ref()
+            .is_none_or(|key| key.gsi1pk.as_deref().unwrap_or(key.pk.as_str()) == self.partition);
+
+        if self.direction == direction && key_in_partition && is_listed_partition(&self.partition) {
+            Ok(())
+        } else {
+            Err(invalid_token())
+        }
+    }
+
+    pub(crate) fn table_key(&self) -> Option<Key> {
+        self.exclusive_start_key.as_ref().map(|key| Key {
+            pk: key.pk.clone(),
+            sk: key.sk.clone(),
+        })
+    }
+
+    pub(crate) fn gsi1_key(&self) -> Option<Gsi1Key> {
+        self.exclusive_start_key.as_ref().map(|key| Gsi1Key {
+            gsi1pk: self.partition.clone(),
+            sk: key.sk.clone(),
+            pk: key.pk.clone(),
+        })
+    }
+}
+
+/// Encodes cursors as versioned base64url tokens signed with HMAC-SHA256, so a modified
+/// token is rejected instead of querying from wherever it points.
+pub(crate) struct CursorCodec {
+    secret: Vec<u8>,
+}
+
+impl Default for CursorCodec {
+    /// Signs with a secret random to this process, so tokens are rejected by any other.
+    fn default() -> Self {
+        let secret: Vec<u8> = [Ulid::generate(), Ulid::generate()]
+            .iter()
+            .flat_map(|ulid| ulid.random().to_be_bytes())
+            .collect();
+
+        Self::new(&secret)
+    }
+}
+
+impl CursorCodec {
+    pub(crate) fn new(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.to_vec(),
+        }
+    }
+
+    fn mac(&self, data: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(data);
+        mac
+    }
+
+    pub(crate) fn encode(&self, cursor: &Cursor) -> String {
+        let mut data = vec![CURSOR_VERSION];
+        serde_json::to_writer(&mut data, cursor).expect("serialize cursor");
+
+        let tag = self.mac(&data).finalize().into_bytes();
+        data.extend_from_slice(&tag[..TAG_LENGTH]);
+
+        URL_SAFE_NO_PAD.encode(data)
+    }
+
+    /// Fails with [`Error::InvalidInput`] if the token was not encoded by this codec, was
+    /// modified, or has another version.
+    pub(crate) fn decode(&self, token: &str) -> Result<Cursor> {
+        let data = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid_token())?;
+        if data.len() <= TAG_LENGTH {
+            return Err(invalid_token());
+        }
+
+        let (signed, tag) = data.split_at(data.len() - TAG_LENGTH);
+        self.mac(signed)
+            .verify_truncated_left(tag)
+            .map_err(|_| invalid_token())?;
+
+        match signed.split_first() {
+            Some((&CURSOR_VERSION, payload)) => {
+                serde_json::from_slice(payload).map_err(|_| invalid_token())
+            }
+            _ => Err(Error::InvalidInput("unsupported next_token version")),
+        }
+    }
+}
+
+fn invalid_token() -> Error {
+    Error::InvalidInput("invalid next_token")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use aws_sdk_dynamodb::types::AttributeValue;
+
+    fn cursor() -> Cursor {
+        let last_evaluated_key = Item::from([
+            ("pk".to_owned(), AttributeValue::S("pkvalue".to_owned())),
+            ("sk".to_owned(), AttributeValue::S("skvalue".to_owned())),
+        ]);
+
+        Cursor::after("pkvalue".to_owned(), last_evaluated_key, Direction::Forward).unwrap()
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let codec = CursorCodec::new(b"secret");
+        let token = codec.encode(&cursor());
+
+        assert!(
+            token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        assert_eq!(codec.decode(&token).unwrap(), cursor());
+        assert_eq!(
+            cursor().table_key(),
+            Some(Key {
+                pk: "pkvalue".to_owned(),
+                sk: "skvalue".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_cursor_decode_tampered() {
+        let codec = CursorCodec::new(b"secret");
+        let token = codec.encode(&cursor());
+
+        let mut data = URL_SAFE_NO_PAD.decode(&token).unwrap();
+        let payload = String::from_utf8(data[1..data.len() - TAG_LENGTH].to_vec()).unwrap();
+        let tampered_payload = payload.replace("skvalue", "skother");
+        data.splice(1..data.len() - TAG_LENGTH, tampered_payload.into_bytes());
+        let tampered_token = URL_SAFE_NO_PAD.encode(data);
+
+        for token in [tampered_token.as_str(), "", "not a token", &token[1..]] {
+            assert!(matches!(codec.decode(token), Err(Error::InvalidInput(_))));
+        }
+
+        // tokens are only valid with the secret they were signed with
+        assert!(matches!(
+            CursorCodec::new(b"other").decode(&token),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_cursor_default_secret() {
+        let token = CursorCodec::default().encode(&cursor());
+
+        // every default codec has a secret of its own
+        assert!(matches!(
+            CursorCodec::default().decode(&token),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_cursor_ensure() {
+        let cursor = cursor();
+
+        assert!(
+            cursor
+                .ensure(Direction::Forward, |p| p == "pkvalue")
+                .is_ok()
+        );
+        assert!(
+            cursor
+                .ensure(Direction::Backward, |p| p == "pkvalue")
+                .is_err()
+        );
+        assert!(cursor.ensure(Direction::Forward, |p| p == "other").is_err());
+    }
+}
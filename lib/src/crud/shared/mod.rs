@@ -1 +1,2 @@
+pub(crate) mod cursor;
 pub mod models;
@@ -1,16 +1,13 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
-pub(crate) struct PaginateKey {
-    pub(crate) pk: String,
-    pub(crate) sk: String,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
-pub(crate) struct Gsi1PaginateKey {
-    pub(crate) pk: String,
-    pub(crate) gsi1pk: String,
-    pub(crate) sk: String,
+/// Order of a listing, by time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Oldest first.
+    #[default]
+    Asc,
+    /// Newest first.
+    Desc,
 }
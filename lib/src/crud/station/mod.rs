@@ -3,66 +3,661 @@ mod provider;
 
 use std::sync::Arc;
 
-use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
 
+use crate::{Error, Result};
+
+use crate::backend::{
+    Backend, GetItemConfig, Key, ProjectedFields, QueryConfig, QueryPrefixInput, StorageBackend,
+    TransactWriteItem,
+};
 use crate::crud::Context;
-use models::{StationId, StationInDB, StationInDBCreate};
-use provider::{DynamoDBProvider, GetItemInput, PutItemInput, QueryPrefixConfig, QueryPrefixInput};
+use crate::crud::play::models::PlayInDB;
+use crate::crud::shared::cursor::{Cursor, Direction};
+use crate::crud::track::CRUDTrack;
+use crate::helpers::{truncate_datetime_to_days, ziso_timestamp};
+use models::{
+    StationDeleteMode, StationDeleteSummary, StationId, StationInDB, StationInDBCreate,
+    StationInDBUpdate, validate_flap_window,
+};
+use provider::{
+    BuildStationDeleteInput, BuildStationDetailsUpdateInput, build_delete, build_station_delete,
+    build_station_details_update,
+};
+
+/// Items deleted per transaction while deleting a station, and plays read per page.
+const DELETE_BATCH_SIZE: usize = 25;
 
-pub struct CRUDStation {
-    provider: DynamoDBProvider,
+pub struct CRUDStation<B: StorageBackend = Backend> {
+    context: Arc<Context<B>>,
+    crud_track: CRUDTrack<B>,
 }
 
-impl CRUDStation {
-    pub fn new(context: Arc<Context>) -> Self {
+impl<B: StorageBackend> CRUDStation<B> {
+    pub fn new(context: Arc<Context<B>>) -> Self {
         Self {
-            provider: DynamoDBProvider::new(context),
+            crud_track: CRUDTrack::new(context.clone()),
+            context,
         }
     }
 
     pub async fn get_station(&self, station_id: StationId) -> Result<Option<StationInDB>> {
+        self.get_station_internal(station_id, false).await
+    }
+
+    /// Reads the station with strong consistency, for callers about to write with the
+    /// station optimistic lock.
+    pub(crate) async fn get_station_consistent(
+        &self,
+        station_id: StationId,
+    ) -> Result<Option<StationInDB>> {
+        self.get_station_internal(station_id, true).await
+    }
+
+    async fn get_station_internal(
+        &self,
+        station_id: StationId,
+        consistent_read: bool,
+    ) -> Result<Option<StationInDB>> {
         let resp = self
-            .provider
-            .get_item(GetItemInput {
-                pk: StationInDB::get_pk(),
-                sk: StationInDB::get_sk(station_id),
-            })
+            .context
+            .backend
+            .get_item(
+                Key {
+                    pk: StationInDB::get_pk(),
+                    sk: StationInDB::get_sk(station_id),
+                },
+                GetItemConfig {
+                    consistent_read,
+                    projected_fields: ProjectedFields::All,
+                },
+            )
             .await?;
 
-        if let Some(item) = resp.item {
+        if let Some(item) = resp {
             Ok(Some(serde_dynamo::from_item(item)?))
         } else {
             Ok(None)
         }
     }
 
-    pub async fn list_stations(&self, limit: i32) -> Result<Vec<StationInDB>> {
+    /// Lists stations in order of creation, `next_token` continues the listing where the
+    /// previous page ended.
+    pub async fn list_stations(
+        &self,
+        limit: i32,
+        next_token: Option<&str>,
+    ) -> Result<(Vec<StationInDB>, Option<String>)> {
+        let partition = StationInDB::get_pk();
+        let cursor = if let Some(next_token) = next_token {
+            let cursor = self.context.cursor_codec.decode(next_token)?;
+            cursor.ensure(Direction::Forward, |p| p == partition)?;
+            cursor
+        } else {
+            Cursor::start_of(partition, Direction::Forward)
+        };
+
         let resp = self
-            .provider
+            .context
+            .backend
             .query_prefix(
                 QueryPrefixInput {
-                    pk: StationInDB::get_pk(),
+                    pk: cursor.partition.clone(),
                     sk_prefix: StationInDB::get_sk_prefix(),
+                    scan_forward: cursor.direction.scan_forward(),
+                    exclusive_start_key: cursor.table_key(),
+                },
+                QueryConfig {
+                    limit,
+                    projected_fields: ProjectedFields::All,
                 },
-                QueryPrefixConfig { limit },
             )
             .await?;
 
-        let items = resp.items().to_vec();
-        let stations: Vec<StationInDB> = serde_dynamo::from_items(items)?;
+        let next_token = if let Some(last_evaluated_key) = resp.last_evaluated_key {
+            let next_cursor =
+                Cursor::after(cursor.partition, last_evaluated_key, cursor.direction)?;
+            Some(self.context.cursor_codec.encode(&next_cursor))
+        } else {
+            None
+        };
 
-        Ok(stations)
+        let stations: Vec<StationInDB> = serde_dynamo::from_items(resp.items)?;
+
+        Ok((stations, next_token))
     }
 
-    pub async fn create_station(&self, station_create: StationInDBCreate) -> Result<StationInDB> {
-        let station: StationInDB = station_create.into();
+    /// Creates a station. Fails with [`Error::InvalidInput`] if the profile cannot be stored
+    /// or a rule has an invalid regex.
+    pub async fn create_station(
+        &self,
+        mut station_create: StationInDBCreate,
+    ) -> Result<StationInDB> {
+        station_create.profile = station_create.profile.validate()?;
+        for rule in &station_create.not_song_rules {
+            rule.validate()?;
+        }
+        for rule in &station_create.ignore_rules {
+            rule.validate()?;
+        }
+        validate_flap_window(station_create.flap_window_seconds)?;
 
-        self.provider
-            .put_item(PutItemInput {
-                item: serde_dynamo::to_item(station.clone())?,
-            })
+        let station = StationInDB::new(
+            station_create,
+            self.context.clock.now(),
+            self.context.id_generator.as_ref(),
+        );
+
+        self.context
+            .backend
+            .put_item(serde_dynamo::to_item(station.clone())?)
             .await?;
 
         Ok(station)
     }
+
+    /// Replaces the editable details of a station. Fails with [`Error::ConditionFailed`] if
+    /// the station changed since `station` was read, or [`Error::InvalidInput`] if the
+    /// profile cannot be stored or a rule has an invalid regex.
+    pub async fn update_station(
+        &self,
+        station: &StationInDB,
+        mut update: StationInDBUpdate,
+    ) -> Result<StationInDB> {
+        update.profile = update.profile.validate()?;
+        for rule in &update.not_song_rules {
+            rule.validate()?;
+        }
+        for rule in &update.ignore_rules {
+            rule.validate()?;
+        }
+        validate_flap_window(update.flap_window_seconds)?;
+        let now = self.context.clock.now();
+
+        let station_update = build_station_details_update(
+            self.context.backend.table_name(),
+            BuildStationDetailsUpdateInput {
+                pk: StationInDB::get_pk(),
+                sk: StationInDB::get_sk(station.id),
+                name: update.name.clone(),
+                location: serde_dynamo::to_attribute_value(&update.location)?,
+                timezone: serde_dynamo::to_attribute_value(update.timezone)?,
+                profile: serde_dynamo::to_attribute_value(&update.profile)?,
+                fetcher: serde_dynamo::to_attribute_value(&update.fetcher)?,
+                normalization: serde_dynamo::to_attribute_value(&update.normalization)?,
+                not_song_rules: serde_dynamo::to_attribute_value(&update.not_song_rules)?,
+                ignore_rules: serde_dynamo::to_attribute_value(&update.ignore_rules)?,
+                flap_window_seconds: serde_dynamo::to_attribute_value(update.flap_window_seconds)?,
+                update_timestamp: ziso_timestamp(&now),
+                locked_timestamp: ziso_timestamp(&station.updated_ts),
+            },
+        )?;
+
+        self.context.backend.update_item(station_update).await?;
+
+        let mut station = station.clone();
+        station.name = update.name;
+        station.location = update.location;
+        station.timezone = update.timezone;
+        station.profile = update.profile;
+        station.fetcher = update.fetcher;
+        station.normalization = update.normalization;
+        station.not_song_rules = update.not_song_rules;
+        station.ignore_rules = update.ignore_rules;
+        station.flap_window_seconds = update.flap_window_seconds;
+        station.updated_ts = now;
+
+        Ok(station)
+    }
+
+    /// Deletes a station. Unless `mode` is [`StationDeleteMode::IfEmpty`], the plays, tracks,
+    /// redirects and track lookup items of the station are deleted first, in batches.
+    ///
+    /// The station itself is deleted last, with the station optimistic lock. A cascade that
+    /// failed halfway, or lost the lock to a play logged meanwhile, can be run again.
+    pub async fn delete_station(
+        &self,
+        station_id: StationId,
+        mode: StationDeleteMode,
+    ) -> Result<StationDeleteSummary> {
+        let station = self
+            .get_station_consistent(station_id)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        if mode == StationDeleteMode::IfEmpty && station.play_count > 0 {
+            return Err(Error::Conflict(format!(
+                "station still has {} plays",
+                station.play_count
+            )));
+        }
+
+        let dry_run = mode == StationDeleteMode::DryRun;
+
+        let plays = self.delete_station_plays(&station, dry_run).await?;
+        let track_keys = self.crud_track.list_station_track_keys(&station).await?;
+
+        let summary = StationDeleteSummary {
+            plays,
+            tracks: track_keys.tracks.len(),
+            redirects: track_keys.redirects.len(),
+            track_metadata: track_keys.track_metadata.len(),
+        };
+
+        if dry_run {
+            return Ok(summary);
+        }
+
+        self.delete_keys(
+            track_keys
+                .track_metadata
+                .into_iter()
+                .chain(track_keys.redirects)
+                .chain(track_keys.tracks)
+                .collect(),
+        )
+        .await?;
+
+        let station_delete = build_station_delete(
+            self.context.backend.table_name(),
+            BuildStationDeleteInput {
+                pk: StationInDB::get_pk(),
+                sk: StationInDB::get_sk(station_id),
+                locked_timestamp: ziso_timestamp(&station.updated_ts),
+            },
+        )?;
+        self.context
+            .backend
+            .transact_write_items(vec![TransactWriteItem::Delete(station_delete)])
+            .await?;
+
+        Ok(summary)
+    }
+
+    /// Deletes, or only counts with `dry_run`, the plays of a station, walking the day
+    /// partitions from its first play to its latest play.
+    async fn delete_station_plays(&self, station: &StationInDB, dry_run: bool) -> Result<usize> {
+        let Some(first_play_id) = station.first_play_id else {
+            return Ok(0);
+        };
+
+        let until: DateTime<Utc> = station.latest_play.as_ref().map_or_else(
+            || self.context.clock.now(),
+            |latest_play| latest_play.id.datetime().into(),
+        );
+        let until_partition = PlayInDB::get_partition(&until);
+
+        let mut count = 0;
+        let mut partition_datetime: DateTime<Utc> = first_play_id.datetime().into();
+
+        loop {
+            let mut exclusive_start_key = None;
+
+            loop {
+                let resp = self
+                    .context
+                    .backend
+                    .query_prefix(
+                        QueryPrefixInput {
+                            pk: PlayInDB::get_pk(station.id, &partition_datetime),
+                            sk_prefix: PlayInDB::get_sk_prefix(),
+                            scan_forward: true,
+                            exclusive_start_key,
+                        },
+                        QueryConfig {
+                            limit: DELETE_BATCH_SIZE as i32,
+                            projected_fields: ProjectedFields::Some(&["pk", "sk"]),
+                        },
+                    )
+                    .await?;
+
+                let keys: Vec<Key> = serde_dynamo::from_items(resp.items)?;
+                count += keys.len();
+                if !dry_run {
+                    self.delete_keys(keys).await?;
+                }
+
+                match resp.last_evaluated_key {
+                    Some(last_evaluated_key) => {
+                        exclusive_start_key = Some(serde_dynamo::from_item(last_evaluated_key)?);
+                    }
+                    None => break,
+                }
+            }
+
+            if PlayInDB::get_partition(&partition_datetime) >= until_partition {
+                return Ok(count);
+            }
+
+            partition_datetime = truncate_datetime_to_days(partition_datetime)
+                .expect("truncate partition datetime to days")
+                + Duration::days(1);
+        }
+    }
+
+    async fn delete_keys(&self, keys: Vec<Key>) -> Result<()> {
+        let table_name = self.context.backend.table_name();
+
+        for chunk in keys.chunks(DELETE_BATCH_SIZE) {
+            let items = chunk
+                .iter()
+                .map(|key| {
+                    Ok(TransactWriteItem::Delete(build_delete(
+                        table_name,
+                        key.clone(),
+                    )?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            self.context.backend.transact_write_items(items).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono_tz::Tz;
+
+    use crate::backend::MemoryBackend;
+    use crate::backend::testing::{FixedClock, TestPlay, memory_context};
+    use crate::crud::logger::CRUDLogger;
+    use crate::crud::play::CRUDPlay;
+    use crate::crud::shared::models::SortOrder;
+    use crate::crud::station::models::{
+        AtimeStation, Band, FetcherConfig, IgnoreRule, MetadataField, NotSongRule, RulePattern,
+        StationProfile,
+    };
+    use crate::crud::track::normalize::NormalizationRules;
+
+    #[tokio::test]
+    async fn test_list_stations_paginated() {
+        let crud_station = CRUDStation::new(memory_context());
+
+        let mut created = vec![];
+        for i in 0..5 {
+            let station = crud_station
+                .create_station(StationInDBCreate {
+                    name: format!("teststation{i}"),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            created.push(station.id);
+        }
+        created.sort_by_key(|station_id| station_id.0);
+
+        let mut listed = vec![];
+        let mut next_token = None;
+        loop {
+            let (stations, page_next_token) = crud_station
+                .list_stations(2, next_token.as_deref())
+                .await
+                .unwrap();
+            assert!(stations.len() <= 2);
+            listed.extend(stations.into_iter().map(|station| station.id));
+
+            next_token = page_next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        assert_eq!(listed, created);
+    }
+
+    #[tokio::test]
+    async fn test_create_station_rejects_invalid_rule_pattern() {
+        let crud_station = CRUDStation::new(memory_context());
+
+        let result = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                not_song_rules: vec![NotSongRule::TitleRegex {
+                    pattern: RulePattern::from("(".to_owned()),
+                }],
+                ..Default::default()
+            })
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+
+        let result = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ignore_rules: vec![IgnoreRule::Regex {
+                    field: MetadataField::Title,
+                    pattern: RulePattern::from("[".to_owned()),
+                }],
+                ..Default::default()
+            })
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+
+        let (stations, _) = crud_station.list_stations(10, None).await.unwrap();
+        assert!(stations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_station() {
+        let crud_station = CRUDStation::new(memory_context());
+
+        let station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let update = StationInDBUpdate {
+            name: "renamed".to_owned(),
+            location: Some("testlocation".to_owned()),
+            timezone: Some(Tz::Asia__Bangkok),
+            profile: StationProfile {
+                band: Some(Band::Fm),
+                frequency: Some("94.0 MHz".to_owned()),
+                country: Some("th".to_owned()),
+                website_url: Some("https://example.com".to_owned()),
+                genres: vec!["Pop ".to_owned(), "pop".to_owned(), "Hits".to_owned()],
+                ..Default::default()
+            },
+            fetcher: Some(FetcherConfig::Atime {
+                station: AtimeStation::EFM,
+            }),
+            normalization: NormalizationRules::verbatim(),
+            not_song_rules: vec![NotSongRule::TitleRegex {
+                pattern: RulePattern::new("^Ad break").unwrap(),
+            }],
+            ignore_rules: vec![IgnoreRule::EmptyTitle],
+            flap_window_seconds: Some(30),
+        };
+
+        let updated = crud_station
+            .update_station(&station, update.clone())
+            .await
+            .unwrap();
+        assert_eq!(updated.name, "renamed");
+        assert_eq!(updated.profile.country.as_deref(), Some("TH"));
+        assert_eq!(updated.profile.genres, ["pop", "hits"]);
+        assert!(updated.profile.active);
+        assert_eq!(updated.normalization, NormalizationRules::verbatim());
+        assert!(!updated.is_song("Artist", "Ad break 1"));
+        assert!(updated.matching_ignore_rule("Artist", " ").is_some());
+        assert_eq!(updated.flap_window_seconds, Some(30));
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, updated);
+
+        // rules are validated like on create
+        assert!(matches!(
+            crud_station
+                .update_station(
+                    &updated,
+                    StationInDBUpdate {
+                        not_song_rules: vec![NotSongRule::TitleRegex {
+                            pattern: RulePattern::from("(".to_owned()),
+                        }],
+                        ..update.clone()
+                    },
+                )
+                .await,
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            crud_station
+                .update_station(
+                    &updated,
+                    StationInDBUpdate {
+                        ignore_rules: vec![IgnoreRule::Regex {
+                            field: MetadataField::Artist,
+                            pattern: RulePattern::from("[".to_owned()),
+                        }],
+                        ..update.clone()
+                    },
+                )
+                .await,
+            Err(Error::InvalidInput(_))
+        ));
+        for flap_window_seconds in [0, 3601] {
+            assert!(matches!(
+                crud_station
+                    .update_station(
+                        &updated,
+                        StationInDBUpdate {
+                            flap_window_seconds: Some(flap_window_seconds),
+                            ..update.clone()
+                        },
+                    )
+                    .await,
+                Err(Error::InvalidInput(_))
+            ));
+        }
+
+        // the station changed since it was read
+        assert!(matches!(
+            crud_station.update_station(&station, update).await,
+            Err(Error::ConditionFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_station() {
+        let now = DateTime::parse_from_rfc3339("2001-06-15T00:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(now)),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_play = CRUDPlay::new(context.clone());
+        let crud_track = CRUDTrack::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let start = now - Duration::days(2);
+        let mut results = vec![];
+        for (artist, hours) in [("artist a", 0), ("artist b", 1), ("artist a", 30)] {
+            results.push(
+                crud_logger
+                    .add_play_at(
+                        &mut station,
+                        TestPlay::new(artist, "title"),
+                        start + Duration::hours(hours),
+                    )
+                    .await
+                    .unwrap(),
+            );
+        }
+        crud_track
+            .merge_tracks(&mut station, results[0].track_id, results[1].track_id)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            crud_station
+                .delete_station(station.id, StationDeleteMode::IfEmpty)
+                .await,
+            Err(Error::Conflict(_))
+        ));
+
+        let expected_summary = StationDeleteSummary {
+            plays: 3,
+            tracks: 1,
+            redirects: 1,
+            track_metadata: 2,
+        };
+
+        let summary = crud_station
+            .delete_station(station.id, StationDeleteMode::DryRun)
+            .await
+            .unwrap();
+        assert_eq!(summary, expected_summary);
+        assert!(
+            crud_station
+                .get_station(station.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let summary = crud_station
+            .delete_station(station.id, StationDeleteMode::Cascade)
+            .await
+            .unwrap();
+        assert_eq!(summary, expected_summary);
+
+        assert!(
+            crud_station
+                .get_station(station.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            crud_track
+                .get_track(station.id, results[0].track_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            crud_track
+                .get_track_redirect(station.id, results[1].track_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            crud_track
+                .get_track_by_metadata(&station, "artist b", "title")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let (plays, _) = crud_play
+            .list_plays(
+                station.id,
+                50,
+                start,
+                start + Duration::hours(2),
+                SortOrder::Asc,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(plays.is_empty());
+    }
 }
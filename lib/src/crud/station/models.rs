@@ -1,12 +1,23 @@
+use std::fmt;
 use std::ops::Deref;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 use ulid::Ulid;
 use utoipa::ToSchema;
 
+use crate::clock::IdGenerator;
 use crate::crud::play::models::PlayId;
 use crate::crud::track::models::TrackId;
+use crate::crud::track::normalize::NormalizationRules;
+use crate::helpers::local_day_range;
+use crate::{Error, Result};
+
+/// Longest flap window a station can have, flicker of a source feed is over within seconds.
+const MAX_FLAP_WINDOW_SECONDS: u32 = 3600;
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(transparent)]
@@ -34,9 +45,32 @@ pub struct StationInDB {
     pub id: StationId,
     pub name: String,
     pub location: Option<String>,
+    /// IANA timezone the broadcast day of the station runs in, UTC if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Tz>,
+    /// Descriptive details of the station, e.g. frequency, website and genres.
+    #[serde(default)]
+    pub profile: StationProfile,
     pub fetcher: Option<FetcherConfig>,
+    /// Rules used to match fetched plays to existing tracks. Stations stored before the rules
+    /// were introduced have their tracks keyed verbatim, so they keep matching verbatim.
+    #[serde(default = "NormalizationRules::verbatim")]
+    pub normalization: NormalizationRules,
+    /// Rules marking fetched plays as not being a song, e.g. station IDs, ads and talk.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub not_song_rules: Vec<NotSongRule>,
+    /// Rules for placeholder metadata that should not be logged at all, e.g. station slogans.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore_rules: Vec<IgnoreRule>,
+    /// A play replaced within this many seconds by the play before it is treated as flicker
+    /// of the source feed, it is retracted and the play before it resumed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flap_window_seconds: Option<u32>,
     pub first_play_id: Option<PlayId>,
     pub latest_play: Option<LatestPlay>,
+    /// The play before `latest_play`, unknown after a play was retracted.
+    #[serde(default)]
+    pub previous_play: Option<LatestPlay>,
     pub track_count: usize,
     pub play_count: usize,
     pub created_ts: DateTime<Utc>,
@@ -56,6 +90,28 @@ impl StationInDB {
         "STATION#".to_owned()
     }
 
+    /// Classifies a fetched play with the station rules, a play is a song unless a rule
+    /// matches.
+    pub fn is_song(&self, artist: &str, title: &str) -> bool {
+        !self
+            .not_song_rules
+            .iter()
+            .any(|rule| rule.matches(&self.name, artist, title))
+    }
+
+    /// Returns the first and the last millisecond of a broadcast day of the station in UTC,
+    /// `None` if the date is out of range.
+    pub fn get_day_range(&self, date: NaiveDate) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        local_day_range(date, self.timezone.unwrap_or(Tz::UTC))
+    }
+
+    /// Returns the first ignore rule matching a fetched play, if any.
+    pub fn matching_ignore_rule(&self, artist: &str, title: &str) -> Option<&IgnoreRule> {
+        self.ignore_rules
+            .iter()
+            .find(|rule| rule.matches(artist, title))
+    }
+
     #[cfg(test)]
     pub(crate) fn new_for_test() -> Self {
         let ts = DateTime::from_timestamp(0, 0).unwrap();
@@ -67,9 +123,16 @@ impl StationInDB {
             id,
             name: "teststation".to_owned(),
             location: Some("testlocation".to_owned()),
+            timezone: None,
+            profile: StationProfile::default(),
             fetcher: None,
+            normalization: NormalizationRules::default(),
+            not_song_rules: vec![],
+            ignore_rules: vec![],
+            flap_window_seconds: None,
             first_play_id: None,
             latest_play: None,
+            previous_play: None,
             track_count: 0,
             play_count: 0,
             created_ts: ts,
@@ -78,6 +141,86 @@ impl StationInDB {
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Band {
+    Am,
+    Fm,
+    Dab,
+    Online,
+}
+
+/// Descriptive details of a station, they have no effect on logging.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct StationProfile {
+    pub band: Option<Band>,
+    /// Frequency as announced on air, e.g. "94.0 MHz" or "12B" for a DAB block.
+    pub frequency: Option<String>,
+    /// ISO 3166-1 alpha-2 country code, e.g. "TH".
+    pub country: Option<String>,
+    /// BCP 47 language tag, e.g. "th".
+    pub language: Option<String>,
+    pub website_url: Option<String>,
+    pub stream_url: Option<String>,
+    pub logo_url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<String>,
+    /// Whether the station is still on air.
+    pub active: bool,
+}
+
+impl Default for StationProfile {
+    fn default() -> Self {
+        Self {
+            band: None,
+            frequency: None,
+            country: None,
+            language: None,
+            website_url: None,
+            stream_url: None,
+            logo_url: None,
+            genres: vec![],
+            active: true,
+        }
+    }
+}
+
+impl StationProfile {
+    /// Checks the profile can be stored, the country code is uppercased and genres are
+    /// trimmed, lowercased and deduplicated.
+    pub(crate) fn validate(mut self) -> Result<Self> {
+        if let Some(country) = &self.country {
+            if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(Error::InvalidInput(
+                    "country must be an ISO 3166-1 alpha-2 code",
+                ));
+            }
+            self.country = Some(country.to_ascii_uppercase());
+        }
+
+        for url in [&self.website_url, &self.stream_url, &self.logo_url]
+            .into_iter()
+            .flatten()
+        {
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err(Error::InvalidInput("station URLs must be http or https"));
+            }
+        }
+
+        let mut genres: Vec<String> = vec![];
+        for genre in self.genres {
+            let genre = genre.trim().to_lowercase();
+            if !genre.is_empty() && !genres.contains(&genre) {
+                genres.push(genre);
+            }
+        }
+        self.genres = genres;
+
+        Ok(self)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct LatestPlay {
     pub id: PlayId,
@@ -86,6 +229,180 @@ pub struct LatestPlay {
     pub title: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum NotSongRule {
+    /// The artist is the station name, ignoring case, as used by station IDs.
+    ArtistIsStationName,
+    /// The artist is empty or whitespace only.
+    EmptyArtist,
+    /// The title matches the regex.
+    TitleRegex { pattern: RulePattern },
+}
+
+impl NotSongRule {
+    pub fn matches(&self, station_name: &str, artist: &str, title: &str) -> bool {
+        match self {
+            Self::ArtistIsStationName => artist.trim().eq_ignore_ascii_case(station_name.trim()),
+            Self::EmptyArtist => artist.trim().is_empty(),
+            Self::TitleRegex { pattern } => pattern.is_match(title),
+        }
+    }
+
+    /// Fails with [`Error::InvalidInput`] if the rule has an invalid regex.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::TitleRegex { pattern } => pattern.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataField {
+    Artist,
+    Title,
+}
+
+impl fmt::Display for MetadataField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Artist => f.write_str("artist"),
+            Self::Title => f.write_str("title"),
+        }
+    }
+}
+
+/// Matches fetched metadata that is not a play. Exact and prefix rules ignore case and
+/// surrounding whitespace.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum IgnoreRule {
+    Exact {
+        field: MetadataField,
+        value: String,
+    },
+    Prefix {
+        field: MetadataField,
+        value: String,
+    },
+    Regex {
+        field: MetadataField,
+        pattern: RulePattern,
+    },
+    EmptyArtist,
+    EmptyTitle,
+}
+
+impl IgnoreRule {
+    pub fn matches(&self, artist: &str, title: &str) -> bool {
+        let field_value = |field: &MetadataField| match field {
+            MetadataField::Artist => artist.trim().to_lowercase(),
+            MetadataField::Title => title.trim().to_lowercase(),
+        };
+
+        match self {
+            Self::Exact { field, value } => field_value(field) == value.trim().to_lowercase(),
+            Self::Prefix { field, value } => {
+                field_value(field).starts_with(&value.trim().to_lowercase())
+            }
+            Self::Regex { field, pattern } => pattern.is_match(match field {
+                MetadataField::Artist => artist,
+                MetadataField::Title => title,
+            }),
+            Self::EmptyArtist => artist.trim().is_empty(),
+            Self::EmptyTitle => title.trim().is_empty(),
+        }
+    }
+
+    /// Fails with [`Error::InvalidInput`] if the rule has an invalid regex.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::Regex { pattern, .. } => pattern.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for IgnoreRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact { field, value } => write!(f, "{field} is {value:?}"),
+            Self::Prefix { field, value } => write!(f, "{field} starts with {value:?}"),
+            Self::Regex { field, pattern } => {
+                write!(f, "{field} matches {:?}", pattern.as_str())
+            }
+            Self::EmptyArtist => f.write_str("artist is empty"),
+            Self::EmptyTitle => f.write_str("title is empty"),
+        }
+    }
+}
+
+/// Regex of a station rule, compiled once when the rule is created or read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct RulePattern {
+    pattern: String,
+    /// `None` if the pattern does not compile, such a rule matches nothing.
+    regex: Option<Regex>,
+}
+
+impl RulePattern {
+    /// Fails with [`Error::InvalidInput`] if the pattern is not a valid regex.
+    pub fn new(pattern: impl Into<String>) -> Result<Self> {
+        let pattern = Self::from(pattern.into());
+        pattern.validate()?;
+
+        Ok(pattern)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    fn is_match(&self, value: &str) -> bool {
+        self.regex
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(value))
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self.regex {
+            Some(_) => Ok(()),
+            None => Err(Error::InvalidInput("invalid regex in station rules")),
+        }
+    }
+}
+
+/// Compiles the pattern. Stored rules are read even if their pattern is invalid, so the
+/// station stays readable.
+impl From<String> for RulePattern {
+    fn from(pattern: String) -> Self {
+        let regex = Regex::new(&pattern)
+            .inspect_err(|error| {
+                warn!(pattern, error = %error, "Ignoring invalid regex in station rules");
+            })
+            .ok();
+
+        Self { pattern, regex }
+    }
+}
+
+impl From<RulePattern> for String {
+    fn from(pattern: RulePattern) -> Self {
+        pattern.pattern
+    }
+}
+
+impl PartialEq for RulePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Eq for RulePattern {}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum AtimeStation {
@@ -102,17 +419,74 @@ pub enum FetcherConfig {
     Atime { station: AtimeStation },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct StationInDBCreate {
     pub name: String,
     pub location: Option<String>,
+    pub timezone: Option<Tz>,
+    pub profile: StationProfile,
+    pub fetcher: Option<FetcherConfig>,
+    pub normalization: NormalizationRules,
+    pub not_song_rules: Vec<NotSongRule>,
+    pub ignore_rules: Vec<IgnoreRule>,
+    pub flap_window_seconds: Option<u32>,
+}
+
+/// The station details that can be edited after the station was created.
+#[derive(Debug, Clone)]
+pub struct StationInDBUpdate {
+    pub name: String,
+    pub location: Option<String>,
+    pub timezone: Option<Tz>,
+    pub profile: StationProfile,
     pub fetcher: Option<FetcherConfig>,
+    /// Applies to plays fetched from now on, tracks keep the lookup keys they were stored
+    /// with and are still found by their verbatim artist and title.
+    pub normalization: NormalizationRules,
+    pub not_song_rules: Vec<NotSongRule>,
+    pub ignore_rules: Vec<IgnoreRule>,
+    pub flap_window_seconds: Option<u32>,
+}
+
+/// Fails with [`Error::InvalidInput`] if a flap window is zero or longer than an hour.
+pub(crate) fn validate_flap_window(flap_window_seconds: Option<u32>) -> Result<()> {
+    match flap_window_seconds {
+        Some(0) => Err(Error::InvalidInput(
+            "flap window must be at least one second",
+        )),
+        Some(seconds) if seconds > MAX_FLAP_WINDOW_SECONDS => {
+            Err(Error::InvalidInput("flap window must be at most an hour"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StationDeleteMode {
+    /// Deletes the station only if it has no plays, fails with `Error::Conflict` otherwise.
+    IfEmpty,
+    /// Deletes the plays, tracks and track lookup items of the station along with it.
+    Cascade,
+    /// Counts the items a cascade would delete, without deleting anything.
+    DryRun,
 }
 
-impl From<StationInDBCreate> for StationInDB {
-    fn from(value: StationInDBCreate) -> Self {
-        let now = Utc::now();
-        let id = Ulid::from_datetime(now.into()).into();
+/// Number of items deleted with a station, or that would be deleted in a dry run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct StationDeleteSummary {
+    pub plays: usize,
+    pub tracks: usize,
+    pub redirects: usize,
+    pub track_metadata: usize,
+}
+
+impl StationInDB {
+    pub fn new(
+        value: StationInDBCreate,
+        created_at: DateTime<Utc>,
+        id_generator: &dyn IdGenerator,
+    ) -> Self {
+        let id = id_generator.generate(created_at).into();
 
         Self {
             pk: Self::get_pk(),
@@ -120,13 +494,128 @@ impl From<StationInDBCreate> for StationInDB {
             id,
             name: value.name,
             location: value.location,
+            timezone: value.timezone,
+            profile: value.profile,
             fetcher: value.fetcher,
+            normalization: value.normalization,
+            not_song_rules: value.not_song_rules,
+            ignore_rules: value.ignore_rules,
+            flap_window_seconds: value.flap_window_seconds,
             first_play_id: None,
             latest_play: None,
+            previous_play: None,
             track_count: 0,
             play_count: 0,
-            created_ts: now,
-            updated_ts: now,
+            created_ts: created_at,
+            updated_ts: created_at,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(NotSongRule::ArtistIsStationName, "TestStation ", "Station ID", false)]
+    #[case(NotSongRule::ArtistIsStationName, "Artist", "Title", true)]
+    #[case(NotSongRule::EmptyArtist, " ", "Ad break", false)]
+    #[case(NotSongRule::EmptyArtist, "Artist", "Title", true)]
+    #[case(NotSongRule::TitleRegex { pattern: RulePattern::new("(?i)^(ad|news)\\b").unwrap() }, "Sponsor", "AD - Sponsor", false)]
+    #[case(NotSongRule::TitleRegex { pattern: RulePattern::new("(?i)^(ad|news)\\b").unwrap() }, "Artist", "Adventure", true)]
+    #[case(NotSongRule::TitleRegex { pattern: RulePattern::from("(".to_owned()) }, "Artist", "(", true)]
+    fn test_station_is_song(
+        #[case] rule: NotSongRule,
+        #[case] artist: &str,
+        #[case] title: &str,
+        #[case] expected: bool,
+    ) {
+        let station = StationInDB {
+            not_song_rules: vec![rule],
+            ..StationInDB::new_for_test()
+        };
+
+        assert_eq!(station.is_song(artist, title), expected);
+    }
+
+    #[test]
+    fn test_rule_pattern() {
+        assert!(matches!(RulePattern::new("("), Err(Error::InvalidInput(_))));
+
+        let rule = IgnoreRule::Regex {
+            field: MetadataField::Artist,
+            pattern: RulePattern::new("^EFM").unwrap(),
+        };
+        let item: crate::backend::Item = serde_dynamo::to_item(&rule).unwrap();
+        assert_eq!(
+            serde_dynamo::from_item::<_, IgnoreRule>(item).unwrap(),
+            rule
+        );
+        assert!(rule.validate().is_ok());
+
+        // a rule stored with an invalid pattern is still read, it matches nothing
+        let rule: NotSongRule =
+            serde_json::from_str(r#"{"type": "title_regex", "pattern": "("}"#).unwrap();
+        assert!(matches!(rule.validate(), Err(Error::InvalidInput(_))));
+        assert!(!rule.matches("teststation", "Artist", "("));
+    }
+
+    #[rstest]
+    #[case(IgnoreRule::Exact { field: MetadataField::Title, value: "Commercial Break".to_owned() }, "", " commercial break", true)]
+    #[case(IgnoreRule::Exact { field: MetadataField::Title, value: "Commercial Break".to_owned() }, "", "Commercial Breakdown", false)]
+    #[case(IgnoreRule::Prefix { field: MetadataField::Artist, value: "EFM".to_owned() }, "efm 94", "Title", true)]
+    #[case(IgnoreRule::Prefix { field: MetadataField::Artist, value: "EFM".to_owned() }, "Artist", "EFM", false)]
+    #[case(IgnoreRule::Regex { field: MetadataField::Title, pattern: RulePattern::new("^\\d+$").unwrap() }, "Artist", "94", true)]
+    #[case(IgnoreRule::EmptyArtist, "", "Title", true)]
+    #[case(IgnoreRule::EmptyTitle, "Artist", "Title", false)]
+    fn test_ignore_rule_matches(
+        #[case] rule: IgnoreRule,
+        #[case] artist: &str,
+        #[case] title: &str,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(rule.matches(artist, title), expected);
+    }
+
+    #[test]
+    fn test_matching_ignore_rule_display() {
+        let station = StationInDB {
+            ignore_rules: vec![
+                IgnoreRule::EmptyTitle,
+                IgnoreRule::Exact {
+                    field: MetadataField::Artist,
+                    value: "EFM 94".to_owned(),
+                },
+            ],
+            ..StationInDB::new_for_test()
+        };
+
+        assert_eq!(
+            station
+                .matching_ignore_rule("EFM 94", "Slogan")
+                .map(ToString::to_string),
+            Some("artist is \"EFM 94\"".to_owned())
+        );
+        assert!(station.matching_ignore_rule("Artist", "Title").is_none());
+    }
+
+    #[rstest]
+    #[case(Some("thailand"), None)]
+    #[case(Some("t1"), None)]
+    #[case(None, Some("example.com"))]
+    #[case(None, Some("ftp://example.com"))]
+    fn test_station_profile_validate_invalid(
+        #[case] country: Option<&str>,
+        #[case] website_url: Option<&str>,
+    ) {
+        let profile = StationProfile {
+            country: country.map(str::to_owned),
+            website_url: website_url.map(str::to_owned),
+            ..Default::default()
+        };
+
+        assert!(matches!(profile.validate(), Err(Error::InvalidInput(_))));
+    }
+}
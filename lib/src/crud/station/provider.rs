@@ -1,83 +1,160 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use aws_sdk_dynamodb::error::BuildError;
+use aws_sdk_dynamodb::types::{AttributeValue, Delete, Update};
 
-use aws_sdk_dynamodb::error::SdkError;
-use aws_sdk_dynamodb::operation::get_item::{GetItemError, GetItemOutput};
-use aws_sdk_dynamodb::operation::put_item::{PutItemError, PutItemOutput};
-use aws_sdk_dynamodb::operation::query::{QueryError, QueryOutput};
-use aws_sdk_dynamodb::types::AttributeValue;
-use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use crate::backend::Key;
 
-use crate::crud::Context;
-
-pub(super) struct DynamoDBProvider {
-    context: Arc<Context>,
-}
-
-pub(super) struct GetItemInput {
+pub(super) struct BuildStationDetailsUpdateInput {
     pub pk: String,
     pub sk: String,
+    pub name: String,
+    pub location: AttributeValue,
+    pub timezone: AttributeValue,
+    pub profile: AttributeValue,
+    pub fetcher: AttributeValue,
+    pub normalization: AttributeValue,
+    pub not_song_rules: AttributeValue,
+    pub ignore_rules: AttributeValue,
+    pub flap_window_seconds: AttributeValue,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
 }
 
-pub(super) struct PutItemInput {
-    pub item: HashMap<String, AttributeValue>,
+/// Replaces the editable station details, unless the station changed since it was read.
+pub fn build_station_details_update(
+    table_name: &str,
+    input: BuildStationDetailsUpdateInput,
+) -> Result<Update, BuildError> {
+    // name, location and timezone are reserved words in DynamoDB expressions
+    Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :station_locked_ts")
+        .update_expression(
+            "SET updated_ts = :ts, #name = :name, #location = :location, \
+             #timezone = :timezone, profile = :profile, fetcher = :fetcher, \
+             normalization = :normalization, not_song_rules = :not_song_rules, \
+             ignore_rules = :ignore_rules, flap_window_seconds = :flap_window_seconds",
+        )
+        .expression_attribute_names("#name", "name")
+        .expression_attribute_names("#location", "location")
+        .expression_attribute_names("#timezone", "timezone")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(
+            ":station_locked_ts",
+            AttributeValue::S(input.locked_timestamp),
+        )
+        .expression_attribute_values(":name", AttributeValue::S(input.name))
+        .expression_attribute_values(":location", input.location)
+        .expression_attribute_values(":timezone", input.timezone)
+        .expression_attribute_values(":profile", input.profile)
+        .expression_attribute_values(":fetcher", input.fetcher)
+        .expression_attribute_values(":normalization", input.normalization)
+        .expression_attribute_values(":not_song_rules", input.not_song_rules)
+        .expression_attribute_values(":ignore_rules", input.ignore_rules)
+        .expression_attribute_values(":flap_window_seconds", input.flap_window_seconds)
+        .build()
 }
 
-pub(super) struct QueryPrefixInput {
+pub(super) struct BuildStationDeleteInput {
     pub pk: String,
-    pub sk_prefix: String,
+    pub sk: String,
+    pub locked_timestamp: String,
 }
 
-pub(super) struct QueryPrefixConfig {
-    pub limit: i32,
+/// Deletes a station, unless it got a play or an edit since it was read.
+pub fn build_station_delete(
+    table_name: &str,
+    input: BuildStationDeleteInput,
+) -> Result<Delete, BuildError> {
+    Delete::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :station_locked_ts")
+        .expression_attribute_values(
+            ":station_locked_ts",
+            AttributeValue::S(input.locked_timestamp),
+        )
+        .build()
 }
 
-impl DynamoDBProvider {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
-    }
+/// Deletes an item of a station being deleted.
+pub fn build_delete(table_name: &str, key: Key) -> Result<Delete, BuildError> {
+    Delete::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(key.pk))
+        .key("sk", AttributeValue::S(key.sk))
+        .build()
+}
 
-    pub async fn get_item(
-        &self,
-        input: GetItemInput,
-    ) -> Result<GetItemOutput, SdkError<GetItemError, HttpResponse>> {
-        self.context
-            .db_client
-            .get_item()
-            .table_name(&self.context.db_table)
-            .key("pk", AttributeValue::S(input.pk))
-            .key("sk", AttributeValue::S(input.sk))
-            .send()
-            .await
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub async fn put_item(
-        &self,
-        input: PutItemInput,
-    ) -> Result<PutItemOutput, SdkError<PutItemError, HttpResponse>> {
-        self.context
-            .db_client
-            .put_item()
-            .table_name(&self.context.db_table)
-            .set_item(Some(input.item))
-            .send()
-            .await
-    }
+    #[test]
+    fn test_build_station_details_update_success() {
+        let update = build_station_details_update(
+            "tablename",
+            BuildStationDetailsUpdateInput {
+                pk: "pkvalue".to_owned(),
+                sk: "skvalue".to_owned(),
+                name: "name".to_owned(),
+                location: AttributeValue::Null(true),
+                timezone: AttributeValue::S("Asia/Bangkok".to_owned()),
+                profile: AttributeValue::M(Default::default()),
+                fetcher: AttributeValue::Null(true),
+                normalization: AttributeValue::M(Default::default()),
+                not_song_rules: AttributeValue::L(vec![]),
+                ignore_rules: AttributeValue::L(vec![]),
+                flap_window_seconds: AttributeValue::N("30".to_owned()),
+                update_timestamp: "12345".to_owned(),
+                locked_timestamp: "67890".to_owned(),
+            },
+        )
+        .unwrap();
 
-    pub async fn query_prefix(
-        &self,
-        input: QueryPrefixInput,
-        config: QueryPrefixConfig,
-    ) -> Result<QueryOutput, SdkError<QueryError, HttpResponse>> {
-        self.context
-            .db_client
-            .query()
-            .table_name(&self.context.db_table)
-            .key_condition_expression("pk = :pk AND begins_with(sk, :sk_prefix)")
-            .expression_attribute_values(":pk", AttributeValue::S(input.pk))
-            .expression_attribute_values(":sk_prefix", AttributeValue::S(input.sk_prefix))
-            .limit(config.limit)
-            .send()
-            .await
+        assert_eq!(
+            update,
+            Update::builder()
+                .table_name("tablename")
+                .key("pk", AttributeValue::S("pkvalue".to_owned()))
+                .key("sk", AttributeValue::S("skvalue".to_owned()))
+                .condition_expression("updated_ts = :station_locked_ts")
+                .update_expression(
+                    "SET updated_ts = :ts, #name = :name, #location = :location, \
+                     #timezone = :timezone, profile = :profile, fetcher = :fetcher, \
+                     normalization = :normalization, not_song_rules = :not_song_rules, \
+                     ignore_rules = :ignore_rules, flap_window_seconds = :flap_window_seconds",
+                )
+                .expression_attribute_names("#name", "name")
+                .expression_attribute_names("#location", "location")
+                .expression_attribute_names("#timezone", "timezone")
+                .expression_attribute_values(":ts", AttributeValue::S("12345".to_owned()))
+                .expression_attribute_values(
+                    ":station_locked_ts",
+                    AttributeValue::S("67890".to_owned())
+                )
+                .expression_attribute_values(":name", AttributeValue::S("name".to_owned()))
+                .expression_attribute_values(":location", AttributeValue::Null(true))
+                .expression_attribute_values(
+                    ":timezone",
+                    AttributeValue::S("Asia/Bangkok".to_owned())
+                )
+                .expression_attribute_values(":profile", AttributeValue::M(Default::default()))
+                .expression_attribute_values(":fetcher", AttributeValue::Null(true))
+                .expression_attribute_values(
+                    ":normalization",
+                    AttributeValue::M(Default::default())
+                )
+                .expression_attribute_values(":not_song_rules", AttributeValue::L(vec![]))
+                .expression_attribute_values(":ignore_rules", AttributeValue::L(vec![]))
+                .expression_attribute_values(
+                    ":flap_window_seconds",
+                    AttributeValue::N("30".to_owned())
+                )
+                .build()
+                .unwrap()
+        );
     }
 }
@@ -1,83 +1,200 @@
 pub mod models;
+pub mod normalize;
 mod provider;
 
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use anyhow::Result;
+use aws_sdk_dynamodb::types::AttributeValue;
 use chrono::{DateTime, Duration, Utc};
+use futures::future::try_join_all;
 use serde::{Deserialize, Serialize};
-use ulid::Ulid;
 
+use crate::backend::{
+    BATCH_GET_ITEM_MAX_KEYS, Backend, BatchGetItemConfig, BatchGetItemInput, GetItemConfig, Item,
+    Key, ProjectedFields, QueryConfig, QueryPrefixGsi1Input, QueryPrefixInput, StorageBackend,
+    TransactWriteItem,
+};
 use crate::crud::Context;
-use crate::crud::play::models::PlayInDB;
-use crate::crud::shared::models::{Gsi1PaginateKey, PaginateKey};
+use crate::crud::play::models::{PlayId, PlayInDB};
+use crate::crud::shared::cursor::{Cursor, Direction};
 use crate::crud::station::models::{StationId, StationInDB};
 use crate::crud::track::models::TrackId;
 use crate::crud::track::models::{
-    TrackInDB, TrackMetadataInDB, TrackMetadataKeys, TrackMinimalInDB, TrackPlayInDB,
+    TrackAlias, TrackInDB, TrackMetadataCreateInDB, TrackMetadataInDB, TrackMetadataKeys,
+    TrackMetadataUpdate, TrackMinimalInDB, TrackPlayInDB, TrackPlayKeyInDB, TrackRedirectInDB,
 };
-use crate::helpers::truncate_datetime_to_months;
+use crate::crud::track::normalize::NormalizationRules;
+use crate::helpers::{truncate_datetime_to_months, ziso_timestamp};
+use crate::{Error, Result};
 use provider::{
-    BatchGetItemInput, DynamoDBProvider, ExclusiveStartKey, GetItemConfig, GetItemInput,
-    ProjectedFields, QueryPrefixConfig, QueryPrefixInput,
+    BuildPlayDeleteInput, BuildPlayTrackUpdateInput, BuildStationTrackRemovedUpdateInput,
+    BuildTrackDeleteInput, BuildTrackIsSongUpdateInput, BuildTrackMergeUpdateInput,
+    BuildTrackMetadataDeleteInput, BuildTrackMetadataPutInput, BuildTrackMetadataUpdateInput,
+    BuildTrackPlayAddedUpdateInput, BuildTrackPlayRemovedUpdateInput,
+    BuildTrackRedirectDeleteInput, BuildTrackRedirectPutInput, BuildTrackRenameUpdateInput,
+    build_play_delete, build_play_track_update, build_station_track_removed_update,
+    build_track_delete, build_track_is_song_update, build_track_merge_update,
+    build_track_metadata_delete, build_track_metadata_put, build_track_metadata_update,
+    build_track_play_added_update, build_track_play_removed_update, build_track_redirect_delete,
+    build_track_redirect_put, build_track_rename_update,
 };
 
-use self::provider::{
-    BatchGetItemConfig, BatchGetItemKey, Gsi1ExclusiveStartKey, QueryPrefixGsi1Config,
-    QueryPrefixGsi1Input,
-};
+/// Attempts per chunk before giving up on keys the backend keeps returning as unprocessed.
+const BATCH_GET_MAX_ATTEMPTS: u32 = 5;
+const BATCH_GET_INITIAL_BACKOFF: std::time::Duration = std::time::Duration::from_millis(50);
 
-const ULID_RANDOM_MAX: u128 = (1 << 80) - 1;
+/// Plays read from the track index per page while moving them to another track.
+const MOVE_PLAYS_PAGE_SIZE: i32 = 25;
 
-pub struct CRUDTrack {
-    provider: DynamoDBProvider,
+/// Tracks and redirects read per page while listing the keys of a station.
+const STATION_KEYS_PAGE_SIZE: i32 = 100;
+
+/// Transaction items taking a play away from its track, see
+/// [`CRUDTrack::prepare_play_removal`] and [`CRUDTrack::prepare_play_reassignment`].
+pub(crate) struct PlayRemoval {
+    pub items: Vec<TransactWriteItem>,
+    /// The play was the only play of its track, the track and its lookup items are deleted.
+    pub track_deleted: bool,
 }
 
-impl CRUDTrack {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self {
-            provider: DynamoDBProvider::new(context),
-        }
+/// Keys of the tracks, redirects and track lookup items of a station, see
+/// [`CRUDTrack::list_station_track_keys`].
+#[derive(Debug, Default)]
+pub(crate) struct StationTrackKeys {
+    pub tracks: Vec<Key>,
+    pub redirects: Vec<Key>,
+    pub track_metadata: Vec<Key>,
+}
+
+pub struct CRUDTrack<B: StorageBackend = Backend> {
+    context: Arc<Context<B>>,
+}
+
+impl<B: StorageBackend> CRUDTrack<B> {
+    pub fn new(context: Arc<Context<B>>) -> Self {
+        Self { context }
     }
 
     pub async fn get_track(
         &self,
         station_id: StationId,
         track_id: TrackId,
+    ) -> Result<Option<TrackInDB>> {
+        self.get_track_internal(station_id, track_id, false).await
+    }
+
+    pub(crate) async fn get_track_internal(
+        &self,
+        station_id: StationId,
+        track_id: TrackId,
+        consistent_read: bool,
     ) -> Result<Option<TrackInDB>> {
         let resp = self
-            .provider
+            .context
+            .backend
             .get_item(
-                GetItemInput {
+                Key {
                     pk: TrackInDB::get_pk(station_id),
                     sk: TrackInDB::get_sk(track_id),
                 },
                 GetItemConfig {
-                    consistent_read: false,
+                    consistent_read,
                     projected_fields: ProjectedFields::All,
                 },
             )
             .await?;
 
-        if let Some(item) = resp.item {
+        if let Some(item) = resp {
             Ok(Some(serde_dynamo::from_item(item)?))
         } else {
             Ok(None)
         }
     }
 
+    /// Returns the track a merged track was merged into.
+    pub async fn get_track_redirect(
+        &self,
+        station_id: StationId,
+        track_id: TrackId,
+    ) -> Result<Option<TrackId>> {
+        let resp = self
+            .context
+            .backend
+            .get_item(
+                Key {
+                    pk: TrackInDB::get_pk(station_id),
+                    sk: TrackRedirectInDB::get_sk(track_id),
+                },
+                GetItemConfig {
+                    consistent_read: false,
+                    projected_fields: ProjectedFields::All,
+                },
+            )
+            .await?;
+
+        if let Some(item) = resp {
+            let redirect: TrackRedirectInDB = serde_dynamo::from_item(item)?;
+            Ok(Some(redirect.track_id))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub async fn get_track_by_metadata(
         &self,
         station: &StationInDB,
         artist: &str,
         title: &str,
+    ) -> Result<Option<TrackMetadataInDB>> {
+        Ok(self
+            .find_track_by_metadata(station, artist, title)
+            .await?
+            .map(|(track_metadata, _)| track_metadata))
+    }
+
+    /// Looks up the track by the normalized artist and title, falling back to the verbatim
+    /// strings used as key before normalization. The flag is set if the track was only found
+    /// with the verbatim key.
+    pub(crate) async fn find_track_by_metadata(
+        &self,
+        station: &StationInDB,
+        artist: &str,
+        title: &str,
+    ) -> Result<Option<(TrackMetadataInDB, bool)>> {
+        let rules = &station.normalization;
+        let normalized = (rules.normalize_artist(artist), rules.normalize_title(title));
+
+        if let Some(track_metadata) = self
+            .get_track_metadata(station.id, &normalized.0, &normalized.1)
+            .await?
+        {
+            return Ok(Some((track_metadata, false)));
+        }
+
+        if normalized.0 == artist && normalized.1 == title {
+            return Ok(None);
+        }
+
+        Ok(self
+            .get_track_metadata(station.id, artist, title)
+            .await?
+            .map(|track_metadata| (track_metadata, true)))
+    }
+
+    pub(crate) async fn get_track_metadata(
+        &self,
+        station_id: StationId,
+        artist_key: &str,
+        title_key: &str,
     ) -> Result<Option<TrackMetadataInDB>> {
         let resp = self
-            .provider
+            .context
+            .backend
             .get_item(
-                GetItemInput {
-                    pk: TrackMetadataInDB::get_pk(station.id, artist),
-                    sk: TrackMetadataInDB::get_sk(title),
+                Key {
+                    pk: TrackMetadataInDB::get_pk(station_id, artist_key),
+                    sk: TrackMetadataInDB::get_sk(title_key),
                 },
                 GetItemConfig {
                     consistent_read: true,
@@ -86,109 +203,104 @@ impl CRUDTrack {
             )
             .await?;
 
-        if let Some(item) = resp.item {
+        if let Some(item) = resp {
             Ok(Some(serde_dynamo::from_item(item)?))
         } else {
             Ok(None)
         }
     }
 
+    /// Lists the tracks of a station, newest first.
     pub async fn list_tracks(
         &self,
         station_id: StationId,
         limit: i32,
-        next_key: Option<Ulid>,
-    ) -> Result<(Vec<TrackInDB>, Option<Ulid>)> {
-        let exclusive_start_key = if let Some(next_key) = next_key {
-            let track_id = next_key.into();
-            Some(ExclusiveStartKey {
-                pk: TrackInDB::get_pk(station_id),
-                sk: TrackInDB::get_sk(track_id),
-            })
+        next_token: Option<&str>,
+    ) -> Result<(Vec<TrackInDB>, Option<String>)> {
+        let partition = TrackInDB::get_pk(station_id);
+        let cursor = if let Some(next_token) = next_token {
+            let cursor = self.context.cursor_codec.decode(next_token)?;
+            cursor.ensure(Direction::Backward, |p| p == partition)?;
+            cursor
         } else {
-            None
+            Cursor::start_of(partition, Direction::Backward)
         };
 
         let resp = self
-            .provider
+            .context
+            .backend
             .query_prefix(
                 QueryPrefixInput {
-                    pk: TrackInDB::get_pk(station_id),
+                    pk: cursor.partition.clone(),
                     sk_prefix: TrackInDB::get_sk_prefix(),
-                    scan_forward: false,
-                    exclusive_start_key,
+                    scan_forward: cursor.direction.scan_forward(),
+                    exclusive_start_key: cursor.table_key(),
                 },
-                QueryPrefixConfig {
+                QueryConfig {
                     limit,
                     projected_fields: ProjectedFields::All,
                 },
             )
             .await?;
 
-        let next_key = if let Some(last_evaluated_key) = resp.last_evaluated_key {
-            let paginate_key: PaginateKey = serde_dynamo::from_item(last_evaluated_key)?;
-            Some(
-                Ulid::from_string(
-                    paginate_key
-                        .sk
-                        .strip_prefix(&TrackInDB::get_sk_prefix())
-                        .expect("parse next key"),
-                )
-                .expect("next key into ulid"),
-            )
+        let next_token = if let Some(last_evaluated_key) = resp.last_evaluated_key {
+            let next_cursor =
+                Cursor::after(cursor.partition, last_evaluated_key, cursor.direction)?;
+            Some(self.context.cursor_codec.encode(&next_cursor))
         } else {
             None
         };
 
-        Ok((
-            serde_dynamo::from_items(resp.items.expect("query response to have items"))?,
-            next_key,
-        ))
+        Ok((serde_dynamo::from_items(resp.items)?, next_token))
     }
 
+    /// Lists the tracks whose artist normalizes to the same key as `artist` under the
+    /// station rules.
     pub async fn list_tracks_by_artist(
         &self,
-        station_id: StationId,
+        station: &StationInDB,
         artist: &str,
         limit: i32,
-        next_key: Option<&str>,
+        next_token: Option<&str>,
     ) -> Result<(Vec<TrackInDB>, Option<String>)> {
-        let exclusive_start_key = next_key.map(|next_key| ExclusiveStartKey {
-            pk: TrackMetadataInDB::get_pk(station_id, artist),
-            sk: TrackMetadataInDB::get_sk(next_key),
-        });
+        let station_id = station.id;
+        let artist = station.normalization.normalize_artist(artist);
+
+        let partition = TrackMetadataInDB::get_pk(station_id, &artist);
+        let cursor = if let Some(next_token) = next_token {
+            let cursor = self.context.cursor_codec.decode(next_token)?;
+            cursor.ensure(Direction::Backward, |p| p == partition)?;
+            cursor
+        } else {
+            Cursor::start_of(partition, Direction::Backward)
+        };
 
         let resp = self
-            .provider
+            .context
+            .backend
             .query_prefix(
                 QueryPrefixInput {
-                    pk: TrackMetadataInDB::get_pk(station_id, artist),
+                    pk: cursor.partition.clone(),
                     sk_prefix: TrackMetadataInDB::get_sk_prefix(),
-                    scan_forward: false,
-                    exclusive_start_key,
+                    scan_forward: cursor.direction.scan_forward(),
+                    exclusive_start_key: cursor.table_key(),
                 },
-                QueryPrefixConfig {
+                QueryConfig {
                     limit,
                     projected_fields: ProjectedFields::Some(&["track_id"]),
                 },
             )
             .await?;
 
-        let next_key = if let Some(last_evaluated_key) = resp.last_evaluated_key {
-            let paginate_key: PaginateKey = serde_dynamo::from_item(last_evaluated_key)?;
-            Some(
-                paginate_key
-                    .sk
-                    .strip_prefix(&TrackMetadataInDB::get_sk_prefix())
-                    .expect("parse next key")
-                    .to_owned(),
-            )
+        let next_token = if let Some(last_evaluated_key) = resp.last_evaluated_key {
+            let next_cursor =
+                Cursor::after(cursor.partition, last_evaluated_key, cursor.direction)?;
+            Some(self.context.cursor_codec.encode(&next_cursor))
         } else {
             None
         };
 
-        let track_metadatas: Vec<TrackMetadataInDB> =
-            serde_dynamo::from_items(resp.items.expect("query response to have items"))?;
+        let track_metadatas: Vec<TrackMetadataInDB> = serde_dynamo::from_items(resp.items)?;
 
         let tracks = self
             .batch_get_tracks(
@@ -197,7 +309,7 @@ impl CRUDTrack {
             )
             .await?;
 
-        Ok((tracks, next_key))
+        Ok((tracks, next_token))
     }
 
     pub async fn batch_get_tracks(
@@ -231,107 +343,1595 @@ impl CRUDTrack {
     where
         O: Serialize + Deserialize<'a>,
     {
-        let resp = self
-            .provider
-            .batch_get_item(
-                BatchGetItemInput {
-                    keys: track_ids.map(|id| BatchGetItemKey {
-                        pk: TrackInDB::get_pk(station_id),
-                        sk: TrackInDB::get_sk(*id),
-                    }),
-                },
-                BatchGetItemConfig { projected_fields },
-            )
-            .await?;
+        let track_ids: Vec<TrackId> = track_ids.copied().collect();
 
-        Ok(serde_dynamo::from_items(
-            resp.responses
-                .expect("responses key must be present")
-                .get(self.provider.table_name())
-                .expect("response with table name must be present")
-                .to_vec(),
-        )?)
+        // DynamoDB rejects requests that contain the same key twice
+        let mut seen_track_ids = HashSet::new();
+        let keys: Vec<Key> = track_ids
+            .iter()
+            .filter(|track_id| seen_track_ids.insert(**track_id))
+            .map(|track_id| Key {
+                pk: TrackInDB::get_pk(station_id),
+                sk: TrackInDB::get_sk(*track_id),
+            })
+            .collect();
+
+        let chunks = try_join_all(
+            keys.chunks(BATCH_GET_ITEM_MAX_KEYS)
+                .map(|chunk| self.batch_get_items_chunk(chunk.to_vec(), projected_fields)),
+        )
+        .await?;
+
+        let mut items_by_id: HashMap<String, Item> = HashMap::new();
+        for item in chunks.into_iter().flatten() {
+            if let Some(AttributeValue::S(id)) = item.get("id") {
+                items_by_id.insert(id.clone(), item);
+            }
+        }
+
+        // return tracks in the order they were requested, skipping ones that do not exist
+        track_ids
+            .iter()
+            .filter_map(|track_id| items_by_id.get(&track_id.to_string()))
+            .map(|item| Ok(serde_dynamo::from_item(item.clone())?))
+            .collect()
     }
 
+    /// Fetches up to [`BATCH_GET_ITEM_MAX_KEYS`] keys, requesting unprocessed keys again
+    /// with exponential backoff.
+    async fn batch_get_items_chunk(
+        &self,
+        mut keys: Vec<Key>,
+        projected_fields: ProjectedFields,
+    ) -> Result<Vec<Item>> {
+        let mut items = vec![];
+        let mut backoff = BATCH_GET_INITIAL_BACKOFF;
+
+        for attempt in 1..=BATCH_GET_MAX_ATTEMPTS {
+            let output = self
+                .context
+                .backend
+                .batch_get_item(
+                    BatchGetItemInput { keys },
+                    BatchGetItemConfig { projected_fields },
+                )
+                .await?;
+
+            items.extend(output.items);
+
+            if output.unprocessed_keys.is_empty() {
+                return Ok(items);
+            }
+
+            keys = output.unprocessed_keys;
+
+            if attempt < BATCH_GET_MAX_ATTEMPTS {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        Err(Error::Throttled(
+            format!(
+                "{} keys still unprocessed after {BATCH_GET_MAX_ATTEMPTS} attempts",
+                keys.len()
+            )
+            .into(),
+        ))
+    }
+
+    /// Lists the plays of a track, newest first, walking the monthly `gsi1` partitions back
+    /// to the earliest play of the track.
     pub async fn list_plays_of_track(
         &self,
         station_id: StationId,
         track_id: TrackId,
         limit: i32,
-        next_key: Option<Ulid>,
+        next_token: Option<&str>,
     ) -> Result<(Vec<TrackPlayInDB>, Option<String>)> {
-        let partition_datetime = if let Some(next_key) = next_key {
-            next_key.datetime().into()
-        } else {
-            Utc::now()
-        };
-
-        let exclusive_start_key = if let Some(next_key) = next_key {
-            // if random < (2 ** 80) - 1, assume no exclusive_start_key
-            if next_key.random() < ULID_RANDOM_MAX {
-                let play_id = next_key.into();
-                Some(Gsi1ExclusiveStartKey {
-                    gsi1pk: TrackPlayInDB::get_gsi1pk(track_id, &partition_datetime),
-                    sk: PlayInDB::get_sk(play_id),
-                    pk: PlayInDB::get_pk(station_id, &partition_datetime),
-                })
-            } else {
-                None
-            }
+        let cursor = if let Some(next_token) = next_token {
+            let cursor = self.context.cursor_codec.decode(next_token)?;
+            cursor.ensure(Direction::Backward, |p| {
+                TrackPlayInDB::parse_gsi1pk(track_id, p).is_some()
+            })?;
+            cursor
         } else {
-            None
+            Cursor::start_of(
+                TrackPlayInDB::get_gsi1pk(track_id, &self.context.clock.now()),
+                Direction::Backward,
+            )
         };
+        let partition_datetime = TrackPlayInDB::parse_gsi1pk(track_id, &cursor.partition)
+            .expect("parse partition of track plays");
 
         let resp = self
-            .provider
+            .context
+            .backend
             .query_prefix_gsi1(
                 QueryPrefixGsi1Input {
-                    gsi1pk: TrackPlayInDB::get_gsi1pk(track_id, &partition_datetime),
+                    gsi1pk: cursor.partition.clone(),
                     sk_prefix: TrackPlayInDB::get_sk_prefix(),
                     // double check if it's the same station we're looking for
                     pk_prefix: Some(PlayInDB::get_pk_station_prefix(station_id)),
-                    scan_forward: false,
-                    exclusive_start_key,
+                    scan_forward: cursor.direction.scan_forward(),
+                    exclusive_start_key: cursor.gsi1_key(),
+                },
+                QueryConfig {
+                    limit,
+                    projected_fields: ProjectedFields::All,
                 },
-                QueryPrefixGsi1Config { limit },
             )
             .await?;
 
-        let next_key = if let Some(last_evaluated_key) = resp.last_evaluated_key {
-            let paginate_key: Gsi1PaginateKey = serde_dynamo::from_item(last_evaluated_key)?;
-            Some(
-                paginate_key
-                    .sk
-                    .strip_prefix(&TrackPlayInDB::get_sk_prefix())
-                    .expect("parse next key")
-                    .to_owned(),
-            )
+        let next_cursor = if let Some(last_evaluated_key) = resp.last_evaluated_key {
+            Some(Cursor::after(
+                cursor.partition,
+                last_evaluated_key,
+                cursor.direction,
+            )?)
         } else {
-            let next_partition_datetime = truncate_datetime_to_months(partition_datetime)
-                .expect("truncate datetime to months")
-                - Duration::nanoseconds(1);
+            let next_partition_datetime = partition_datetime - Duration::nanoseconds(1);
 
-            let track_creation: DateTime<Utc> = track_id.datetime().into();
-            if next_partition_datetime > track_creation {
-                Some(
-                    Ulid::from_parts(
-                        next_partition_datetime
-                            .timestamp_millis()
-                            .try_into()
-                            .expect("convert i64 unix ts to u64"),
-                        u128::MAX,
-                    )
-                    .to_string(),
-                )
+            if next_partition_datetime > self.get_earliest_play_bound(station_id, track_id).await? {
+                Some(Cursor::start_of(
+                    TrackPlayInDB::get_gsi1pk(track_id, &next_partition_datetime),
+                    cursor.direction,
+                ))
             } else {
                 None
             }
         };
 
-        if let Some(items) = resp.items {
-            Ok((serde_dynamo::from_items(items.to_vec())?, next_key))
+        Ok((
+            serde_dynamo::from_items(resp.items)?,
+            next_cursor.map(|next_cursor| self.context.cursor_codec.encode(&next_cursor)),
+        ))
+    }
+
+    /// Returns the time no play of a track is older than, the creation of the track if the
+    /// track no longer exists.
+    async fn get_earliest_play_bound(
+        &self,
+        station_id: StationId,
+        track_id: TrackId,
+    ) -> Result<DateTime<Utc>> {
+        Ok(self
+            .get_track_internal(station_id, track_id, false)
+            .await?
+            .map_or_else(
+                || track_id.datetime().into(),
+                |track| track.earliest_play_bound(),
+            ))
+    }
+
+    /// Merges track `absorb` into track `keep`. The plays and metadata items of `absorb` are
+    /// pointed at `keep`, its play count is added to `keep` and `absorb` is replaced with a
+    /// redirect to `keep`.
+    ///
+    /// Plays are moved one by one before the tracks are updated in a transaction, so a merge
+    /// that fails can leave plays pointed at `keep` while the play counts are unchanged. It
+    /// has to be run again until it succeeds, which moves any remaining plays and completes
+    /// the merge. Fails with [`Error::ConditionFailed`] if either track or the station changed
+    /// while merging.
+    pub async fn merge_tracks(
+        &self,
+        station: &mut StationInDB,
+        keep: TrackId,
+        absorb: TrackId,
+    ) -> Result<TrackInDB> {
+        if keep == absorb {
+            return Err(Error::InvalidInput("cannot merge a track into itself"));
+        }
+
+        let mut keep_track = self
+            .get_track_internal(station.id, keep, true)
+            .await?
+            .ok_or(Error::NotFound)?;
+        let absorb_track = self
+            .get_track_internal(station.id, absorb, true)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        self.move_plays_of_track(station.id, &absorb_track, keep)
+            .await?;
+
+        let table_name = self.context.backend.table_name();
+        let now = self.context.clock.now();
+        let mut items = vec![];
+
+        let rules = &station.normalization;
+        let keep_alias = TrackAlias::normalized(rules, &keep_track.artist, &keep_track.title);
+        let mut aliases = keep_track.aliases.clone();
+
+        for alias in lookup_keys_of_track(&absorb_track, rules) {
+            let track_metadata = self
+                .get_track_metadata(station.id, &alias.artist, &alias.title)
+                .await?;
+
+            if track_metadata.is_some_and(|track_metadata| track_metadata.track_id == absorb) {
+                items.push(TransactWriteItem::Update(build_track_metadata_update(
+                    table_name,
+                    BuildTrackMetadataUpdateInput {
+                        pk: TrackMetadataInDB::get_pk(station.id, &alias.artist),
+                        sk: TrackMetadataInDB::get_sk(&alias.title),
+                        from_track_id: absorb.to_string(),
+                        to_track_id: keep.to_string(),
+                    },
+                )?));
+
+                if alias != keep_alias && !aliases.contains(&alias) {
+                    aliases.push(alias);
+                }
+            }
+        }
+
+        let latest_play_id = match (keep_track.latest_play_id, absorb_track.latest_play_id) {
+            (Some(left), Some(right)) => Some(if left.0 >= right.0 { left } else { right }),
+            (left, right) => left.or(right),
+        };
+        // the kept track may be newer than plays of the absorbed track
+        let earliest_play_ts = (absorb_track.earliest_play_bound()
+            < keep_track.earliest_play_bound())
+        .then(|| absorb_track.earliest_play_bound());
+
+        items.push(TransactWriteItem::Update(build_track_merge_update(
+            table_name,
+            BuildTrackMergeUpdateInput {
+                pk: TrackInDB::get_pk(station.id),
+                sk: TrackInDB::get_sk(keep),
+                play_count: absorb_track.play_count,
+                latest_play_id: latest_play_id.map(|play_id| play_id.to_string()),
+                earliest_play_timestamp: earliest_play_ts.as_ref().map(ziso_timestamp),
+                aliases: serde_dynamo::to_attribute_value(&aliases)?,
+                update_timestamp: ziso_timestamp(&now),
+                locked_timestamp: ziso_timestamp(&keep_track.updated_ts),
+            },
+        )?));
+
+        items.push(TransactWriteItem::Delete(build_track_delete(
+            table_name,
+            BuildTrackDeleteInput {
+                pk: TrackInDB::get_pk(station.id),
+                sk: TrackInDB::get_sk(absorb),
+                locked_timestamp: ziso_timestamp(&absorb_track.updated_ts),
+            },
+        )?));
+
+        items.push(TransactWriteItem::Put(build_track_redirect_put(
+            table_name,
+            BuildTrackRedirectPutInput {
+                item: serde_dynamo::to_item(TrackRedirectInDB::new(station.id, absorb, keep, now))?,
+            },
+        )?));
+
+        let latest_play_absorbed = station
+            .latest_play
+            .as_ref()
+            .is_some_and(|latest_play| latest_play.track_id == absorb);
+        let previous_play_absorbed = station
+            .previous_play
+            .as_ref()
+            .is_some_and(|previous_play| previous_play.track_id == absorb);
+
+        items.push(TransactWriteItem::Update(
+            build_station_track_removed_update(
+                table_name,
+                BuildStationTrackRemovedUpdateInput {
+                    pk: StationInDB::get_pk(),
+                    sk: StationInDB::get_sk(station.id),
+                    latest_play_track_id: latest_play_absorbed.then(|| keep.to_string()),
+                    previous_play_track_id: previous_play_absorbed.then(|| keep.to_string()),
+                    update_timestamp: ziso_timestamp(&now),
+                    locked_timestamp: ziso_timestamp(&station.updated_ts),
+                },
+            )?,
+        ));
+
+        self.context.backend.transact_write_items(items).await?;
+
+        station.updated_ts = now;
+        station.track_count -= 1;
+        if let Some(latest_play) = station.latest_play.as_mut()
+            && latest_play_absorbed
+        {
+            latest_play.track_id = keep;
+        }
+        if let Some(previous_play) = station.previous_play.as_mut()
+            && previous_play_absorbed
+        {
+            previous_play.track_id = keep;
+        }
+
+        keep_track.play_count += absorb_track.play_count;
+        keep_track.latest_play_id = latest_play_id;
+        if earliest_play_ts.is_some() {
+            keep_track.earliest_play_ts = earliest_play_ts;
+        }
+        keep_track.aliases = aliases;
+        keep_track.updated_ts = now;
+
+        Ok(keep_track)
+    }
+
+    /// Marks an existing track as a song or not, e.g. after a station rule was added for
+    /// jingles that were already logged.
+    pub async fn set_is_song(
+        &self,
+        station_id: StationId,
+        track_id: TrackId,
+        is_song: bool,
+    ) -> Result<()> {
+        let update = build_track_is_song_update(
+            self.context.backend.table_name(),
+            BuildTrackIsSongUpdateInput {
+                pk: TrackInDB::get_pk(station_id),
+                sk: TrackInDB::get_sk(track_id),
+                is_song,
+                update_timestamp: ziso_timestamp(&self.context.clock.now()),
+            },
+        )?;
+
+        match self.context.backend.update_item(update).await {
+            Err(Error::ConditionFailed(_)) => Err(Error::NotFound),
+            result => result,
+        }
+    }
+
+    /// Changes the artist and title of a track and moves its lookup item to the new artist and
+    /// title in one transaction. With `keep_alias` the previous lookup items keep resolving to
+    /// the track, otherwise they are removed.
+    ///
+    /// Fails with [`Error::Conflict`] if the new artist and title already resolve to another
+    /// track, [`CRUDTrack::merge_tracks`] is the way to combine those.
+    pub async fn update_track_metadata(
+        &self,
+        station: &StationInDB,
+        track_id: TrackId,
+        update: TrackMetadataUpdate,
+    ) -> Result<TrackInDB> {
+        let mut track = self
+            .get_track_internal(station.id, track_id, true)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        let rules = &station.normalization;
+        let new_key = TrackAlias::normalized(rules, &update.artist, &update.title);
+
+        let new_key_exists = match self
+            .get_track_metadata(station.id, &new_key.artist, &new_key.title)
+            .await?
+        {
+            Some(track_metadata) if track_metadata.track_id != track_id => {
+                return Err(Error::Conflict(format!(
+                    "artist and title already belong to track {}",
+                    track_metadata.track_id.0
+                )));
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        let table_name = self.context.backend.table_name();
+        let now = self.context.clock.now();
+        let mut items = vec![];
+
+        let mut aliases: Vec<TrackAlias> = track
+            .aliases
+            .iter()
+            .filter(|alias| **alias != new_key)
+            .cloned()
+            .collect();
+
+        let previous_keys = [
+            TrackAlias::normalized(rules, &track.artist, &track.title),
+            TrackAlias::verbatim(&track.artist, &track.title),
+        ];
+        for (index, previous_key) in previous_keys.iter().enumerate() {
+            if *previous_key == new_key || previous_keys[..index].contains(previous_key) {
+                continue;
+            }
+
+            let track_metadata = self
+                .get_track_metadata(station.id, &previous_key.artist, &previous_key.title)
+                .await?;
+            if !track_metadata.is_some_and(|track_metadata| track_metadata.track_id == track_id) {
+                continue;
+            }
+
+            if update.keep_alias {
+                if !aliases.contains(previous_key) {
+                    aliases.push(previous_key.clone());
+                }
+            } else {
+                items.push(TransactWriteItem::Delete(build_track_metadata_delete(
+                    table_name,
+                    BuildTrackMetadataDeleteInput {
+                        pk: TrackMetadataInDB::get_pk(station.id, &previous_key.artist),
+                        sk: TrackMetadataInDB::get_sk(&previous_key.title),
+                        track_id: track_id.to_string(),
+                    },
+                )?));
+            }
+        }
+
+        if !new_key_exists {
+            items.push(TransactWriteItem::Put(build_track_metadata_put(
+                table_name,
+                BuildTrackMetadataPutInput {
+                    item: serde_dynamo::to_item(TrackMetadataCreateInDB::from_alias(
+                        station.id, &new_key, track_id,
+                    ))?,
+                },
+            )?));
+        }
+
+        items.push(TransactWriteItem::Update(build_track_rename_update(
+            table_name,
+            BuildTrackRenameUpdateInput {
+                pk: TrackInDB::get_pk(station.id),
+                sk: TrackInDB::get_sk(track_id),
+                artist: update.artist.clone(),
+                title: update.title.clone(),
+                aliases: serde_dynamo::to_attribute_value(&aliases)?,
+                update_timestamp: ziso_timestamp(&now),
+                locked_timestamp: ziso_timestamp(&track.updated_ts),
+            },
+        )?));
+
+        self.context.backend.transact_write_items(items).await?;
+
+        track.artist = update.artist;
+        track.title = update.title;
+        track.aliases = aliases;
+        track.updated_ts = now;
+
+        Ok(track)
+    }
+
+    /// Builds the transaction items that delete a play and take it off its track. The track
+    /// is deleted along with its lookup items if this was its only play. The station is left
+    /// to the caller.
+    pub(crate) async fn prepare_play_removal(
+        &self,
+        station: &StationInDB,
+        play_id: PlayId,
+        track_id: TrackId,
+        timestamp: DateTime<Utc>,
+    ) -> Result<PlayRemoval> {
+        let play_datetime: DateTime<Utc> = play_id.datetime().into();
+
+        let mut items = vec![TransactWriteItem::Delete(build_play_delete(
+            self.context.backend.table_name(),
+            BuildPlayDeleteInput {
+                pk: PlayInDB::get_pk(station.id, &play_datetime),
+                sk: PlayInDB::get_sk(play_id),
+                track_id: track_id.to_string(),
+            },
+        )?)];
+
+        let removal = self
+            .prepare_track_play_removal(station, play_id, track_id, timestamp)
+            .await?;
+        items.extend(removal.items);
+
+        Ok(PlayRemoval {
+            items,
+            track_deleted: removal.track_deleted,
+        })
+    }
+
+    /// Builds the transaction items that point a play at track `to` and move it from the
+    /// play count of its current track to `to`. The previous track is deleted along with its
+    /// lookup items if this was its only play. The station is left to the caller.
+    pub(crate) async fn prepare_play_reassignment(
+        &self,
+        station: &StationInDB,
+        play_id: PlayId,
+        from: TrackId,
+        to: TrackId,
+        timestamp: DateTime<Utc>,
+    ) -> Result<PlayRemoval> {
+        if from == to {
+            return Err(Error::InvalidInput("play already belongs to the track"));
+        }
+
+        let to_track = self
+            .get_track_internal(station.id, to, true)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        let table_name = self.context.backend.table_name();
+        let play_datetime: DateTime<Utc> = play_id.datetime().into();
+
+        let mut items = vec![TransactWriteItem::Update(build_play_track_update(
+            table_name,
+            BuildPlayTrackUpdateInput {
+                pk: PlayInDB::get_pk(station.id, &play_datetime),
+                sk: PlayInDB::get_sk(play_id),
+                from_track_id: from.to_string(),
+                to_track_id: to.to_string(),
+                gsi1pk: TrackPlayInDB::get_gsi1pk(to, &play_datetime),
+            },
+        )?)];
+
+        let removal = self
+            .prepare_track_play_removal(station, play_id, from, timestamp)
+            .await?;
+        items.extend(removal.items);
+
+        let is_latest_play = to_track
+            .latest_play_id
+            .is_none_or(|latest_play_id| latest_play_id.0 < play_id.0);
+        // the play may be older than the track it is moved to
+        let is_earliest_play = play_datetime < to_track.earliest_play_bound();
+
+        items.push(TransactWriteItem::Update(build_track_play_added_update(
+            table_name,
+            BuildTrackPlayAddedUpdateInput {
+                pk: TrackInDB::get_pk(station.id),
+                sk: TrackInDB::get_sk(to),
+                latest_play_id: is_latest_play.then(|| play_id.to_string()),
+                earliest_play_timestamp: is_earliest_play.then(|| ziso_timestamp(&play_datetime)),
+                update_timestamp: ziso_timestamp(&timestamp),
+                locked_timestamp: ziso_timestamp(&to_track.updated_ts),
+            },
+        )?));
+
+        Ok(PlayRemoval {
+            items,
+            track_deleted: removal.track_deleted,
+        })
+    }
+
+    /// Builds the transaction items that take a play off the play count of its track, or
+    /// delete the track along with its lookup items and the redirects resolving to it if this
+    /// was its only play.
+    async fn prepare_track_play_removal(
+        &self,
+        station: &StationInDB,
+        play_id: PlayId,
+        track_id: TrackId,
+        timestamp: DateTime<Utc>,
+    ) -> Result<PlayRemoval> {
+        let track = self
+            .get_track_internal(station.id, track_id, true)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+        let table_name = self.context.backend.table_name();
+        let mut items = vec![];
+
+        let track_deleted = track.play_count <= 1;
+        if track_deleted {
+            items.push(TransactWriteItem::Delete(build_track_delete(
+                table_name,
+                BuildTrackDeleteInput {
+                    pk: TrackInDB::get_pk(station.id),
+                    sk: TrackInDB::get_sk(track_id),
+                    locked_timestamp: ziso_timestamp(&track.updated_ts),
+                },
+            )?));
+
+            for alias in lookup_keys_of_track(&track, &station.normalization) {
+                let track_metadata = self
+                    .get_track_metadata(station.id, &alias.artist, &alias.title)
+                    .await?;
+
+                if track_metadata.is_some_and(|track_metadata| track_metadata.track_id == track_id)
+                {
+                    items.push(TransactWriteItem::Delete(build_track_metadata_delete(
+                        table_name,
+                        BuildTrackMetadataDeleteInput {
+                            pk: TrackMetadataInDB::get_pk(station.id, &alias.artist),
+                            sk: TrackMetadataInDB::get_sk(&alias.title),
+                            track_id: track_id.to_string(),
+                        },
+                    )?));
+                }
+            }
+
+            for redirect in self.list_redirects_to_track(station.id, track_id).await? {
+                items.push(TransactWriteItem::Delete(build_track_redirect_delete(
+                    table_name,
+                    BuildTrackRedirectDeleteInput {
+                        pk: TrackInDB::get_pk(station.id),
+                        sk: TrackRedirectInDB::get_sk(redirect.id),
+                        track_id: redirect.track_id.to_string(),
+                    },
+                )?));
+            }
         } else {
-            Ok((vec![], next_key))
+            let latest_play_id = if track.latest_play_id == Some(play_id) {
+                self.find_latest_play_of_track(station.id, &track, play_id)
+                    .await?
+            } else {
+                None
+            };
+
+            items.push(TransactWriteItem::Update(build_track_play_removed_update(
+                table_name,
+                BuildTrackPlayRemovedUpdateInput {
+                    pk: TrackInDB::get_pk(station.id),
+                    sk: TrackInDB::get_sk(track_id),
+                    latest_play_id: latest_play_id.map(|play_id| play_id.to_string()),
+                    update_timestamp: ziso_timestamp(&timestamp),
+                    locked_timestamp: ziso_timestamp(&track.updated_ts),
+                },
+            )?));
+        }
+
+        Ok(PlayRemoval {
+            items,
+            track_deleted,
+        })
+    }
+
+    /// Lists the redirects resolving to a track, directly or through the redirect of a track
+    /// merged into it before.
+    async fn list_redirects_to_track(
+        &self,
+        station_id: StationId,
+        track_id: TrackId,
+    ) -> Result<Vec<TrackRedirectInDB>> {
+        let mut redirects: Vec<TrackRedirectInDB> = vec![];
+
+        let mut exclusive_start_key = None;
+        loop {
+            let resp = self
+                .context
+                .backend
+                .query_prefix(
+                    QueryPrefixInput {
+                        pk: TrackInDB::get_pk(station_id),
+                        sk_prefix: TrackRedirectInDB::get_sk_prefix(),
+                        scan_forward: true,
+                        exclusive_start_key,
+                    },
+                    QueryConfig {
+                        limit: STATION_KEYS_PAGE_SIZE,
+                        projected_fields: ProjectedFields::All,
+                    },
+                )
+                .await?;
+
+            redirects.extend(serde_dynamo::from_items::<_, TrackRedirectInDB>(
+                resp.items,
+            )?);
+
+            match resp.last_evaluated_key {
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(serde_dynamo::from_item(last_evaluated_key)?);
+                }
+                None => break,
+            }
+        }
+
+        let mut targets = HashSet::from([track_id]);
+        let mut resolving = vec![];
+        loop {
+            let (found, rest): (Vec<_>, Vec<_>) = redirects
+                .into_iter()
+                .partition(|redirect| targets.contains(&redirect.track_id));
+            if found.is_empty() {
+                return Ok(resolving);
+            }
+
+            targets.extend(found.iter().map(|redirect| redirect.id));
+            resolving.extend(found);
+            redirects = rest;
+        }
+    }
+
+    /// Finds the latest play of a track other than `excluding`, walking the monthly index
+    /// partitions back from the month of `excluding` to the earliest play of the track.
+    async fn find_latest_play_of_track(
+        &self,
+        station_id: StationId,
+        track: &TrackInDB,
+        excluding: PlayId,
+    ) -> Result<Option<PlayId>> {
+        let track_id = track.id;
+        let earliest_play_bound = track.earliest_play_bound();
+        let mut partition_datetime: DateTime<Utc> = excluding.datetime().into();
+
+        loop {
+            let mut exclusive_start_key = None;
+
+            loop {
+                let resp = self
+                    .context
+                    .backend
+                    .query_prefix_gsi1(
+                        QueryPrefixGsi1Input {
+                            gsi1pk: TrackPlayInDB::get_gsi1pk(track_id, &partition_datetime),
+                            sk_prefix: TrackPlayInDB::get_sk_prefix(),
+                            pk_prefix: Some(PlayInDB::get_pk_station_prefix(station_id)),
+                            scan_forward: false,
+                            exclusive_start_key,
+                        },
+                        QueryConfig {
+                            limit: 2,
+                            projected_fields: ProjectedFields::Some(&["pk", "sk", "gsi1pk", "id"]),
+                        },
+                    )
+                    .await?;
+
+                let plays: Vec<TrackPlayKeyInDB> = serde_dynamo::from_items(resp.items)?;
+                if let Some(play) = plays.into_iter().find(|play| play.id != excluding) {
+                    return Ok(Some(play.id));
+                }
+
+                match resp.last_evaluated_key {
+                    Some(last_evaluated_key) => {
+                        exclusive_start_key = Some(serde_dynamo::from_item(last_evaluated_key)?);
+                    }
+                    None => break,
+                }
+            }
+
+            partition_datetime = truncate_datetime_to_months(partition_datetime)
+                .expect("truncate datetime to months")
+                - Duration::nanoseconds(1);
+
+            if partition_datetime < earliest_play_bound {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Lists the keys of every track and redirect of a station, and of the lookup items
+    /// resolving to its tracks, for deleting the station.
+    pub(crate) async fn list_station_track_keys(
+        &self,
+        station: &StationInDB,
+    ) -> Result<StationTrackKeys> {
+        let mut keys = StationTrackKeys::default();
+        let mut seen_track_metadata = HashSet::new();
+
+        let mut exclusive_start_key = None;
+        loop {
+            let resp = self
+                .context
+                .backend
+                .query_prefix(
+                    QueryPrefixInput {
+                        pk: TrackInDB::get_pk(station.id),
+                        sk_prefix: TrackInDB::get_sk_prefix(),
+                        scan_forward: true,
+                        exclusive_start_key,
+                    },
+                    QueryConfig {
+                        limit: STATION_KEYS_PAGE_SIZE,
+                        projected_fields: ProjectedFields::All,
+                    },
+                )
+                .await?;
+
+            let tracks: Vec<TrackInDB> = serde_dynamo::from_items(resp.items)?;
+            for track in tracks {
+                for alias in lookup_keys_of_track(&track, &station.normalization) {
+                    let key = Key {
+                        pk: TrackMetadataInDB::get_pk(station.id, &alias.artist),
+                        sk: TrackMetadataInDB::get_sk(&alias.title),
+                    };
+                    if seen_track_metadata.contains(&key) {
+                        continue;
+                    }
+
+                    let track_metadata = self
+                        .get_track_metadata(station.id, &alias.artist, &alias.title)
+                        .await?;
+                    if track_metadata
+                        .is_some_and(|track_metadata| track_metadata.track_id == track.id)
+                    {
+                        seen_track_metadata.insert(key.clone());
+                        keys.track_metadata.push(key);
+                    }
+                }
+
+                keys.tracks.push(Key {
+                    pk: TrackInDB::get_pk(station.id),
+                    sk: TrackInDB::get_sk(track.id),
+                });
+            }
+
+            match resp.last_evaluated_key {
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(serde_dynamo::from_item(last_evaluated_key)?);
+                }
+                None => break,
+            }
+        }
+
+        let mut exclusive_start_key = None;
+        loop {
+            let resp = self
+                .context
+                .backend
+                .query_prefix(
+                    QueryPrefixInput {
+                        pk: TrackInDB::get_pk(station.id),
+                        sk_prefix: TrackRedirectInDB::get_sk_prefix(),
+                        scan_forward: true,
+                        exclusive_start_key,
+                    },
+                    QueryConfig {
+                        limit: STATION_KEYS_PAGE_SIZE,
+                        projected_fields: ProjectedFields::Some(&["pk", "sk"]),
+                    },
+                )
+                .await?;
+
+            keys.redirects
+                .extend(serde_dynamo::from_items::<_, Key>(resp.items)?);
+
+            match resp.last_evaluated_key {
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(serde_dynamo::from_item(last_evaluated_key)?);
+                }
+                None => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Points every play of track `from` at track `to`, walking the monthly index partitions
+    /// of `from` back to its earliest play.
+    async fn move_plays_of_track(
+        &self,
+        station_id: StationId,
+        from_track: &TrackInDB,
+        to: TrackId,
+    ) -> Result<()> {
+        let from = from_track.id;
+        let earliest_play_bound = from_track.earliest_play_bound();
+        let mut partition_datetime = self.context.clock.now();
+
+        loop {
+            let mut exclusive_start_key = None;
+
+            loop {
+                let resp = self
+                    .context
+                    .backend
+                    .query_prefix_gsi1(
+                        QueryPrefixGsi1Input {
+                            gsi1pk: TrackPlayInDB::get_gsi1pk(from, &partition_datetime),
+                            sk_prefix: TrackPlayInDB::get_sk_prefix(),
+                            pk_prefix: Some(PlayInDB::get_pk_station_prefix(station_id)),
+                            scan_forward: true,
+                            exclusive_start_key,
+                        },
+                        QueryConfig {
+                            limit: MOVE_PLAYS_PAGE_SIZE,
+                            projected_fields: ProjectedFields::Some(&["pk", "sk", "gsi1pk", "id"]),
+                        },
+                    )
+                    .await?;
+
+                let plays: Vec<TrackPlayKeyInDB> = serde_dynamo::from_items(resp.items)?;
+                try_join_all(plays.into_iter().map(|play| self.move_play(play, from, to))).await?;
+
+                match resp.last_evaluated_key {
+                    Some(last_evaluated_key) => {
+                        exclusive_start_key = Some(serde_dynamo::from_item(last_evaluated_key)?);
+                    }
+                    None => break,
+                }
+            }
+
+            partition_datetime = truncate_datetime_to_months(partition_datetime)
+                .expect("truncate datetime to months")
+                - Duration::nanoseconds(1);
+
+            if partition_datetime < earliest_play_bound {
+                return Ok(());
+            }
         }
     }
+
+    async fn move_play(&self, play: TrackPlayKeyInDB, from: TrackId, to: TrackId) -> Result<()> {
+        let play_datetime: DateTime<Utc> = play.id.datetime().into();
+
+        let update = build_play_track_update(
+            self.context.backend.table_name(),
+            BuildPlayTrackUpdateInput {
+                pk: play.pk,
+                sk: play.sk,
+                from_track_id: from.to_string(),
+                to_track_id: to.to_string(),
+                gsi1pk: TrackPlayInDB::get_gsi1pk(to, &play_datetime),
+            },
+        )?;
+
+        match self.context.backend.update_item(update).await {
+            // the index is eventually consistent, the play may have been moved already
+            Err(Error::ConditionFailed(_)) => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Keys of every lookup item that may resolve to the track.
+fn lookup_keys_of_track(track: &TrackInDB, rules: &NormalizationRules) -> Vec<TrackAlias> {
+    let mut keys = vec![
+        TrackAlias::normalized(rules, &track.artist, &track.title),
+        TrackAlias::verbatim(&track.artist, &track.title),
+    ];
+
+    for alias in &track.aliases {
+        if !keys.contains(alias) {
+            keys.push(alias.clone());
+        }
+    }
+    keys.dedup();
+
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use aws_sdk_dynamodb::types::Update;
+    use ulid::Ulid;
+
+    use crate::backend::testing::{FixedClock, TestPlay, list_all_plays_of_track, memory_context};
+    use crate::backend::{
+        BatchGetItemOutput, MemoryBackend, QueryOutput, QueryRangeInput, TransactWriteItem,
+    };
+    use crate::clock::{IdGenerator, UlidGenerator};
+    use crate::crud::logger::CRUDLogger;
+    use crate::crud::play::CRUDPlay;
+    use crate::crud::station::CRUDStation;
+    use crate::crud::station::models::StationInDBCreate;
+    use crate::crud::track::models::{TrackAlias, TrackMetadataUpdate};
+
+    /// Leaves the last key of every batch unprocessed until it has been asked once.
+    struct UnprocessedKeysBackend {
+        inner: MemoryBackend,
+        throttled: AtomicBool,
+    }
+
+    impl StorageBackend for UnprocessedKeysBackend {
+        fn table_name(&self) -> &str {
+            self.inner.table_name()
+        }
+
+        async fn get_item(&self, key: Key, config: GetItemConfig) -> Result<Option<Item>> {
+            self.inner.get_item(key, config).await
+        }
+
+        async fn put_item(&self, item: Item) -> Result<()> {
+            self.inner.put_item(item).await
+        }
+
+        async fn update_item(&self, update: Update) -> Result<()> {
+            self.inner.update_item(update).await
+        }
+
+        async fn query_prefix(
+            &self,
+            input: QueryPrefixInput,
+            config: QueryConfig,
+        ) -> Result<QueryOutput> {
+            self.inner.query_prefix(input, config).await
+        }
+
+        async fn query_range(
+            &self,
+            input: QueryRangeInput,
+            config: QueryConfig,
+        ) -> Result<QueryOutput> {
+            self.inner.query_range(input, config).await
+        }
+
+        async fn query_prefix_gsi1(
+            &self,
+            input: QueryPrefixGsi1Input,
+            config: QueryConfig,
+        ) -> Result<QueryOutput> {
+            self.inner.query_prefix_gsi1(input, config).await
+        }
+
+        async fn batch_get_item(
+            &self,
+            mut input: BatchGetItemInput,
+            config: BatchGetItemConfig,
+        ) -> Result<BatchGetItemOutput> {
+            let unprocessed_keys = if self.throttled.swap(false, Ordering::SeqCst) {
+                input.keys.pop().into_iter().collect()
+            } else {
+                vec![]
+            };
+
+            let mut output = self.inner.batch_get_item(input, config).await?;
+            output.unprocessed_keys = unprocessed_keys;
+
+            Ok(output)
+        }
+
+        async fn transact_write_items(&self, items: Vec<TransactWriteItem>) -> Result<()> {
+            self.inner.transact_write_items(items).await
+        }
+    }
+
+    async fn put_tracks<B: StorageBackend>(
+        backend: &B,
+        station_id: StationId,
+        count: usize,
+    ) -> Vec<TrackId> {
+        let mut track_ids = vec![];
+
+        for index in 0..count {
+            let track = TrackInDB::new(
+                station_id,
+                "artist",
+                format!("title {index}"),
+                true,
+                Utc::now(),
+                &UlidGenerator,
+            );
+            track_ids.push(track.id);
+            backend
+                .put_item(serde_dynamo::to_item(track).unwrap())
+                .await
+                .unwrap();
+        }
+
+        track_ids
+    }
+
+    #[tokio::test]
+    async fn test_batch_get_tracks_chunks_in_request_order() {
+        let context = memory_context();
+        let station_id = StationId(Ulid::generate());
+        let mut track_ids = put_tracks(&context.backend, station_id, 250).await;
+
+        track_ids.reverse();
+        let missing_track_id = TrackId(Ulid::generate());
+        let mut requested = vec![missing_track_id];
+        requested.extend(&track_ids);
+        requested.push(track_ids[0]);
+
+        let tracks = CRUDTrack::new(context)
+            .batch_get_tracks_minimal(station_id, requested.iter())
+            .await
+            .unwrap();
+
+        let mut expected = track_ids.clone();
+        expected.push(track_ids[0]);
+        assert_eq!(
+            tracks
+                .iter()
+                .map(|track| TrackId(track.id))
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_get_tracks_retries_unprocessed_keys() {
+        let context = Arc::new(Context::with_backend(UnprocessedKeysBackend {
+            inner: MemoryBackend::new("tablename".to_owned()),
+            throttled: AtomicBool::new(true),
+        }));
+        let station_id = StationId(Ulid::generate());
+        let track_ids = put_tracks(&context.backend, station_id, 3).await;
+
+        let tracks = CRUDTrack::new(context)
+            .batch_get_tracks(station_id, track_ids.iter())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tracks.iter().map(|track| track.id).collect::<Vec<_>>(),
+            track_ids
+        );
+    }
+
+    /// Keeps IDs generated within the same millisecond in order.
+    struct MonotonicIdGenerator(std::sync::Mutex<ulid::Generator>);
+
+    impl IdGenerator for MonotonicIdGenerator {
+        fn generate(&self, datetime: DateTime<Utc>) -> Ulid {
+            self.0
+                .lock()
+                .unwrap()
+                .generate_from_datetime(datetime.into())
+                .unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_tracks_paginated() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let other_station = crud_station
+            .create_station(StationInDBCreate {
+                name: "otherstation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        for artist in ["artist a", "artist b", "artist c"] {
+            crud_logger
+                .add_play(&mut station, TestPlay::new(artist, "title"))
+                .await
+                .unwrap();
+        }
+
+        let (first_page, next_token) = crud_track.list_tracks(station.id, 2, None).await.unwrap();
+        let next_token = next_token.unwrap();
+        assert_eq!(first_page.len(), 2);
+
+        let (second_page, next_token_after) = crud_track
+            .list_tracks(station.id, 2, Some(&next_token))
+            .await
+            .unwrap();
+        assert_eq!(second_page.len(), 1);
+        assert!(next_token_after.is_none());
+        assert!(!first_page.iter().any(|track| track.id == second_page[0].id));
+
+        // a token only continues the listing it came from
+        assert!(matches!(
+            crud_track
+                .list_tracks(other_station.id, 2, Some(&next_token))
+                .await,
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            crud_track
+                .list_tracks_by_artist(&station, "artist a", 2, Some(&next_token))
+                .await,
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_merge_tracks() {
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_id_generator(MonotonicIdGenerator(Default::default())),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut results = vec![];
+        for artist in ["artist a", "other", "artist b", "artist a", "artist b"] {
+            results.push(
+                crud_logger
+                    .add_play(&mut station, TestPlay::new(artist, "title"))
+                    .await
+                    .unwrap(),
+            );
+        }
+        let keep = results[0].track_id;
+        let absorb = results[2].track_id;
+        assert_eq!(station.track_count, 3);
+
+        let track = crud_track
+            .merge_tracks(&mut station, keep, absorb)
+            .await
+            .unwrap();
+        assert_eq!(track.play_count, 4);
+        assert_eq!(track.latest_play_id, Some(results[4].play_id));
+        assert_eq!(station.track_count, 2);
+        assert_eq!(station.latest_play.as_ref().unwrap().track_id, keep);
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, station);
+
+        let stored_track = crud_track
+            .get_track(station.id, keep)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored_track.play_count, 4);
+        assert!(
+            crud_track
+                .get_track(station.id, absorb)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            crud_track
+                .get_track_redirect(station.id, absorb)
+                .await
+                .unwrap(),
+            Some(keep)
+        );
+
+        let (plays, _) = crud_track
+            .list_plays_of_track(station.id, keep, 10, None)
+            .await
+            .unwrap();
+        assert_eq!(plays.len(), 4);
+        let (plays, _) = crud_track
+            .list_plays_of_track(station.id, absorb, 10, None)
+            .await
+            .unwrap();
+        assert!(plays.is_empty());
+
+        // the absorbed spelling now resolves to the kept track
+        let track_metadata = crud_track
+            .get_track_by_metadata(&station, "artist b", "title")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track_metadata.track_id, keep);
+
+        assert!(matches!(
+            crud_track.merge_tracks(&mut station, keep, absorb).await,
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            crud_track.merge_tracks(&mut station, keep, keep).await,
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_merge_tracks_after_partial_merge() {
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_id_generator(MonotonicIdGenerator(Default::default())),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut results = vec![];
+        for artist in ["artist a", "artist b", "artist a", "artist b"] {
+            results.push(
+                crud_logger
+                    .add_play(&mut station, TestPlay::new(artist, "title"))
+                    .await
+                    .unwrap(),
+            );
+        }
+        let keep = results[0].track_id;
+        let absorb = results[1].track_id;
+
+        // the station changes after it was read, the plays are moved but the transaction fails
+        let mut stale_station = station.clone();
+        crud_logger
+            .add_play(&mut station, TestPlay::new("other", "title"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            crud_track
+                .merge_tracks(&mut stale_station, keep, absorb)
+                .await,
+            Err(Error::ConditionFailed(_))
+        ));
+
+        assert_eq!(
+            list_all_plays_of_track(&crud_track, station.id, keep).await,
+            results
+                .iter()
+                .rev()
+                .map(|result| result.play_id)
+                .collect::<Vec<_>>()
+        );
+        let absorb_track = crud_track
+            .get_track(station.id, absorb)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(absorb_track.play_count, 2);
+
+        // running the merge again completes it
+        let track = crud_track
+            .merge_tracks(&mut station, keep, absorb)
+            .await
+            .unwrap();
+        assert_eq!(track.play_count, 4);
+        assert_eq!(track.latest_play_id, Some(results[3].play_id));
+        assert_eq!(station.track_count, 2);
+
+        let stored_station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        assert_eq!(stored_station, station);
+        assert!(
+            crud_track
+                .get_track(station.id, absorb)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            crud_track
+                .get_track_redirect(station.id, absorb)
+                .await
+                .unwrap(),
+            Some(keep)
+        );
+        assert_eq!(
+            list_all_plays_of_track(&crud_track, station.id, keep)
+                .await
+                .len(),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_tracks_into_newer_track() {
+        let datetime = |value: &str| DateTime::parse_from_rfc3339(value).unwrap().to_utc();
+
+        let context = Arc::new(
+            Context::with_backend(MemoryBackend::new("tablename".to_owned()))
+                .with_clock(FixedClock(datetime("2001-06-15T00:00:00Z"))),
+        );
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_play = CRUDPlay::new(context.clone());
+        let crud_track = CRUDTrack::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut results = vec![];
+        for (artist, played_at) in [
+            ("artist a", "2001-01-10T00:00:00Z"),
+            ("artist a", "2001-03-10T00:00:00Z"),
+            ("artist b", "2001-05-10T00:00:00Z"),
+            ("artist c", "2001-06-01T00:00:00Z"),
+        ] {
+            // another track in between, so each play is a new play
+            crud_logger
+                .add_play_at(
+                    &mut station,
+                    TestPlay::new("other", "title"),
+                    datetime(played_at) - Duration::minutes(5),
+                )
+                .await
+                .unwrap();
+            results.push(
+                crud_logger
+                    .add_play_at(
+                        &mut station,
+                        TestPlay::new(artist, "title"),
+                        datetime(played_at),
+                    )
+                    .await
+                    .unwrap(),
+            );
+        }
+        let older = results[0].track_id;
+        let keep = results[2].track_id;
+        let newest = results[3].track_id;
+
+        let track = crud_track
+            .merge_tracks(&mut station, keep, older)
+            .await
+            .unwrap();
+        assert_eq!(
+            track.earliest_play_ts,
+            Some(datetime("2001-01-10T00:00:00Z"))
+        );
+
+        // the plays of the older track are listed with the newer track they were merged into
+        assert_eq!(
+            list_all_plays_of_track(&crud_track, station.id, keep).await,
+            [results[2].play_id, results[1].play_id, results[0].play_id]
+        );
+
+        // deleting its latest play finds the next latest play among the merged plays
+        crud_play
+            .delete_play(station.id, results[2].play_id)
+            .await
+            .unwrap();
+        let track = crud_track
+            .get_track(station.id, keep)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(track.latest_play_id, Some(results[1].play_id));
+
+        // merging again moves the plays merged before
+        let mut station = crud_station.get_station(station.id).await.unwrap().unwrap();
+        let track = crud_track
+            .merge_tracks(&mut station, newest, keep)
+            .await
+            .unwrap();
+        assert_eq!(track.play_count, 3);
+        assert_eq!(
+            track.earliest_play_ts,
+            Some(datetime("2001-01-10T00:00:00Z"))
+        );
+        assert_eq!(
+            list_all_plays_of_track(&crud_track, station.id, newest).await,
+            [results[3].play_id, results[1].play_id, results[0].play_id]
+        );
+        assert!(
+            list_all_plays_of_track(&crud_track, station.id, keep)
+                .await
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_track_metadata() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let typo = crud_logger
+            .add_play(&mut station, TestPlay::new("Artsit", "title"))
+            .await
+            .unwrap()
+            .track_id;
+        let other = crud_logger
+            .add_play(&mut station, TestPlay::new("Other", "title"))
+            .await
+            .unwrap()
+            .track_id;
+
+        let lookup = async |station: &StationInDB, artist: &str| {
+            crud_track
+                .get_track_by_metadata(station, artist, "title")
+                .await
+                .unwrap()
+                .map(|track_metadata| track_metadata.track_id)
+        };
+
+        let result = crud_track
+            .update_track_metadata(
+                &station,
+                typo,
+                TrackMetadataUpdate {
+                    artist: "other".to_owned(),
+                    title: "Title".to_owned(),
+                    keep_alias: false,
+                },
+            )
+            .await;
+        assert!(matches!(result, Err(Error::Conflict(_))));
+
+        let track = crud_track
+            .update_track_metadata(
+                &station,
+                typo,
+                TrackMetadataUpdate {
+                    artist: "Artist".to_owned(),
+                    title: "title".to_owned(),
+                    keep_alias: true,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(track.artist, "Artist");
+        assert_eq!(track.aliases, vec![TrackAlias::verbatim("artsit", "title")]);
+        assert_eq!(lookup(&station, "Artist").await, Some(typo));
+        assert_eq!(lookup(&station, "Artsit").await, Some(typo));
+
+        let stored_track = crud_track
+            .get_track(station.id, typo)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored_track.artist, "Artist");
+        assert_eq!(stored_track.title, "title");
+        assert_eq!(stored_track.aliases, track.aliases);
+
+        // without an alias the previous spelling stops resolving to the track
+        crud_track
+            .update_track_metadata(
+                &station,
+                other,
+                TrackMetadataUpdate {
+                    artist: "Another".to_owned(),
+                    title: "title".to_owned(),
+                    keep_alias: false,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(lookup(&station, "Another").await, Some(other));
+        assert_eq!(lookup(&station, "Other").await, None);
+
+        // aliases move along when the track is merged
+        let track = crud_track
+            .merge_tracks(&mut station, other, typo)
+            .await
+            .unwrap();
+        assert!(
+            track
+                .aliases
+                .contains(&TrackAlias::verbatim("artsit", "title"))
+        );
+        assert_eq!(lookup(&station, "Artsit").await, Some(other));
+        assert_eq!(lookup(&station, "Artist").await, Some(other));
+    }
+
+    #[tokio::test]
+    async fn test_set_is_song() {
+        let context = memory_context();
+        let crud_station = CRUDStation::new(context.clone());
+        let crud_logger = CRUDLogger::new(context.clone());
+        let crud_track = CRUDTrack::new(context);
+
+        let mut station = crud_station
+            .create_station(StationInDBCreate {
+                name: "teststation".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let track_id = crud_logger
+            .add_play(&mut station, TestPlay::new("teststation", "title"))
+            .await
+            .unwrap()
+            .track_id;
+
+        crud_track
+            .set_is_song(station.id, track_id, false)
+            .await
+            .unwrap();
+        let track = crud_track
+            .get_track(station.id, track_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!track.is_song);
+
+        let result = crud_track
+            .set_is_song(station.id, Ulid::from_parts(1, 1).into(), false)
+            .await;
+        assert!(matches!(result, Err(Error::NotFound)));
+        assert!(
+            crud_track
+                .get_track(station.id, Ulid::from_parts(1, 1).into())
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
 }
@@ -1,12 +1,14 @@
 use std::ops::Deref;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 use utoipa::ToSchema;
 
+use crate::clock::IdGenerator;
 use crate::crud::play::models::PlayId;
 use crate::crud::station::models::StationId;
+use crate::crud::track::normalize::NormalizationRules;
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(transparent)]
@@ -35,12 +37,86 @@ pub struct TrackInDB {
     pub title: String,
     pub artist: String,
     pub is_song: bool,
+    #[serde(flatten)]
+    pub details: TrackDetails,
+    /// Lookup keys besides the one of the current artist and title that resolve to the track.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<TrackAlias>,
     pub play_count: usize,
     pub latest_play_id: Option<PlayId>,
+    /// Time of the earliest play of the track if that is older than the track, because older
+    /// plays were merged into it or added to it later.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earliest_play_ts: Option<DateTime<Utc>>,
     pub created_ts: DateTime<Utc>,
     pub updated_ts: DateTime<Utc>,
 }
 
+/// Normalized artist and title of a track lookup item.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackAlias {
+    pub artist: String,
+    pub title: String,
+}
+
+impl TrackAlias {
+    pub(crate) fn normalized(rules: &NormalizationRules, artist: &str, title: &str) -> Self {
+        Self {
+            artist: rules.normalize_artist(artist),
+            title: rules.normalize_title(title),
+        }
+    }
+
+    pub(crate) fn verbatim(artist: &str, title: &str) -> Self {
+        Self {
+            artist: artist.to_owned(),
+            title: title.to_owned(),
+        }
+    }
+}
+
+/// New artist and title of a track.
+#[derive(Debug)]
+pub struct TrackMetadataUpdate {
+    pub artist: String,
+    pub title: String,
+    /// Keep resolving the previous artist and title to the track.
+    pub keep_alias: bool,
+}
+
+/// Optional metadata reported by some sources in addition to artist and title. Missing
+/// fields are left out of the item so they can be filled in by a later play.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isrc: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artwork_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_track_id: Option<String>,
+}
+
+impl TrackDetails {
+    /// Copies the fields of `other` that are missing here.
+    pub fn fill_missing(&mut self, other: &Self) {
+        fn fill<T: Clone>(field: &mut Option<T>, other: &Option<T>) {
+            if field.is_none() {
+                field.clone_from(other);
+            }
+        }
+
+        fill(&mut self.album, &other.album);
+        fill(&mut self.duration_ms, &other.duration_ms);
+        fill(&mut self.isrc, &other.isrc);
+        fill(&mut self.artwork_url, &other.artwork_url);
+        fill(&mut self.provider_track_id, &other.provider_track_id);
+    }
+}
+
 impl TrackInDB {
     pub(crate) fn get_pk(station_id: StationId) -> String {
         format!("STATION#{}#TRACKS", station_id.0)
@@ -69,9 +145,10 @@ impl TrackInDB {
         artist: impl Into<String>,
         title: impl Into<String>,
         is_song: bool,
+        created_at: DateTime<Utc>,
+        id_generator: &dyn IdGenerator,
     ) -> Self {
-        let now = Utc::now();
-        let track_id = Ulid::generate().into();
+        let track_id = id_generator.generate(created_at).into();
 
         let title = title.into();
         let artist = artist.into();
@@ -83,12 +160,30 @@ impl TrackInDB {
             title,
             artist,
             is_song,
+            details: TrackDetails::default(),
+            aliases: vec![],
             play_count: 0,
             latest_play_id: None,
-            created_ts: now,
-            updated_ts: now,
+            earliest_play_ts: None,
+            created_ts: created_at,
+            updated_ts: created_at,
         }
     }
+
+    /// Time no play of the track is older than, lookups of its plays walk back to it.
+    pub(crate) fn earliest_play_bound(&self) -> DateTime<Utc> {
+        let track_creation: DateTime<Utc> = self.id.datetime().into();
+
+        self.earliest_play_ts
+            .map_or(track_creation, |earliest_play_ts| {
+                earliest_play_ts.min(track_creation)
+            })
+    }
+
+    pub fn with_details(mut self, details: TrackDetails) -> Self {
+        self.details = details;
+        self
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -100,17 +195,34 @@ pub struct TrackPlayInDB {
     pub track_id: Ulid,
 }
 
+/// Key of a play read from the `gsi1` index, used to update the play in the table.
+#[derive(Debug, Deserialize)]
+pub(crate) struct TrackPlayKeyInDB {
+    pub pk: String,
+    pub sk: String,
+    pub id: PlayId,
+}
+
 impl TrackPlayInDB {
     pub(crate) fn get_gsi1pk(track_id: TrackId, datetime: &DateTime<Utc>) -> String {
         let track_partition = datetime.format("%Y-%m").to_string();
         format!("TRACK#{}#{}", track_id.0, track_partition)
     }
 
+    /// Returns the start of the month of a `gsi1pk` of the track.
+    pub(crate) fn parse_gsi1pk(track_id: TrackId, gsi1pk: &str) -> Option<DateTime<Utc>> {
+        let month = gsi1pk.strip_prefix(&format!("TRACK#{}#", track_id.0))?;
+        let date = NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d").ok()?;
+
+        Some(date.and_time(NaiveTime::MIN).and_utc())
+    }
+
     pub(crate) fn get_sk_prefix() -> String {
         "PLAY#".to_owned()
     }
 }
 
+/// Keys of the track lookup items, artist and title are expected to be normalized already.
 pub(crate) trait TrackMetadataKeys {
     fn get_pk(station_id: StationId, artist: &str) -> String {
         format!("STATION#{}#ARTIST#{}", station_id.0, artist)
@@ -143,12 +255,72 @@ pub struct TrackMetadataCreateInDB {
 
 impl TrackMetadataKeys for TrackMetadataCreateInDB {}
 
-impl From<&TrackInDB> for TrackMetadataCreateInDB {
-    fn from(track: &TrackInDB) -> Self {
+impl TrackMetadataCreateInDB {
+    /// Builds the lookup item of a track keyed by its normalized artist and title.
+    pub(crate) fn new(
+        station_id: StationId,
+        rules: &NormalizationRules,
+        artist: &str,
+        title: &str,
+        track_id: TrackId,
+    ) -> Self {
+        Self {
+            pk: Self::get_pk(station_id, &rules.normalize_artist(artist)),
+            sk: Self::get_sk(&rules.normalize_title(title)),
+            track_id,
+        }
+    }
+
+    pub(crate) fn from_alias(station_id: StationId, alias: &TrackAlias, track_id: TrackId) -> Self {
+        Self {
+            pk: Self::get_pk(station_id, &alias.artist),
+            sk: Self::get_sk(&alias.title),
+            track_id,
+        }
+    }
+
+    pub(crate) fn for_track(track: &TrackInDB, rules: &NormalizationRules) -> Self {
+        Self::new(
+            track.station_id(),
+            rules,
+            &track.artist,
+            &track.title,
+            track.id,
+        )
+    }
+}
+
+/// Left in place of a track that was merged into another track, so the old ID keeps resolving.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrackRedirectInDB {
+    pk: String,
+    sk: String,
+    pub id: TrackId,
+    pub track_id: TrackId,
+    pub created_ts: DateTime<Utc>,
+}
+
+impl TrackRedirectInDB {
+    pub(crate) fn get_sk(track_id: TrackId) -> String {
+        format!("REDIRECT#{}", track_id.0)
+    }
+
+    pub(crate) fn get_sk_prefix() -> String {
+        "REDIRECT#".to_owned()
+    }
+
+    pub(crate) fn new(
+        station_id: StationId,
+        from: TrackId,
+        to: TrackId,
+        created_at: DateTime<Utc>,
+    ) -> Self {
         Self {
-            pk: Self::get_pk(track.station_id(), &track.artist),
-            sk: Self::get_sk(&track.title),
-            track_id: track.id,
+            pk: TrackInDB::get_pk(station_id),
+            sk: Self::get_sk(from),
+            id: from,
+            track_id: to,
+            created_ts: created_at,
         }
     }
 }
@@ -0,0 +1,247 @@
+//! Normalization of artist and title before they are used as the track lookup key, so that
+//! spelling variants of the same track are matched. The original strings are still stored on
+//! the track and the latest play for display.
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// Words treated as a featuring marker, compared case-insensitively.
+const FEATURING_MARKERS: &[&str] = &["feat.", "feat", "ft.", "ft", "featuring"];
+const FEATURING_CANONICAL: &str = "feat.";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnicodeForm {
+    /// Keep the code points as they are.
+    None,
+    /// Canonical composition, e.g. a letter followed by a combining accent is composed.
+    #[default]
+    Nfc,
+    /// Compatibility composition, additionally folds full-width and other presentation forms.
+    Nfkc,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BracketSuffix {
+    #[default]
+    Keep,
+    /// Drop trailing `(...)` and `[...]` groups from the title, e.g. `(Radio Edit)`.
+    Strip,
+}
+
+/// Rules applied to artist and title to build the track lookup key. Stored per station, fields
+/// missing from the stored rules take their default.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalizationRules {
+    pub unicode_form: UnicodeForm,
+    pub case_fold: bool,
+    pub collapse_whitespace: bool,
+    /// Rewrite `ft.`, `ft`, `feat` and `featuring` to `feat.`.
+    pub canonicalize_featuring: bool,
+    pub title_bracket_suffix: BracketSuffix,
+}
+
+impl Default for NormalizationRules {
+    fn default() -> Self {
+        Self {
+            unicode_form: UnicodeForm::Nfc,
+            case_fold: true,
+            collapse_whitespace: true,
+            canonicalize_featuring: true,
+            title_bracket_suffix: BracketSuffix::Keep,
+        }
+    }
+}
+
+impl NormalizationRules {
+    /// Rules that keep artist and title unchanged, the way tracks were matched before
+    /// normalization was introduced.
+    pub fn verbatim() -> Self {
+        Self {
+            unicode_form: UnicodeForm::None,
+            case_fold: false,
+            collapse_whitespace: false,
+            canonicalize_featuring: false,
+            title_bracket_suffix: BracketSuffix::Keep,
+        }
+    }
+
+    pub fn normalize_artist(&self, artist: &str) -> String {
+        self.normalize(artist, false)
+    }
+
+    pub fn normalize_title(&self, title: &str) -> String {
+        self.normalize(title, true)
+    }
+
+    fn normalize(&self, value: &str, is_title: bool) -> String {
+        let mut value: String = match self.unicode_form {
+            UnicodeForm::None => value.to_owned(),
+            UnicodeForm::Nfc => value.nfc().collect(),
+            UnicodeForm::Nfkc => value.nfkc().collect(),
+        };
+
+        if self.case_fold {
+            value = value.to_lowercase();
+        }
+
+        if self.canonicalize_featuring {
+            value = canonicalize_featuring(&value);
+        }
+
+        if is_title && self.title_bracket_suffix == BracketSuffix::Strip {
+            value = strip_bracket_suffix(&value).to_owned();
+        }
+
+        if self.collapse_whitespace {
+            value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+
+        value
+    }
+}
+
+fn canonicalize_featuring(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    // walk alternating runs of whitespace and words, so the whitespace is kept as is
+    while !rest.is_empty() {
+        let run_end = if rest.starts_with(char::is_whitespace) {
+            rest.find(|c: char| !c.is_whitespace())
+        } else {
+            rest.find(char::is_whitespace)
+        }
+        .unwrap_or(rest.len());
+
+        let (run, remaining) = rest.split_at(run_end);
+        result.push_str(&canonicalize_featuring_word(run));
+        rest = remaining;
+    }
+
+    result
+}
+
+fn canonicalize_featuring_word(word: &str) -> Cow<'_, str> {
+    // keep an opening bracket, e.g. "(ft. someone)"
+    let (bracket, marker) = match word.strip_prefix(['(', '[']) {
+        Some(marker) => (&word[..1], marker),
+        None => ("", word),
+    };
+
+    if FEATURING_MARKERS
+        .iter()
+        .any(|featuring| marker.eq_ignore_ascii_case(featuring))
+    {
+        Cow::Owned(format!("{bracket}{FEATURING_CANONICAL}"))
+    } else {
+        Cow::Borrowed(word)
+    }
+}
+
+fn strip_bracket_suffix(mut value: &str) -> &str {
+    loop {
+        let trimmed = value.trim_end();
+        let open = match trimmed.chars().last() {
+            Some(')') => '(',
+            Some(']') => '[',
+            _ => return value,
+        };
+
+        match trimmed.rfind(open) {
+            // never strip the whole title
+            Some(index) if !trimmed[..index].trim().is_empty() => value = &trimmed[..index],
+            _ => return value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use rstest::rstest;
+
+    use crate::backend::Item;
+
+    #[rstest]
+    #[case("Artist feat. X", "artist feat. x")]
+    #[case("ARTIST ft X", "artist feat. x")]
+    #[case("Artist Featuring X ", "artist feat. x")]
+    #[case("  Artist   (FT. X)", "artist (feat. x)")]
+    #[case("Craft Left", "craft left")]
+    #[case("Cafe\u{301}", "caf\u{e9}")]
+    #[case("\u{ff21}rtist", "\u{ff41}rtist")]
+    fn test_normalize_artist_default(#[case] artist: &str, #[case] expected: &str) {
+        assert_eq!(
+            NormalizationRules::default().normalize_artist(artist),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_normalize_nfkc_folds_full_width() {
+        let rules = NormalizationRules {
+            unicode_form: UnicodeForm::Nfkc,
+            ..Default::default()
+        };
+
+        assert_eq!(rules.normalize_artist("\u{ff21}rtist"), "artist");
+    }
+
+    #[rstest]
+    #[case("Title (Radio Edit)", "title")]
+    #[case("Title [Remastered 2011] (Live)", "title")]
+    #[case("(Untitled)", "(untitled)")]
+    #[case("Title (ft. X)", "title")]
+    #[case("Title (part 1", "title (part 1")]
+    fn test_normalize_title_strip_bracket_suffix(#[case] title: &str, #[case] expected: &str) {
+        let rules = NormalizationRules {
+            title_bracket_suffix: BracketSuffix::Strip,
+            ..Default::default()
+        };
+
+        assert_eq!(rules.normalize_title(title), expected);
+    }
+
+    #[test]
+    fn test_normalize_bracket_suffix_only_applies_to_title() {
+        let rules = NormalizationRules {
+            title_bracket_suffix: BracketSuffix::Strip,
+            ..Default::default()
+        };
+
+        assert_eq!(rules.normalize_artist("Artist (US)"), "artist (us)");
+    }
+
+    #[test]
+    fn test_normalize_verbatim() {
+        let rules = NormalizationRules::verbatim();
+
+        assert_eq!(
+            rules.normalize_title(" Title  ft X (Edit) "),
+            " Title  ft X (Edit) "
+        );
+    }
+
+    #[test]
+    fn test_rules_missing_fields_take_default() {
+        let item: Item =
+            serde_dynamo::to_item(HashMap::from([("title_bracket_suffix", "strip")])).unwrap();
+        let rules: NormalizationRules = serde_dynamo::from_item(item).unwrap();
+
+        assert_eq!(
+            rules,
+            NormalizationRules {
+                title_bracket_suffix: BracketSuffix::Strip,
+                ..Default::default()
+            }
+        );
+    }
+}
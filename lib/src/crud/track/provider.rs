@@ -1,207 +1,476 @@
 use std::collections::HashMap;
-use std::sync::Arc;
 
-use aws_sdk_dynamodb::error::SdkError;
-use aws_sdk_dynamodb::operation::batch_get_item::{BatchGetItemError, BatchGetItemOutput};
-use aws_sdk_dynamodb::operation::get_item::{GetItemError, GetItemOutput};
-use aws_sdk_dynamodb::operation::query::{QueryError, QueryOutput};
-use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes, Select};
-use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_sdk_dynamodb::error::BuildError;
+use aws_sdk_dynamodb::types::{AttributeValue, Delete, Put, Update};
 
-use crate::crud::Context;
+pub(super) struct BuildPlayTrackUpdateInput {
+    pub pk: String,
+    pub sk: String,
+    pub from_track_id: String,
+    pub to_track_id: String,
+    pub gsi1pk: String,
+}
 
-pub(super) struct DynamoDBProvider {
-    context: Arc<Context>,
+/// Points a play at another track, unless the play was already moved.
+pub fn build_play_track_update(
+    table_name: &str,
+    input: BuildPlayTrackUpdateInput,
+) -> Result<Update, BuildError> {
+    Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("track_id = :from_track_id")
+        .update_expression("SET track_id = :to_track_id, gsi1pk = :gsi1pk")
+        .expression_attribute_values(":from_track_id", AttributeValue::S(input.from_track_id))
+        .expression_attribute_values(":to_track_id", AttributeValue::S(input.to_track_id))
+        .expression_attribute_values(":gsi1pk", AttributeValue::S(input.gsi1pk))
+        .build()
 }
 
-pub(super) enum ProjectedFields {
-    All,
-    Some(&'static [&'static str]),
+pub(super) struct BuildTrackMetadataUpdateInput {
+    pub pk: String,
+    pub sk: String,
+    pub from_track_id: String,
+    pub to_track_id: String,
 }
 
-pub(super) struct ExclusiveStartKey {
+pub fn build_track_metadata_update(
+    table_name: &str,
+    input: BuildTrackMetadataUpdateInput,
+) -> Result<Update, BuildError> {
+    Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("track_id = :from_track_id")
+        .update_expression("SET track_id = :to_track_id")
+        .expression_attribute_values(":from_track_id", AttributeValue::S(input.from_track_id))
+        .expression_attribute_values(":to_track_id", AttributeValue::S(input.to_track_id))
+        .build()
+}
+
+pub(super) struct BuildTrackMetadataPutInput {
+    pub item: HashMap<String, AttributeValue>,
+}
+
+/// Adds a lookup item, unless the key is already taken.
+pub fn build_track_metadata_put(
+    table_name: &str,
+    input: BuildTrackMetadataPutInput,
+) -> Result<Put, BuildError> {
+    Put::builder()
+        .table_name(table_name)
+        .set_item(Some(input.item))
+        .condition_expression("attribute_not_exists(pk)")
+        .build()
+}
+
+pub(super) struct BuildTrackMetadataDeleteInput {
     pub pk: String,
     pub sk: String,
+    pub track_id: String,
 }
 
-pub(super) struct Gsi1ExclusiveStartKey {
-    pub gsi1pk: String,
+pub fn build_track_metadata_delete(
+    table_name: &str,
+    input: BuildTrackMetadataDeleteInput,
+) -> Result<Delete, BuildError> {
+    Delete::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("track_id = :track_id")
+        .expression_attribute_values(":track_id", AttributeValue::S(input.track_id))
+        .build()
+}
+
+pub(super) struct BuildTrackRenameUpdateInput {
+    pub pk: String,
     pub sk: String,
+    pub artist: String,
+    pub title: String,
+    pub aliases: AttributeValue,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
+}
+
+pub fn build_track_rename_update(
+    table_name: &str,
+    input: BuildTrackRenameUpdateInput,
+) -> Result<Update, BuildError> {
+    Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :locked_ts")
+        .update_expression(
+            "SET updated_ts = :ts, artist = :artist, title = :title, aliases = :aliases",
+        )
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(":locked_ts", AttributeValue::S(input.locked_timestamp))
+        .expression_attribute_values(":artist", AttributeValue::S(input.artist))
+        .expression_attribute_values(":title", AttributeValue::S(input.title))
+        .expression_attribute_values(":aliases", input.aliases)
+        .build()
+}
+
+pub(super) struct BuildTrackIsSongUpdateInput {
     pub pk: String,
+    pub sk: String,
+    pub is_song: bool,
+    pub update_timestamp: String,
+}
+
+pub fn build_track_is_song_update(
+    table_name: &str,
+    input: BuildTrackIsSongUpdateInput,
+) -> Result<Update, BuildError> {
+    Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("attribute_exists(pk)")
+        .update_expression("SET updated_ts = :ts, is_song = :is_song")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(":is_song", AttributeValue::Bool(input.is_song))
+        .build()
 }
 
-pub(super) struct GetItemInput {
+pub(super) struct BuildTrackMergeUpdateInput {
     pub pk: String,
     pub sk: String,
+    pub play_count: usize,
+    pub latest_play_id: Option<String>,
+    /// Replaces the earliest play time of the kept track, if the absorbed track has older plays.
+    pub earliest_play_timestamp: Option<String>,
+    pub aliases: AttributeValue,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
 }
 
-pub(super) struct GetItemConfig {
-    pub consistent_read: bool,
-    pub projected_fields: ProjectedFields,
+/// Adds the plays of the absorbed track to the kept track.
+pub fn build_track_merge_update(
+    table_name: &str,
+    input: BuildTrackMergeUpdateInput,
+) -> Result<Update, BuildError> {
+    let mut update_builder = Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :locked_ts")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(":locked_ts", AttributeValue::S(input.locked_timestamp))
+        .expression_attribute_values(":count", AttributeValue::N(input.play_count.to_string()))
+        .expression_attribute_values(":aliases", input.aliases);
+
+    let mut update_expression_parts = vec![
+        "updated_ts = :ts",
+        "play_count = play_count + :count",
+        "aliases = :aliases",
+    ];
+    if let Some(latest_play_id) = input.latest_play_id {
+        update_expression_parts.push("latest_play_id = :play_id");
+        update_builder = update_builder
+            .expression_attribute_values(":play_id", AttributeValue::S(latest_play_id));
+    }
+    if let Some(earliest_play_timestamp) = input.earliest_play_timestamp {
+        update_expression_parts.push("earliest_play_ts = :earliest_play_ts");
+        update_builder = update_builder.expression_attribute_values(
+            ":earliest_play_ts",
+            AttributeValue::S(earliest_play_timestamp),
+        );
+    }
+
+    update_builder
+        .update_expression(format!("SET {}", update_expression_parts.join(", ")))
+        .build()
 }
 
-pub(super) struct QueryPrefixInput {
+pub(super) struct BuildTrackDeleteInput {
     pub pk: String,
-    pub sk_prefix: String,
-    pub scan_forward: bool,
-    pub exclusive_start_key: Option<ExclusiveStartKey>,
+    pub sk: String,
+    pub locked_timestamp: String,
 }
 
-pub(super) struct QueryPrefixConfig {
-    pub limit: i32,
-    pub projected_fields: ProjectedFields,
+/// Deletes a track, unless it got a play since it was read.
+pub fn build_track_delete(
+    table_name: &str,
+    input: BuildTrackDeleteInput,
+) -> Result<Delete, BuildError> {
+    Delete::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :locked_ts")
+        .expression_attribute_values(":locked_ts", AttributeValue::S(input.locked_timestamp))
+        .build()
 }
 
-pub(super) struct QueryPrefixGsi1Input {
-    pub gsi1pk: String,
-    pub sk_prefix: String,
-    pub pk_prefix: Option<String>,
-    pub scan_forward: bool,
-    pub exclusive_start_key: Option<Gsi1ExclusiveStartKey>,
+pub(super) struct BuildTrackRedirectPutInput {
+    pub item: HashMap<String, AttributeValue>,
 }
 
-pub(super) struct QueryPrefixGsi1Config {
-    pub limit: i32,
+/// Points a removed track at the track that replaced it, replacing any earlier redirect of
+/// the removed track.
+pub fn build_track_redirect_put(
+    table_name: &str,
+    input: BuildTrackRedirectPutInput,
+) -> Result<Put, BuildError> {
+    Put::builder()
+        .table_name(table_name)
+        .set_item(Some(input.item))
+        .build()
 }
 
-pub(super) struct BatchGetItemKey {
+pub(super) struct BuildTrackRedirectDeleteInput {
     pub pk: String,
     pub sk: String,
+    pub track_id: String,
 }
 
-pub(super) struct BatchGetItemInput<I>
-where
-    I: Iterator<Item = BatchGetItemKey>,
-{
-    pub keys: I,
+/// Deletes a redirect, unless it was pointed at another track since it was read.
+pub fn build_track_redirect_delete(
+    table_name: &str,
+    input: BuildTrackRedirectDeleteInput,
+) -> Result<Delete, BuildError> {
+    Delete::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("track_id = :track_id")
+        .expression_attribute_values(":track_id", AttributeValue::S(input.track_id))
+        .build()
 }
 
-pub(super) struct BatchGetItemConfig {
-    pub projected_fields: ProjectedFields,
+pub(super) struct BuildStationTrackRemovedUpdateInput {
+    pub pk: String,
+    pub sk: String,
+    /// Replaces the track of the station latest play, if that was the removed track.
+    pub latest_play_track_id: Option<String>,
+    /// Same for the play before the latest play.
+    pub previous_play_track_id: Option<String>,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
 }
 
-impl DynamoDBProvider {
-    pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+pub fn build_station_track_removed_update(
+    table_name: &str,
+    input: BuildStationTrackRemovedUpdateInput,
+) -> Result<Update, BuildError> {
+    let mut update_builder = Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :station_locked_ts")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(
+            ":station_locked_ts",
+            AttributeValue::S(input.locked_timestamp),
+        )
+        .expression_attribute_values(":inc", AttributeValue::N("1".to_owned()));
+
+    let mut update_expression_parts = vec!["updated_ts = :ts", "track_count = track_count - :inc"];
+    if let Some(track_id) = input.latest_play_track_id {
+        update_expression_parts.push("latest_play.track_id = :track_id");
+        update_builder =
+            update_builder.expression_attribute_values(":track_id", AttributeValue::S(track_id));
     }
+    if let Some(track_id) = input.previous_play_track_id {
+        update_expression_parts.push("previous_play.track_id = :previous_track_id");
+        update_builder = update_builder
+            .expression_attribute_values(":previous_track_id", AttributeValue::S(track_id));
+    }
+
+    update_builder
+        .update_expression(format!("SET {}", update_expression_parts.join(", ")))
+        .build()
+}
+
+pub(super) struct BuildPlayDeleteInput {
+    pub pk: String,
+    pub sk: String,
+    pub track_id: String,
+}
+
+/// Deletes a play, unless it was moved to another track since it was read.
+pub fn build_play_delete(
+    table_name: &str,
+    input: BuildPlayDeleteInput,
+) -> Result<Delete, BuildError> {
+    Delete::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("track_id = :track_id")
+        .expression_attribute_values(":track_id", AttributeValue::S(input.track_id))
+        .build()
+}
 
-    pub fn table_name(&self) -> &str {
-        &self.context.db_table
+pub(super) struct BuildTrackPlayAddedUpdateInput {
+    pub pk: String,
+    pub sk: String,
+    /// Replaces the latest play of the track, if the added play is newer.
+    pub latest_play_id: Option<String>,
+    /// Replaces the earliest play time of the track, if the added play is older.
+    pub earliest_play_timestamp: Option<String>,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
+}
+
+pub fn build_track_play_added_update(
+    table_name: &str,
+    input: BuildTrackPlayAddedUpdateInput,
+) -> Result<Update, BuildError> {
+    let mut update_builder = Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :locked_ts")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(":locked_ts", AttributeValue::S(input.locked_timestamp))
+        .expression_attribute_values(":inc", AttributeValue::N("1".to_owned()));
+
+    let mut update_expression_parts = vec!["updated_ts = :ts", "play_count = play_count + :inc"];
+    if let Some(latest_play_id) = input.latest_play_id {
+        update_expression_parts.push("latest_play_id = :play_id");
+        update_builder = update_builder
+            .expression_attribute_values(":play_id", AttributeValue::S(latest_play_id));
     }
+    if let Some(earliest_play_timestamp) = input.earliest_play_timestamp {
+        update_expression_parts.push("earliest_play_ts = :earliest_play_ts");
+        update_builder = update_builder.expression_attribute_values(
+            ":earliest_play_ts",
+            AttributeValue::S(earliest_play_timestamp),
+        );
+    }
+
+    update_builder
+        .update_expression(format!("SET {}", update_expression_parts.join(", ")))
+        .build()
+}
 
-    pub async fn get_item(
-        &self,
-        input: GetItemInput,
-        config: GetItemConfig,
-    ) -> Result<GetItemOutput, SdkError<GetItemError, HttpResponse>> {
-        let mut get_item = self
-            .context
-            .db_client
-            .get_item()
-            .table_name(&self.context.db_table)
-            .key("pk", AttributeValue::S(input.pk))
-            .key("sk", AttributeValue::S(input.sk))
-            .consistent_read(config.consistent_read);
-
-        if let ProjectedFields::Some(projected_fields) = config.projected_fields {
-            get_item = get_item.projection_expression(projected_fields.join(", "));
-        }
-
-        get_item.send().await
+pub(super) struct BuildTrackPlayRemovedUpdateInput {
+    pub pk: String,
+    pub sk: String,
+    /// Replaces the latest play of the track, if that was the removed play.
+    pub latest_play_id: Option<String>,
+    pub update_timestamp: String,
+    pub locked_timestamp: String,
+}
+
+pub fn build_track_play_removed_update(
+    table_name: &str,
+    input: BuildTrackPlayRemovedUpdateInput,
+) -> Result<Update, BuildError> {
+    let mut update_builder = Update::builder()
+        .table_name(table_name)
+        .key("pk", AttributeValue::S(input.pk))
+        .key("sk", AttributeValue::S(input.sk))
+        .condition_expression("updated_ts = :locked_ts")
+        .expression_attribute_values(":ts", AttributeValue::S(input.update_timestamp))
+        .expression_attribute_values(":locked_ts", AttributeValue::S(input.locked_timestamp))
+        .expression_attribute_values(":inc", AttributeValue::N("1".to_owned()));
+
+    let mut update_expression_parts = vec!["updated_ts = :ts", "play_count = play_count - :inc"];
+    if let Some(latest_play_id) = input.latest_play_id {
+        update_expression_parts.push("latest_play_id = :play_id");
+        update_builder = update_builder
+            .expression_attribute_values(":play_id", AttributeValue::S(latest_play_id));
     }
 
-    pub async fn query_prefix(
-        &self,
-        input: QueryPrefixInput,
-        config: QueryPrefixConfig,
-    ) -> Result<QueryOutput, SdkError<QueryError, HttpResponse>> {
-        let mut query = self
-            .context
-            .db_client
-            .query()
-            .table_name(&self.context.db_table)
-            .key_condition_expression("pk = :pk AND begins_with(sk, :sk_prefix)")
-            .expression_attribute_values(":pk", AttributeValue::S(input.pk))
-            .expression_attribute_values(":sk_prefix", AttributeValue::S(input.sk_prefix))
-            .scan_index_forward(input.scan_forward)
-            .limit(config.limit);
-
-        query = match config.projected_fields {
-            ProjectedFields::All => query.select(Select::AllAttributes),
-            ProjectedFields::Some(projected_fields) => query
-                .select(Select::SpecificAttributes)
-                .projection_expression(projected_fields.join(", ")),
-        };
-
-        if let Some(exclusive_start_key) = input.exclusive_start_key {
-            query = query
-                .exclusive_start_key("pk", AttributeValue::S(exclusive_start_key.pk))
-                .exclusive_start_key("sk", AttributeValue::S(exclusive_start_key.sk));
-        }
-
-        query.send().await
+    update_builder
+        .update_expression(format!("SET {}", update_expression_parts.join(", ")))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_play_track_update_success() {
+        let update = build_play_track_update(
+            "tablename",
+            BuildPlayTrackUpdateInput {
+                pk: "pkvalue".to_owned(),
+                sk: "skvalue".to_owned(),
+                from_track_id: "from".to_owned(),
+                to_track_id: "to".to_owned(),
+                gsi1pk: "gsi1pkvalue".to_owned(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            update,
+            Update::builder()
+                .table_name("tablename")
+                .key("pk", AttributeValue::S("pkvalue".to_owned()))
+                .key("sk", AttributeValue::S("skvalue".to_owned()))
+                .condition_expression("track_id = :from_track_id")
+                .update_expression("SET track_id = :to_track_id, gsi1pk = :gsi1pk")
+                .expression_attribute_values(":from_track_id", AttributeValue::S("from".to_owned()))
+                .expression_attribute_values(":to_track_id", AttributeValue::S("to".to_owned()))
+                .expression_attribute_values(":gsi1pk", AttributeValue::S("gsi1pkvalue".to_owned()))
+                .build()
+                .unwrap()
+        );
     }
 
-    pub async fn query_prefix_gsi1(
-        &self,
-        input: QueryPrefixGsi1Input,
-        config: QueryPrefixGsi1Config,
-    ) -> Result<QueryOutput, SdkError<QueryError, HttpResponse>> {
-        let mut query = self
-            .context
-            .db_client
-            .query()
-            .table_name(&self.context.db_table)
-            .index_name("gsi1")
-            .key_condition_expression("gsi1pk = :gsi1pk AND begins_with(sk, :sk_prefix)")
-            .expression_attribute_values(":gsi1pk", AttributeValue::S(input.gsi1pk))
-            .expression_attribute_values(":sk_prefix", AttributeValue::S(input.sk_prefix))
-            .scan_index_forward(input.scan_forward)
-            .limit(config.limit);
-
-        if let Some(pk_prefix) = input.pk_prefix {
-            query = query
-                .filter_expression("begins_with(pk, :pk_prefix)")
-                .expression_attribute_values(":pk_prefix", AttributeValue::S(pk_prefix))
-        }
-
-        if let Some(exclusive_start_key) = input.exclusive_start_key {
-            query = query
-                .exclusive_start_key("gsi1pk", AttributeValue::S(exclusive_start_key.gsi1pk))
-                .exclusive_start_key("sk", AttributeValue::S(exclusive_start_key.sk))
-                .exclusive_start_key("pk", AttributeValue::S(exclusive_start_key.pk));
-        }
-
-        query.send().await
+    #[test]
+    fn test_build_station_track_removed_update_latest_play() {
+        let update = build_station_track_removed_update(
+            "tablename",
+            BuildStationTrackRemovedUpdateInput {
+                pk: "pkvalue".to_owned(),
+                sk: "skvalue".to_owned(),
+                latest_play_track_id: Some("trackid".to_owned()),
+                previous_play_track_id: None,
+                update_timestamp: "12345".to_owned(),
+                locked_timestamp: "67890".to_owned(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            update.update_expression(),
+            "SET updated_ts = :ts, track_count = track_count - :inc, latest_play.track_id = :track_id"
+        );
+        assert_eq!(
+            update.condition_expression(),
+            Some("updated_ts = :station_locked_ts")
+        );
+        assert_eq!(
+            update
+                .expression_attribute_values()
+                .unwrap()
+                .get(":track_id"),
+            Some(&AttributeValue::S("trackid".to_owned()))
+        );
     }
 
-    pub async fn batch_get_item<I>(
-        &self,
-        input: BatchGetItemInput<I>,
-        config: BatchGetItemConfig,
-    ) -> Result<BatchGetItemOutput, SdkError<BatchGetItemError, HttpResponse>>
-    where
-        I: Iterator<Item = BatchGetItemKey>,
-    {
-        let mut request_keys = KeysAndAttributes::builder();
-
-        if let ProjectedFields::Some(projected_fields) = config.projected_fields {
-            request_keys = request_keys.projection_expression(projected_fields.join(", "));
-        }
-
-        // FIXME: do multiple batches if key count > 100
-        for key in input.keys {
-            request_keys = request_keys.keys(HashMap::from([
-                ("pk".to_owned(), AttributeValue::S(key.pk)),
-                ("sk".to_owned(), AttributeValue::S(key.sk)),
-            ]))
-        }
-
-        self.context
-            .db_client
-            .batch_get_item()
-            .request_items(&self.context.db_table, request_keys.build()?)
-            .send()
-            .await
+    #[test]
+    fn test_build_track_play_removed_update_latest_play() {
+        let update = build_track_play_removed_update(
+            "tablename",
+            BuildTrackPlayRemovedUpdateInput {
+                pk: "pkvalue".to_owned(),
+                sk: "skvalue".to_owned(),
+                latest_play_id: Some("playid".to_owned()),
+                update_timestamp: "12345".to_owned(),
+                locked_timestamp: "67890".to_owned(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            update.update_expression(),
+            "SET updated_ts = :ts, play_count = play_count - :inc, latest_play_id = :play_id"
+        );
+        assert_eq!(
+            update.condition_expression(),
+            Some("updated_ts = :locked_ts")
+        );
     }
 }
@@ -0,0 +1,51 @@
+use thiserror::Error;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by the radiojournal storage and CRUD layer.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The item the operation depends on does not exist.
+    #[error("item not found")]
+    NotFound,
+    /// A condition expression did not hold, e.g. the station was updated since it was read.
+    #[error("conditional check failed: {0}")]
+    ConditionFailed(String),
+    /// The storage backend rejected the request because of load or a conflicting transaction,
+    /// it can be retried later.
+    #[error("request throttled by storage backend: {0}")]
+    Throttled(#[source] BoxError),
+    /// An item could not be converted from or into its stored representation.
+    #[error("failed to serialize or deserialize item: {0}")]
+    Serialization(#[source] BoxError),
+    /// The change would make the item collide with another item, e.g. renaming a track to
+    /// the artist and title of another track.
+    #[error("conflict: {0}")]
+    Conflict(String),
+    /// The caller passed a value that cannot be stored or queried.
+    #[error("invalid input: {0}")]
+    InvalidInput(&'static str),
+    /// Any other failure reported by the storage backend.
+    #[error("storage backend error: {0}")]
+    Backend(#[source] BoxError),
+}
+
+impl Error {
+    pub(crate) fn backend(error: impl Into<BoxError>) -> Self {
+        Self::Backend(error.into())
+    }
+}
+
+impl From<serde_dynamo::Error> for Error {
+    fn from(error: serde_dynamo::Error) -> Self {
+        Self::Serialization(Box::new(error))
+    }
+}
+
+impl From<aws_sdk_dynamodb::error::BuildError> for Error {
+    fn from(error: aws_sdk_dynamodb::error::BuildError) -> Self {
+        Self::backend(error)
+    }
+}
@@ -1,4 +1,8 @@
-use chrono::{DateTime, Datelike, SubsecRound, Timelike, Utc};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveTime, Offset, SubsecRound, TimeZone,
+    Timelike, Utc,
+};
+use chrono_tz::Tz;
 
 pub(crate) fn ziso_timestamp(dt: &DateTime<Utc>) -> String {
     dt.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
@@ -27,6 +31,34 @@ pub fn truncate_datetime_to_months(dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
     )
 }
 
+/// Returns the first instant of `date` in `timezone`. A day whose midnight is skipped by a
+/// DST change starts when the clocks jump forward.
+pub fn start_of_local_day(date: NaiveDate, timezone: Tz) -> Option<DateTime<Utc>> {
+    let midnight = date.and_time(NaiveTime::MIN);
+
+    match timezone.from_local_datetime(&midnight) {
+        LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) => Some(dt.with_timezone(&Utc)),
+        LocalResult::None => {
+            // midnight with the offset in effect before the gap is when the gap ends
+            let before_gap = timezone
+                .from_local_datetime(&(midnight - Duration::hours(6)))
+                .earliest()?;
+            let offset = before_gap.offset().fix().local_minus_utc();
+
+            Some((midnight - Duration::seconds(offset.into())).and_utc())
+        }
+    }
+}
+
+/// Returns the first and the last millisecond of `date` in `timezone`, the range a day
+/// spans in UTC and in the day partitions of plays.
+pub fn local_day_range(date: NaiveDate, timezone: Tz) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let start = start_of_local_day(date, timezone)?;
+    let end = start_of_local_day(date.succ_opt()?, timezone)? - Duration::milliseconds(1);
+
+    Some((start, end))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,4 +111,41 @@ mod tests {
             DateTime::parse_from_rfc3339("2001-02-01T00:00:00Z").unwrap()
         );
     }
+
+    #[rstest]
+    #[case(
+        Tz::UTC,
+        "2024-01-02",
+        "2024-01-02T00:00:00Z",
+        "2024-01-02T23:59:59.999Z"
+    )]
+    #[case(
+        Tz::Asia__Bangkok,
+        "2024-01-02",
+        "2024-01-01T17:00:00Z",
+        "2024-01-02T16:59:59.999Z"
+    )]
+    #[case(
+        Tz::America__New_York,
+        "2024-03-10",
+        "2024-03-10T05:00:00Z",
+        "2024-03-11T03:59:59.999Z"
+    )]
+    // midnight is skipped when DST starts in Santiago
+    #[case(
+        Tz::America__Santiago,
+        "2024-09-08",
+        "2024-09-08T04:00:00Z",
+        "2024-09-09T02:59:59.999Z"
+    )]
+    fn test_local_day_range_success(
+        #[case] timezone: Tz,
+        #[case] date: &str,
+        #[case] expected_start: &str,
+        #[case] expected_end: &str,
+    ) {
+        let (start, end) = local_day_range(date.parse().unwrap(), timezone).unwrap();
+        assert_eq!(start, DateTime::parse_from_rfc3339(expected_start).unwrap());
+        assert_eq!(end, DateTime::parse_from_rfc3339(expected_end).unwrap());
+    }
 }
@@ -1,11 +1,17 @@
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use aws_config::{BehaviorVersion, meta::region::RegionProviderChain};
 use aws_sdk_dynamodb::Client;
 
+use crate::backend::{Backend, DynamoDBBackend};
 use crate::crud::Context;
 
+#[cfg(feature = "local")]
+use crate::backend::MemoryBackend;
+#[cfg(feature = "sqlite")]
+use crate::backend::SqliteBackend;
+
 const LOCALSTACK_ENDPOINT: &str = "http://localhost:4566";
 
 /// If LOCALSTACK environment variable is true, use LocalStack endpoints.
@@ -14,7 +20,49 @@ pub fn use_localstack() -> bool {
     std::env::var("LOCALSTACK").unwrap_or_default() == "true"
 }
 
+/// Whether the selected storage backend is LocalStack or the in-process `memory` backend,
+/// neither of which keeps data clients could page through across restarts.
+pub fn use_local_backend() -> bool {
+    use_localstack() || std::env::var("DB_BACKEND").unwrap_or_default() == "memory"
+}
+
+/// Storage backend selected by the DB_BACKEND environment variable, DynamoDB if unset.
+/// `memory` keeps everything in-process and is only available with the `local` feature.
+/// `sqlite` stores the table in the SQLite database at DB_SQLITE_PATH and is only available
+/// with the `sqlite` feature.
+///
+/// Pagination tokens are signed with the CURSOR_SECRET environment variable, see
+/// [`Context::with_cursor_secret`]. Anything serving clients other than a local backend has
+/// to set it, see [`use_local_backend`], as tokens signed without it are rejected once the
+/// process restarts.
 pub async fn initialize() -> Result<Arc<Context>> {
+    let table_name = std::env::var("DB_TABLE_NAME").expect("env DB_TABLE_NAME to be set");
+
+    let backend = match std::env::var("DB_BACKEND").unwrap_or_default().as_str() {
+        "" | "dynamodb" => Backend::DynamoDB(initialize_dynamodb(table_name).await),
+        #[cfg(feature = "local")]
+        "memory" => Backend::Memory(MemoryBackend::new(table_name)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = std::env::var("DB_SQLITE_PATH").expect("env DB_SQLITE_PATH to be set");
+            Backend::Sqlite(SqliteBackend::open(path, table_name)?)
+        }
+        other => bail!("unsupported DB_BACKEND: {other}"),
+    };
+
+    let mut context = Context::with_backend(backend);
+    if let Ok(secret) = std::env::var("CURSOR_SECRET")
+        && !secret.is_empty()
+    {
+        context = context.with_cursor_secret(secret);
+    }
+
+    let context = Arc::new(context);
+
+    Ok(context)
+}
+
+async fn initialize_dynamodb(table_name: String) -> DynamoDBBackend {
     let region_provider = RegionProviderChain::default_provider().or_else("ap-southeast-1");
 
     let mut config = aws_config::defaults(BehaviorVersion::latest()).region(region_provider);
@@ -25,9 +73,6 @@ pub async fn initialize() -> Result<Arc<Context>> {
     let config = config.load().await;
 
     let db_client = Client::new(&config);
-    let table_name = std::env::var("DB_TABLE_NAME").expect("env DB_TABLE_NAME to be set");
-
-    let context = Arc::new(Context::new(db_client, table_name));
 
-    Ok(context)
+    DynamoDBBackend::new(db_client, table_name)
 }
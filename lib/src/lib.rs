@@ -1,6 +1,11 @@
+pub mod backend;
+pub mod clock;
 pub mod crud;
+pub mod error;
 pub mod helpers;
 pub mod init;
 
+pub use error::{Error, Result};
+
 #[cfg(feature = "local")]
 pub mod mock;
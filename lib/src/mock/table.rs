@@ -7,20 +7,34 @@ use aws_sdk_dynamodb::types::{
 };
 use tracing::warn;
 
+use crate::backend::Backend;
 use crate::crud::Context;
 
 pub async fn delete_then_create_table(context: Arc<Context>) -> Result<()> {
-    if context
-        .db_client
+    let backend = match &context.backend {
+        Backend::DynamoDB(backend) => backend,
+        Backend::Memory(backend) => {
+            backend.clear();
+            return Ok(());
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(backend) => {
+            backend.clear().await?;
+            return Ok(());
+        }
+    };
+
+    if backend
+        .client
         .delete_table()
-        .table_name(&context.db_table)
+        .table_name(&backend.table_name)
         .send()
         .await
         .is_err()
     {
         warn!(
             "Table {} does not exist, skipping delete.",
-            context.db_table
+            backend.table_name
         );
     }
 
@@ -67,10 +81,10 @@ pub async fn delete_then_create_table(context: Arc<Context>) -> Result<()> {
         .projection(gsi1_projection)
         .build()?;
 
-    context
-        .db_client
+    backend
+        .client
         .create_table()
-        .table_name(&context.db_table)
+        .table_name(&backend.table_name)
         .key_schema(ks_pk)
         .key_schema(ks_sk)
         .attribute_definitions(ad_pk)
@@ -108,6 +108,7 @@ impl Fetcher for Atime {
         Ok(Play {
             title: station_data.title.trim().to_owned(),
             artist: station_data.artists.trim().to_owned(),
+            ..Default::default()
         })
     }
 }
@@ -8,7 +8,7 @@ use serde::Deserialize;
 use tokio::sync::Mutex;
 use tracing::{info, warn};
 
-use super::{DEFAULT_USER_AGENT, Fetcher, Play};
+use super::{DEFAULT_USER_AGENT, Fetcher, Play, non_empty};
 use radiojournal::crud::station::models::FetcherConfig;
 
 #[derive(Debug)]
@@ -153,15 +153,27 @@ struct Data {
 struct NowSong {
     song: String,
     artist: String,
+    id: Option<u64>,
+    album: Option<String>,
+    /// Length of the song in seconds
+    duration: Option<u64>,
+    isrc: Option<String>,
+    cover: Option<String>,
 }
 
 #[async_trait]
 impl Fetcher for Coolism {
     async fn fetch_play(&self, _config: &FetcherConfig) -> Result<Play> {
-        let metadata = self.fetch_metadata().await?;
+        let now_song = self.fetch_metadata().await?.now_song;
         Ok(Play {
-            title: metadata.now_song.song.trim().to_owned(),
-            artist: metadata.now_song.artist.trim().to_owned(),
+            title: now_song.song.trim().to_owned(),
+            artist: now_song.artist.trim().to_owned(),
+            album: non_empty(now_song.album),
+            duration: now_song.duration.map(Duration::from_secs),
+            isrc: non_empty(now_song.isrc),
+            artwork_url: non_empty(now_song.cover),
+            provider_track_id: now_song.id.map(|id| id.to_string()),
+            ..Default::default()
         })
     }
 }
@@ -2,11 +2,12 @@ use std::time::Duration;
 
 use anyhow::{Result, anyhow, bail};
 use async_trait::async_trait;
+use chrono::DateTime;
 use reqwest::header::{HeaderMap, HeaderValue};
 use serde::Deserialize;
 use serde_json::json;
 
-use super::{DEFAULT_USER_AGENT, Fetcher, Play};
+use super::{DEFAULT_USER_AGENT, Fetcher, Play, non_empty};
 use radiojournal::crud::station::models::FetcherConfig;
 
 #[derive(Debug)]
@@ -90,9 +91,31 @@ struct CurrentlyPlaying {
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Track {
     title: String,
     artist: Artist,
+    /// Unix timestamp in seconds of when the track started playing
+    start_time: Option<i64>,
+    /// Unix timestamp in seconds of when the track is expected to end
+    end_time: Option<i64>,
+    track_id: Option<u64>,
+    album_name: Option<String>,
+    /// Length of the track in seconds
+    track_duration: Option<u64>,
+    isrc: Option<String>,
+    image_path: Option<String>,
+}
+
+impl Track {
+    fn duration(&self) -> Option<Duration> {
+        let duration_secs = self.track_duration.or_else(|| {
+            let (start_time, end_time) = (self.start_time?, self.end_time?);
+            u64::try_from(end_time - start_time).ok()
+        })?;
+
+        Some(Duration::from_secs(duration_secs)).filter(|duration| !duration.is_zero())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -120,6 +143,15 @@ impl Fetcher for Iheart {
         Ok(Play {
             artist: current_track.artist.artist_name.trim().to_owned(),
             title: current_track.title.trim().to_owned(),
+            started_at: current_track
+                .start_time
+                .and_then(|start_time| DateTime::from_timestamp(start_time, 0)),
+            duration: current_track.duration(),
+            album: non_empty(current_track.album_name),
+            isrc: non_empty(current_track.isrc),
+            artwork_url: non_empty(current_track.image_path),
+            provider_track_id: current_track.track_id.map(|id| id.to_string()),
+            ..Default::default()
         })
     }
 }
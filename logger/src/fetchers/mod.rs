@@ -2,18 +2,29 @@ pub(crate) mod atime;
 pub(crate) mod coolism;
 pub(crate) mod iheart;
 
+use std::time::Duration;
+
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 
 use radiojournal::crud::logger::models::Play as PlayTrait;
 use radiojournal::crud::station::models::FetcherConfig;
 
 pub(crate) const DEFAULT_USER_AGENT: &str = include_str!("./default_user_agent.txt");
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub(crate) struct Play {
     pub(crate) title: String,
     pub(crate) artist: String,
+    /// Set from the station rules once the play is fetched.
+    pub(crate) is_song: bool,
+    pub(crate) started_at: Option<DateTime<Utc>>,
+    pub(crate) album: Option<String>,
+    pub(crate) duration: Option<Duration>,
+    pub(crate) isrc: Option<String>,
+    pub(crate) artwork_url: Option<String>,
+    pub(crate) provider_track_id: Option<String>,
 }
 
 impl PlayTrait for Play {
@@ -26,7 +37,31 @@ impl PlayTrait for Play {
     }
 
     fn is_song(&self) -> bool {
-        true
+        self.is_song
+    }
+
+    fn get_started_at(&self) -> Option<DateTime<Utc>> {
+        self.started_at
+    }
+
+    fn get_album(&self) -> Option<&str> {
+        self.album.as_deref()
+    }
+
+    fn get_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn get_isrc(&self) -> Option<&str> {
+        self.isrc.as_deref()
+    }
+
+    fn get_artwork_url(&self) -> Option<&str> {
+        self.artwork_url.as_deref()
+    }
+
+    fn get_provider_track_id(&self) -> Option<&str> {
+        self.provider_track_id.as_deref()
     }
 }
 
@@ -35,6 +70,13 @@ pub(crate) trait Fetcher {
     async fn fetch_play(&self, config: &FetcherConfig) -> Result<Play>;
 }
 
+/// Trims the value and drops it if nothing is left.
+pub(crate) fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -42,6 +84,6 @@ mod tests {
     #[test]
     fn test_default_user_agent_trimmed() {
         assert_eq!(DEFAULT_USER_AGENT, DEFAULT_USER_AGENT.trim());
-        assert!(DEFAULT_USER_AGENT.len() > 0);
+        assert!(!DEFAULT_USER_AGENT.is_empty());
     }
 }
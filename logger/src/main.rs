@@ -9,6 +9,7 @@ use serde_json::Value;
 use tokio::task::JoinSet;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
 
 use fetchers::Fetcher;
 use radiojournal::crud::logger::models::AddPlayResult;
@@ -97,7 +98,15 @@ struct InvokeOutput {
 struct StationResult {
     id: StationId,
     name: String,
-    logger_result: Option<AddPlayResult>,
+    #[serde(flatten)]
+    outcome: StationOutcome,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "outcome")]
+enum StationOutcome {
+    Logged { logger_result: AddPlayResult },
+    Skipped { reason: String },
 }
 
 async fn invoke(
@@ -108,17 +117,27 @@ async fn invoke(
 ) -> Result<InvokeOutput, Error> {
     let mut join_set = JoinSet::new();
 
-    crud_station
-        .list_stations(100)
-        .await
-        .expect("list stations successfully")
-        .into_iter()
-        .filter(|station| station.fetcher.is_some())
-        .for_each(|station| {
-            let crud_logger = crud_logger.clone();
-            let state = state.clone();
-            join_set.spawn(async move { process_station(state, crud_logger, station).await });
-        });
+    let mut next_token = None;
+    loop {
+        let (stations, page_next_token) = crud_station
+            .list_stations(100, next_token.as_deref())
+            .await
+            .expect("list stations successfully");
+
+        stations
+            .into_iter()
+            .filter(|station| station.fetcher.is_some())
+            .for_each(|station| {
+                let crud_logger = crud_logger.clone();
+                let state = state.clone();
+                join_set.spawn(async move { process_station(state, crud_logger, station).await });
+            });
+
+        next_token = page_next_token;
+        if next_token.is_none() {
+            break;
+        }
+    }
 
     let mut stations = vec![];
     let mut errors = vec![];
@@ -166,36 +185,72 @@ async fn process_station(
 ) -> anyhow::Result<StationResult> {
     let maybe_fetcher = get_fetcher(&state, &station).await;
 
-    let logger_result = if let Some((fetcher, config)) = maybe_fetcher {
+    let outcome = if let Some((fetcher, config)) = maybe_fetcher {
         info!(
             station_name = station.name,
             fetcher = ?config,
             "Processing station"
         );
 
-        let play = fetcher.fetch_play(config).await?;
-
-        info!(title = play.title, artist = play.artist, "Fetched play");
-
-        let result = crud_logger.add_play(&mut station, play).await?;
+        let mut play = fetcher.fetch_play(config).await?;
+        play.is_song = station.is_song(&play.artist, &play.title);
 
         info!(
-            add_type = ?result.add_type,
-            track_id = result.track_id.to_string(),
-            play_id = result.play_id.to_string(),
-            "Play added with type {:?}", result.add_type
+            title = play.title,
+            artist = play.artist,
+            is_song = play.is_song,
+            started_at = ?play.started_at,
+            "Fetched play"
         );
 
-        Some(result)
+        if let Some(rule) = station.matching_ignore_rule(&play.artist, &play.title) {
+            let reason = format!("ignored play, {rule}");
+            info!(reason, "Skipping station");
+
+            return Ok(StationResult {
+                id: station.id,
+                name: station.name,
+                outcome: StationOutcome::Skipped { reason },
+            });
+        }
+
+        match crud_logger.add_play(&mut station, play).await {
+            Ok(result) => {
+                info!(
+                    add_type = ?result.add_type,
+                    track_id = result.track_id.to_string(),
+                    play_id = result.play_id.to_string(),
+                    "Play added with type {:?}", result.add_type
+                );
+
+                StationOutcome::Logged {
+                    logger_result: result,
+                }
+            }
+            Err(
+                error @ (radiojournal::Error::ConditionFailed(_)
+                | radiojournal::Error::Throttled(_)),
+            ) => {
+                // transient, the play will be picked up again on the next invocation
+                warn!(error = ?error, "Play was not added, skipping station");
+
+                StationOutcome::Skipped {
+                    reason: format!("play was not added, {error}"),
+                }
+            }
+            Err(error) => return Err(error.into()),
+        }
     } else {
         info!("Fetcher not found, skipping station");
 
-        None
+        StationOutcome::Skipped {
+            reason: "fetcher not found".to_owned(),
+        }
     };
 
     Ok(StationResult {
         id: station.id,
         name: station.name,
-        logger_result,
+        outcome,
     })
 }
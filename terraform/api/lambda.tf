@@ -16,6 +16,7 @@ resource "aws_lambda_function" "this" {
   environment {
     variables = {
       DB_TABLE_NAME = var.db_table_name
+      CURSOR_SECRET = var.cursor_secret
     }
   }
 
@@ -54,5 +55,6 @@ resource "aws_lambda_function_url" "this" {
   cors {
     allow_credentials = true
     allow_origins     = var.allowed_cors_domains
+    expose_headers    = ["x-next-token"]
   }
 }
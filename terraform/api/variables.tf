@@ -14,6 +14,12 @@ variable "db_table_name" {
   type = string
 }
 
+variable "cursor_secret" {
+  description = "Secret signing the next_token of paginated responses"
+  type        = string
+  sensitive   = true
+}
+
 variable "allowed_cors_domains" {
   type = list(string)
 }